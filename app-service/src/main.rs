@@ -1,27 +1,198 @@
-use std::env;
+use std::{env, future::IntoFuture, sync::Arc, time::Duration};
 
 use askama::Template;
+use auth_middleware::{AuthConfig, RemoteVerifier, RequireAuthLayer};
 use axum::{
-    http::StatusCode,
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse},
     routing::get,
     Json, Router,
 };
-use axum_extra::extract::CookieJar;
+use opentelemetry::{propagation::Injector, trace::TracerProvider as _, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
 use serde::Serialize;
-use tower_http::services::ServeDir;
+use tokio::{signal, sync::Notify};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
+use tracing::{Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const HOST_PREFIX: &str = "__Host-";
+// Calls to /verify-token give up after these, so a stalled auth-service fails requests
+// instead of holding them open
+const VERIFY_TOKEN_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const VERIFY_TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() {
+    init_tracing();
+
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route(
+            "/protected",
+            get(protected).route_layer(RequireAuthLayer::new(auth_config())),
+        )
+        .route("/health/live", get(health_live))
+        .route("/health/ready", get(health_ready))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(make_span))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
-    println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    tracing::info!("listening on {}", listener.local_addr().unwrap());
+
+    let draining = Arc::new(Notify::new());
+    let server = axum::serve(listener, app)
+        .with_graceful_shutdown({
+            let draining = draining.clone();
+            async move {
+                shutdown_signal().await;
+                tracing::info!("Shutting down, draining in-flight requests");
+                draining.notify_one();
+            }
+        })
+        .into_future();
+
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = async {
+            draining.notified().await;
+            tokio::time::sleep(DRAIN_TIMEOUT).await;
+        } => tracing::warn!("Drain timeout elapsed, stopping with requests still in flight"),
+    }
+
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+// How long in-flight requests may take to finish once shutdown has started
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Resolves when the process receives SIGINT (Ctrl+C) or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT"),
+        _ = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+// Logs to stdout and, when OTEL_EXPORTER_OTLP_ENDPOINT is set, exports spans over OTLP
+// so that calls to auth-service show up in the same trace.
+fn init_tracing() {
+    let resource = Resource::new([KeyValue::new("service.name", "app-service")]);
+    let mut provider = trace::TracerProvider::builder().with_resource(resource);
+
+    if let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .expect("Failed to build OTLP exporter");
+        provider = provider.with_batch_exporter(exporter, runtime::Tokio);
+    }
+
+    let provider = provider.build();
+    let otel_layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("app-service"));
+    opentelemetry::global::set_tracer_provider(provider);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter_layer = EnvFilter::try_from_default_env().unwrap_or(EnvFilter::new("info"));
+
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(tracing_subscriber::fmt::layer().compact())
+        .with(otel_layer)
+        .init();
+}
+
+// Request span that joins the caller's trace when a `traceparent` header is present.
+fn make_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::span!(
+        Level::INFO,
+        "[REQUEST]",
+        method = tracing::field::display(request.method()),
+        uri = tracing::field::display(request.uri()),
+        request_id = tracing::field::display(request_id),
+    );
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
+
+// Headers forwarded to auth-service: the request ID plus the W3C trace context of the
+// current span, so both services' logs and traces can be correlated.
+fn propagation_headers(incoming: &HeaderMap) -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+
+    if let Some(request_id) = incoming
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| reqwest::header::HeaderValue::from_str(value).ok())
+    {
+        headers.insert(REQUEST_ID_HEADER, request_id);
+    }
+
+    let context = Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut ReqwestHeaderInjector(&mut headers))
+    });
+
+    headers
+}
+
+// reqwest 0.11 uses http 0.2 header types, which opentelemetry-http does not support.
+struct ReqwestHeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for ReqwestHeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if value.is_empty() {
+            return;
+        }
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
 }
 
 #[derive(Template)]
@@ -33,16 +204,17 @@ struct IndexTemplate {
 
 async fn root() -> impl IntoResponse {
     let auth_service_ip = env::var("AUTH_SERVICE_IP").unwrap_or("localhost".to_owned());
-    
-    let (login_link, logout_link) = if auth_service_ip == "localhost" || auth_service_ip.is_empty() {
+
+    let (login_link, logout_link) = if auth_service_ip == "localhost" || auth_service_ip.is_empty()
+    {
         (
             format!("http://{}:3000", auth_service_ip),
-            format!("http://{}:3000/logout", auth_service_ip)
+            format!("http://{}:3000/logout", auth_service_ip),
         )
     } else {
         (
             "https://lgr.ddrcode.me/auth/".to_string(),
-            "https://lgr.ddrcode.me/logout".to_string()
+            "https://lgr.ddrcode.me/logout".to_string(),
         )
     };
 
@@ -53,43 +225,128 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
-        Some(cookie) => cookie,
-        None => {
-            return StatusCode::UNAUTHORIZED.into_response();
-        }
-    };
+// Tokens are checked by auth-service, which also knows about logged out and revoked ones
+fn auth_config() -> AuthConfig {
+    let client = reqwest::Client::builder()
+        .connect_timeout(VERIFY_TOKEN_CONNECT_TIMEOUT)
+        .timeout(VERIFY_TOKEN_TIMEOUT)
+        .build()
+        .expect("Failed to build HTTP client");
+    let verifier = RemoteVerifier::new(verify_token_url())
+        .with_client(client)
+        .with_forwarded_headers(propagation_headers);
 
-    let api_client = reqwest::Client::builder().build().unwrap();
+    AuthConfig::new(auth_cookie_name(), verifier)
+}
 
-    let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
-    });
+// Must match auth-service's auth.cookie settings: AUTH_COOKIE_NAME (defaults to "jwt") and
+// AUTH_COOKIE_HOST_PREFIX, set to "true" when auth-service adds the __Host- prefix
+fn auth_cookie_name() -> String {
+    let name = env::var("AUTH_COOKIE_NAME").unwrap_or("jwt".to_owned());
+    let host_prefix = env::var("AUTH_COOKIE_HOST_PREFIX")
+        .map(|value| value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    if host_prefix {
+        format!("{HOST_PREFIX}{name}")
+    } else {
+        name
+    }
+}
+
+// Only reached with a token auth-service accepted, from the cookie or a bearer header
+async fn protected() -> impl IntoResponse {
+    Json(ProtectedRouteResponse {
+        img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct ProtectedRouteResponse {
+    pub img_url: String,
+}
 
+fn verify_token_url() -> String {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
-    let url = format!("http://{}:3000/verify-token", auth_hostname);
+    format!("http://{}:3000/verify-token", auth_hostname)
+}
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
-        Ok(response) => response,
-        Err(_) => {
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-        }
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+async fn health_live() -> impl IntoResponse {
+    Json(HealthResponse {
+        status: "up",
+        checks: HealthChecks { auth_service: None },
+    })
+}
+
+// Ready only when auth-service answers /verify-token. Any non-5xx response, including
+// the 4xx we get for the dummy token, means requests to /protected can be served.
+async fn health_ready() -> impl IntoResponse {
+    let api_client = reqwest::Client::builder()
+        .timeout(HEALTH_CHECK_TIMEOUT)
+        .build()
+        .unwrap();
+
+    let started = std::time::Instant::now();
+    let result = api_client
+        .post(verify_token_url())
+        .json(&serde_json::json!({ "token": "" }))
+        .send()
+        .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    let auth_service = match result {
+        Ok(response) if !response.status().is_server_error() => DependencyHealth {
+            status: "up",
+            latency_ms,
+            error: None,
+        },
+        Ok(response) => DependencyHealth {
+            status: "down",
+            latency_ms,
+            error: Some(format!("Unexpected status {}", response.status())),
+        },
+        Err(e) => DependencyHealth {
+            status: "down",
+            latency_ms,
+            error: Some(e.to_string()),
+        },
     };
 
-    match response.status() {
-        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
-        reqwest::StatusCode::OK => Json(ProtectedRouteResponse {
-            img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
-        })
-        .into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-    }
+    let (status_code, status) = if auth_service.status == "up" {
+        (StatusCode::OK, "up")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "down")
+    };
+
+    (
+        status_code,
+        Json(HealthResponse {
+            status,
+            checks: HealthChecks {
+                auth_service: Some(auth_service),
+            },
+        }),
+    )
 }
 
 #[derive(Serialize)]
-pub struct ProtectedRouteResponse {
-    pub img_url: String,
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub checks: HealthChecks,
+}
+
+#[derive(Serialize)]
+pub struct HealthChecks {
+    #[serde(rename = "auth-service", skip_serializing_if = "Option::is_none")]
+    pub auth_service: Option<DependencyHealth>,
+}
+
+#[derive(Serialize)]
+pub struct DependencyHealth {
+    pub status: &'static str,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
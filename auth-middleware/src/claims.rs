@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    // Seconds since the epoch, compared against the user's `tokens_valid_after`
+    #[serde(default)]
+    pub iat: usize,
+    // Unique per token, so a token can be banned without storing the token itself
+    #[serde(default)]
+    pub jti: String,
+    // Fixed when the token is issued, e.g. "admin"
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // Granted by `roles`, e.g. "users:read", so services can check them without a lookup
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+impl Claims {
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        i64::try_from(self.exp)
+            .ok()
+            .and_then(|exp| DateTime::from_timestamp(exp, 0))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        i64::try_from(self.iat)
+            .ok()
+            .and_then(|iat| DateTime::from_timestamp(iat, 0))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
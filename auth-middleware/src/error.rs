@@ -0,0 +1,30 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use thiserror::Error;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("Missing token")]
+    MissingToken,
+    // Malformed, expired, revoked or banned
+    #[error("Invalid token")]
+    InvalidToken,
+    // The verifier could not tell, e.g. auth-service or the banned token store is down
+    #[error("Token could not be verified")]
+    Unexpected(#[source] BoxError),
+}
+
+// Services with their own error responses map `AuthError` into them instead
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        match self {
+            AuthError::MissingToken | AuthError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AuthError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+        .into_response()
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap},
+};
+use axum_extra::extract::CookieJar;
+
+use crate::{AuthError, Claims, TokenVerifier};
+
+// Where a service looks for tokens and how it verifies them. The `AuthenticatedUser`
+// extractor takes it from the router state, so the state must implement `FromRef` for it.
+#[derive(Clone)]
+pub struct AuthConfig {
+    cookie_name: String,
+    verifier: Arc<dyn TokenVerifier>,
+}
+
+impl AuthConfig {
+    pub fn new(cookie_name: impl Into<String>, verifier: impl TokenVerifier + 'static) -> Self {
+        Self {
+            cookie_name: cookie_name.into(),
+            verifier: Arc::new(verifier),
+        }
+    }
+
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<AuthenticatedUser, AuthError> {
+        let token = extract_token(headers, &self.cookie_name).ok_or(AuthError::MissingToken)?;
+        let claims = self.verifier.verify(&token, headers).await?;
+
+        Ok(AuthenticatedUser(claims))
+    }
+}
+
+// The `Authorization: Bearer` token, or else the value of the cookie named `cookie_name`.
+// Any other authorization scheme is ignored.
+pub fn extract_token(headers: &HeaderMap, cookie_name: &str) -> Option<String> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim().to_owned())
+        .filter(|token| !token.is_empty());
+
+    bearer.or_else(|| {
+        CookieJar::from_headers(headers)
+            .get(cookie_name)
+            .map(|cookie| cookie.value().to_owned())
+    })
+}
+
+// The claims of the request's token, once the verifier has accepted it. Rejects with
+// `AuthError`, take it as `Result<AuthenticatedUser, AuthError>` to handle the rejection.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser(pub Claims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedUser
+where
+    AuthConfig: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Already verified by `RequireAuthLayer`
+        if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(user.clone());
+        }
+
+        AuthConfig::from_ref(state)
+            .authenticate(&parts.headers)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_extract_token_from_cookie() {
+        let headers = headers(&[("cookie", "other=1; jwt=cookie-token")]);
+
+        assert_eq!(
+            extract_token(&headers, "jwt"),
+            Some("cookie-token".to_owned())
+        );
+        assert_eq!(extract_token(&headers, "__Host-jwt"), None);
+    }
+
+    #[test]
+    fn test_extract_token_prefers_bearer() {
+        let headers = headers(&[
+            ("authorization", "Bearer header-token"),
+            ("cookie", "jwt=cookie-token"),
+        ]);
+
+        assert_eq!(
+            extract_token(&headers, "jwt"),
+            Some("header-token".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_extract_token_ignores_other_schemes() {
+        let basic = headers(&[
+            ("authorization", "Basic dXNlcjpwYXNz"),
+            ("cookie", "jwt=cookie-token"),
+        ]);
+        assert_eq!(
+            extract_token(&basic, "jwt"),
+            Some("cookie-token".to_owned())
+        );
+
+        let empty_bearer = headers(&[("authorization", "Bearer ")]);
+        assert_eq!(extract_token(&empty_bearer, "jwt"), None);
+
+        let lowercase = headers(&[("authorization", "bearer header-token")]);
+        assert_eq!(
+            extract_token(&lowercase, "jwt"),
+            Some("header-token".to_owned())
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    http::Request,
+    response::{IntoResponse, Response},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::AuthConfig;
+
+// Rejects requests without a valid token with `AuthError`'s response, and hands the
+// `AuthenticatedUser` of the others to the inner service in the request extensions, where
+// the extractor picks it up without verifying the token again
+#[derive(Clone)]
+pub struct RequireAuthLayer {
+    config: AuthConfig,
+}
+
+impl RequireAuthLayer {
+    pub fn new(config: AuthConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for RequireAuthLayer {
+    type Service = RequireAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireAuth {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequireAuth<S> {
+    inner: S,
+    config: AuthConfig,
+}
+
+impl<S, B> Service<Request<B>> for RequireAuth<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // The clone may not be ready, keep the one `poll_ready` was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            match config.authenticate(request.headers()).await {
+                Ok(user) => {
+                    request.extensions_mut().insert(user);
+                    inner.call(request).await
+                }
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        async_trait,
+        body::Body,
+        http::{HeaderMap, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{AuthError, AuthenticatedUser, Claims, TokenVerifier};
+
+    // Accepts the token "valid" for test@example.com, and fails on "crash"
+    struct StaticVerifier;
+
+    #[async_trait]
+    impl TokenVerifier for StaticVerifier {
+        async fn verify(&self, token: &str, _headers: &HeaderMap) -> Result<Claims, AuthError> {
+            match token {
+                "valid" => Ok(Claims {
+                    sub: "test@example.com".to_owned(),
+                    exp: usize::MAX,
+                    iat: 0,
+                    jti: "jti".to_owned(),
+                    roles: Vec::new(),
+                    permissions: Vec::new(),
+                }),
+                "crash" => Err(AuthError::Unexpected("verifier is down".into())),
+                _ => Err(AuthError::InvalidToken),
+            }
+        }
+    }
+
+    fn app() -> Router {
+        let config = AuthConfig::new("jwt", StaticVerifier);
+
+        Router::new()
+            .route(
+                "/protected",
+                get(|AuthenticatedUser(claims): AuthenticatedUser| async move { claims.sub }),
+            )
+            .route_layer(RequireAuthLayer::new(config.clone()))
+            .with_state(config)
+    }
+
+    async fn get_protected(header: Option<(&str, &str)>) -> (StatusCode, String) {
+        let mut request = Request::get("/protected");
+        if let Some((name, value)) = header {
+            request = request.header(name, value);
+        }
+
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_layer_passes_the_user_to_the_handler() {
+        for header in [("cookie", "jwt=valid"), ("authorization", "Bearer valid")] {
+            assert_eq!(
+                get_protected(Some(header)).await,
+                (StatusCode::OK, "test@example.com".to_owned())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layer_rejects_requests_without_a_valid_token() {
+        assert_eq!(get_protected(None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get_protected(Some(("cookie", "jwt=invalid"))).await.0,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_protected(Some(("authorization", "Bearer crash")))
+                .await
+                .0,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
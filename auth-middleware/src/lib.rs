@@ -0,0 +1,18 @@
+// Authentication for axum services that accept tokens issued by auth-service.
+//
+// `AuthenticatedUser` is an extractor for the claims of a request's token, and
+// `RequireAuthLayer` a Tower layer that rejects requests without a valid one. Tokens are
+// read from the `Authorization: Bearer` header, or else from the auth cookie, and checked by
+// a `TokenVerifier`: `LocalVerifier` with the JWT secret (feature "local") or
+// `RemoteVerifier` through auth-service's /verify-token (feature "remote").
+mod claims;
+mod error;
+mod extract;
+mod layer;
+mod verifier;
+
+pub use claims::*;
+pub use error::*;
+pub use extract::*;
+pub use layer::*;
+pub use verifier::*;
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::{async_trait, http::HeaderMap};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use sha2::{Digest, Sha256};
+
+use crate::{AuthError, BannedTokens, Claims, TokenVerifier};
+
+// Verifies tokens with the secret auth-service signs them with, without a network call.
+// Tokens revoked by "log out everywhere" stay valid here until they expire, use
+// `RemoteVerifier` where that matters.
+#[derive(Clone)]
+pub struct LocalVerifier {
+    key: DecodingKey,
+    banned_tokens: Option<Arc<dyn BannedTokens>>,
+}
+
+impl LocalVerifier {
+    pub fn new(jwt_secret: &[u8]) -> Self {
+        Self {
+            key: DecodingKey::from_secret(jwt_secret),
+            banned_tokens: None,
+        }
+    }
+
+    // Also rejects tokens that were used to log out
+    pub fn with_banned_tokens(mut self, banned_tokens: impl BannedTokens + 'static) -> Self {
+        self.banned_tokens = Some(Arc::new(banned_tokens));
+        self
+    }
+}
+
+#[async_trait]
+impl TokenVerifier for LocalVerifier {
+    async fn verify(&self, token: &str, _headers: &HeaderMap) -> Result<Claims, AuthError> {
+        // No leeway: tokens are accepted until exactly `exp`, so bans can expire at `exp` too
+        let mut validation = Validation::default();
+        validation.leeway = 0;
+
+        let mut claims = decode::<Claims>(token, &self.key, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| AuthError::InvalidToken)?;
+
+        // Tokens issued before `jti` was added are banned under their SHA-256 digest, the
+        // same way auth-service identifies them
+        if claims.jti.is_empty() {
+            claims.jti = format!("{:x}", Sha256::digest(token));
+        }
+
+        if let Some(banned_tokens) = &self.banned_tokens {
+            let is_banned = banned_tokens
+                .is_banned(&claims)
+                .await
+                .map_err(AuthError::Unexpected)?;
+            if is_banned {
+                return Err(AuthError::InvalidToken);
+            }
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn token(jti: &str, exp: usize, secret: &[u8]) -> String {
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp,
+            iat: 0,
+            jti: jti.to_owned(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    fn in_an_hour() -> usize {
+        (chrono::Utc::now().timestamp() + 3600) as usize
+    }
+
+    #[derive(Default)]
+    struct BannedJtis(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl BannedTokens for Arc<BannedJtis> {
+        async fn is_banned(&self, claims: &Claims) -> Result<bool, crate::BoxError> {
+            Ok(self.0.lock().unwrap().contains(&claims.jti))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_checks_signature_and_expiry() {
+        let verifier = LocalVerifier::new(SECRET);
+        let headers = HeaderMap::new();
+
+        let claims = verifier
+            .verify(&token("jti", in_an_hour(), SECRET), &headers)
+            .await
+            .unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+
+        for token in [
+            token("jti", in_an_hour(), b"other secret"),
+            token("jti", 1, SECRET),
+            "not a token".to_owned(),
+        ] {
+            assert!(matches!(
+                verifier.verify(&token, &headers).await,
+                Err(AuthError::InvalidToken)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_rejects_banned_tokens() {
+        let banned = Arc::new(BannedJtis::default());
+        let verifier = LocalVerifier::new(SECRET).with_banned_tokens(banned.clone());
+        let token = token("jti", in_an_hour(), SECRET);
+        let headers = HeaderMap::new();
+
+        assert!(verifier.verify(&token, &headers).await.is_ok());
+
+        banned.0.lock().unwrap().push("jti".to_owned());
+        assert!(matches!(
+            verifier.verify(&token, &headers).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_identifies_legacy_tokens_by_digest() {
+        let banned = Arc::new(BannedJtis::default());
+        let verifier = LocalVerifier::new(SECRET).with_banned_tokens(banned.clone());
+        let token = token("", in_an_hour(), SECRET);
+        let digest = format!("{:x}", Sha256::digest(&token));
+        let headers = HeaderMap::new();
+
+        let claims = verifier.verify(&token, &headers).await.unwrap();
+        assert_eq!(claims.jti, digest);
+
+        banned.0.lock().unwrap().push(digest);
+        assert!(matches!(
+            verifier.verify(&token, &headers).await,
+            Err(AuthError::InvalidToken)
+        ));
+    }
+}
@@ -0,0 +1,26 @@
+use axum::{async_trait, http::HeaderMap};
+
+use crate::{AuthError, Claims};
+
+#[cfg(feature = "local")]
+mod local;
+#[cfg(feature = "remote")]
+mod remote;
+
+#[cfg(feature = "local")]
+pub use local::*;
+#[cfg(feature = "remote")]
+pub use remote::*;
+
+#[async_trait]
+pub trait TokenVerifier: Send + Sync {
+    // The claims of `token` if it is valid. `headers` are those of the request the token
+    // came with, e.g. to forward its request ID.
+    async fn verify(&self, token: &str, headers: &HeaderMap) -> Result<Claims, AuthError>;
+}
+
+// Tokens that were used to log out, checked by `LocalVerifier` after the signature
+#[async_trait]
+pub trait BannedTokens: Send + Sync {
+    async fn is_banned(&self, claims: &Claims) -> Result<bool, crate::BoxError>;
+}
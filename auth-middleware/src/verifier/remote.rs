@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use axum::{async_trait, http::HeaderMap};
+use serde::Serialize;
+
+use crate::{AuthError, Claims, TokenVerifier};
+
+type ForwardHeaders = dyn Fn(&HeaderMap) -> reqwest::header::HeaderMap + Send + Sync;
+
+// Verifies tokens by posting them to auth-service's /verify-token, which also checks
+// revocations and banned tokens
+#[derive(Clone)]
+pub struct RemoteVerifier {
+    client: reqwest::Client,
+    verify_token_url: String,
+    forward_headers: Option<Arc<ForwardHeaders>>,
+}
+
+impl RemoteVerifier {
+    pub fn new(verify_token_url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            verify_token_url: verify_token_url.into(),
+            forward_headers: None,
+        }
+    }
+
+    // E.g. to set a timeout
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    // Headers to send along, built from those of the request being authenticated, e.g. its
+    // request ID and trace context
+    pub fn with_forwarded_headers(
+        mut self,
+        forward_headers: impl Fn(&HeaderMap) -> reqwest::header::HeaderMap + Send + Sync + 'static,
+    ) -> Self {
+        self.forward_headers = Some(Arc::new(forward_headers));
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct VerifyTokenRequest<'a> {
+    token: &'a str,
+}
+
+#[async_trait]
+impl TokenVerifier for RemoteVerifier {
+    async fn verify(&self, token: &str, headers: &HeaderMap) -> Result<Claims, AuthError> {
+        let mut request = self
+            .client
+            .post(&self.verify_token_url)
+            .json(&VerifyTokenRequest { token });
+        if let Some(forward_headers) = &self.forward_headers {
+            request = request.headers(forward_headers(headers));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| AuthError::Unexpected(e.into()))?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => response
+                .json::<Claims>()
+                .await
+                .map_err(|e| AuthError::Unexpected(e.into())),
+            status if status.is_client_error() => Err(AuthError::InvalidToken),
+            status => Err(AuthError::Unexpected(
+                format!("Unexpected status {status} from /verify-token").into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    fn claims_json() -> serde_json::Value {
+        serde_json::json!({
+            "sub": "test@example.com",
+            "exp": 2000000000,
+            "iat": 1000000000,
+            "jti": "jti",
+            "roles": ["admin"],
+            "permissions": ["users:read"],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_verify_returns_the_claims_of_valid_tokens() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/verify-token"))
+            .and(body_json(serde_json::json!({ "token": "valid" })))
+            .and(header("x-request-id", "request-id"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(claims_json()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let verifier = RemoteVerifier::new(format!("{}/verify-token", server.uri()))
+            .with_forwarded_headers(|incoming| {
+                let mut headers = reqwest::header::HeaderMap::new();
+                if let Some(request_id) = incoming.get("x-request-id") {
+                    headers.insert(
+                        "x-request-id",
+                        reqwest::header::HeaderValue::from_bytes(request_id.as_bytes()).unwrap(),
+                    );
+                }
+                headers
+            });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "request-id".parse().unwrap());
+        let claims = verifier.verify("valid", &headers).await.unwrap();
+
+        assert_eq!(claims.sub, "test@example.com");
+        assert!(claims.has_role("admin"));
+        assert!(claims.has_permission("users:read"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_maps_errors() {
+        let server = MockServer::start().await;
+        for (token, status) in [("invalid", 401), ("malformed", 400), ("crash", 500)] {
+            Mock::given(method("POST"))
+                .and(body_json(serde_json::json!({ "token": token })))
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&server)
+                .await;
+        }
+        let verifier = RemoteVerifier::new(format!("{}/verify-token", server.uri()));
+        let headers = HeaderMap::new();
+
+        assert!(matches!(
+            verifier.verify("invalid", &headers).await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            verifier.verify("malformed", &headers).await,
+            Err(AuthError::InvalidToken)
+        ));
+        assert!(matches!(
+            verifier.verify("crash", &headers).await,
+            Err(AuthError::Unexpected(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_fails_when_auth_service_is_unreachable() {
+        let verifier = RemoteVerifier::new("http://127.0.0.1:9/verify-token");
+
+        assert!(matches!(
+            verifier.verify("valid", &HeaderMap::new()).await,
+            Err(AuthError::Unexpected(_))
+        ));
+    }
+}
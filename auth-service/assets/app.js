@@ -129,7 +129,6 @@ const TwoFAErrAlter = document.getElementById("2fa-err-alert");
 TwoFAButton.addEventListener("click", (e) => {
     e.preventDefault();
 
-    const email = TwoFAForm.email.value;
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
 
@@ -138,7 +137,7 @@ TwoFAButton.addEventListener("click", (e) => {
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
+        body: JSON.stringify({ loginAttemptId, "2FACode": TwoFACode }),
     }).then(response => {
         if (response.ok) {
             TwoFAForm.email.value = "";
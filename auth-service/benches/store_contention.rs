@@ -0,0 +1,356 @@
+// Store throughput under concurrent requests, with and without a global lock around the
+// store. Each iteration runs `CONCURRENT_USERS` clients at once.
+//
+// signup_and_login: each client signs up a new user and then logs in as it `LOGINS_PER_USER`
+// times, against the global `RwLock` AppState used to wrap every store in, and without it.
+// The store answers after `ROUND_TRIP`, standing in for a database call, so the numbers
+// reflect time spent waiting for the lock rather than for the CPU.
+//
+// ban_and_check_token, add_and_take_2fa_code: the Redis stores against a live Redis
+// (REDIS_HOST_NAME, 127.0.0.1 by default), skipped when it can't be reached. Each client
+// bans a token and checks it `CHECKS_PER_TOKEN` times like /verify-token does, or goes
+// through a 2FA attempt. "global_lock" serializes every command like the single
+// `Arc<RwLock<redis::Connection>>` the stores used to share.
+//
+//     cargo bench --bench store_contention
+use std::{future::Future, sync::Arc, time::Duration};
+
+use auth_service::{
+    domain::{
+        BannedTokenStore, Email, LoginAttemptId, Password, Role, TwoFAAttempt, TwoFACode,
+        TwoFACodeStore, User, UserQuery, UserStore, UserStoreError,
+    },
+    get_redis_connection,
+    services::data_stores::{HashMapUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
+    utils::{env, Claims},
+};
+use chrono::{DateTime, Utc};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::future::join_all;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use tokio::{
+    runtime::Runtime,
+    sync::{Mutex, RwLock},
+};
+
+const CONCURRENT_USERS: usize = 32;
+const LOGINS_PER_USER: usize = 4;
+const CHECKS_PER_TOKEN: usize = 4;
+const ROUND_TRIP: Duration = Duration::from_millis(2);
+const PASSWORD: &str = "password123!";
+const TWO_FA_CODE_TTL_SECONDS: u64 = 600;
+
+// Delays every call by `ROUND_TRIP`
+#[derive(Default)]
+struct RemoteUserStore {
+    inner: HashMapUserStore,
+}
+
+#[async_trait::async_trait]
+impl UserStore for RemoteUserStore {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.add_user(user).await
+    }
+
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.get_user(email).await
+    }
+
+    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.validate_user(email, password).await
+    }
+
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.set_tokens_valid_after(email, valid_after).await
+    }
+
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.list_users(query).await
+    }
+
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.set_disabled(email, disabled).await
+    }
+
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.set_requires_2fa(email, requires_2fa).await
+    }
+
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner
+            .record_failed_login(email, max_attempts, lock_until)
+            .await
+    }
+
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.reset_lockout(email).await
+    }
+
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.delete_user(email).await
+    }
+
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.assign_role(email, role).await
+    }
+
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.remove_role(email, role).await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError> {
+        tokio::time::sleep(ROUND_TRIP).await;
+        self.inner.list_roles().await
+    }
+}
+
+fn user(email: &Email) -> User {
+    let password = Password::parse(&Secret::new(PASSWORD.to_owned())).unwrap();
+    User::new(email.clone(), password, false)
+}
+
+fn random_email() -> Email {
+    Email::parse(&format!("{}@example.com", uuid::Uuid::new_v4())).unwrap()
+}
+
+fn claims() -> Claims {
+    let now = Utc::now().timestamp() as usize;
+    Claims {
+        sub: random_email().as_ref().expose_secret().to_owned(),
+        exp: now + 600,
+        iat: now,
+        jti: uuid::Uuid::new_v4().to_string(),
+        roles: Vec::new(),
+        permissions: Vec::new(),
+    }
+}
+
+fn two_fa_attempt() -> (LoginAttemptId, TwoFAAttempt) {
+    let attempt = TwoFAAttempt::new(random_email(), TwoFACode::default(), "bench".to_owned());
+    (LoginAttemptId::default(), attempt)
+}
+
+// Runs `CONCURRENT_USERS` clients at once
+async fn concurrently<F, Fut>(client: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let clients = (0..CONCURRENT_USERS).map(|_| tokio::spawn(client()));
+
+    for client in join_all(clients).await {
+        client.unwrap();
+    }
+}
+
+// The previous AppState wiring: signups take the write lock, and logins hold the read lock
+// across both lookups
+async fn signup_and_login_with_global_lock(store: Arc<RwLock<RemoteUserStore>>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let email = random_email();
+            store.write().await.add_user(user(&email)).await.unwrap();
+
+            for _ in 0..LOGINS_PER_USER {
+                let store = store.read().await;
+                store.validate_user(&email, PASSWORD).await.unwrap();
+                store.get_user(&email).await.unwrap();
+            }
+        }
+    })
+    .await
+}
+
+async fn signup_and_login(store: Arc<RemoteUserStore>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let email = random_email();
+            store.add_user(user(&email)).await.unwrap();
+
+            for _ in 0..LOGINS_PER_USER {
+                store.validate_user(&email, PASSWORD).await.unwrap();
+                store.get_user(&email).await.unwrap();
+            }
+        }
+    })
+    .await
+}
+
+async fn ban_and_check_token_with_global_lock(store: Arc<Mutex<RedisBannedTokenStore>>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let claims = claims();
+            store.lock().await.add_token(&claims).await.unwrap();
+
+            for _ in 0..CHECKS_PER_TOKEN {
+                assert!(store.lock().await.is_token_banned(&claims).await.unwrap());
+            }
+        }
+    })
+    .await
+}
+
+async fn ban_and_check_token(store: Arc<RedisBannedTokenStore>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let claims = claims();
+            store.add_token(&claims).await.unwrap();
+
+            for _ in 0..CHECKS_PER_TOKEN {
+                assert!(store.is_token_banned(&claims).await.unwrap());
+            }
+        }
+    })
+    .await
+}
+
+async fn add_and_take_2fa_code_with_global_lock(store: Arc<Mutex<RedisTwoFACodeStore>>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let (id, attempt) = two_fa_attempt();
+            store
+                .lock()
+                .await
+                .add_code(id.clone(), attempt)
+                .await
+                .unwrap();
+            store.lock().await.get_code(&id).await.unwrap();
+            assert!(store.lock().await.take_code(&id).await.unwrap().is_some());
+        }
+    })
+    .await
+}
+
+async fn add_and_take_2fa_code(store: Arc<RedisTwoFACodeStore>) {
+    concurrently(|| {
+        let store = store.clone();
+        async move {
+            let (id, attempt) = two_fa_attempt();
+            store.add_code(id.clone(), attempt).await.unwrap();
+            store.get_code(&id).await.unwrap();
+            assert!(store.take_code(&id).await.unwrap().is_some());
+        }
+    })
+    .await
+}
+
+fn bench_signup_and_login(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let store = Arc::new(RemoteUserStore::default());
+    let locked_store = Arc::new(RwLock::new(RemoteUserStore::default()));
+
+    let mut group = c.benchmark_group("signup_and_login");
+    group.throughput(Throughput::Elements(
+        (CONCURRENT_USERS * (1 + LOGINS_PER_USER)) as u64,
+    ));
+
+    group.bench_function(BenchmarkId::new("global_lock", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| signup_and_login_with_global_lock(locked_store.clone()))
+    });
+    group.bench_function(BenchmarkId::new("shared", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| signup_and_login(store.clone()))
+    });
+
+    group.finish();
+}
+
+fn redis_connection(runtime: &Runtime) -> Option<ConnectionManager> {
+    let host_name =
+        std::env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or_else(|_| "127.0.0.1".to_owned());
+    let connect = async {
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            get_redis_connection(host_name.clone()),
+        )
+        .await
+    };
+
+    match runtime.block_on(connect) {
+        Ok(Ok(conn)) => Some(conn),
+        _ => {
+            eprintln!("Redis is not reachable at {host_name}, skipping the Redis store benchmarks");
+            None
+        }
+    }
+}
+
+fn bench_redis_stores(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let Some(conn) = redis_connection(&runtime) else {
+        return;
+    };
+
+    let store = Arc::new(RedisBannedTokenStore::new(conn.clone()));
+    let locked_store = Arc::new(Mutex::new(RedisBannedTokenStore::new(conn.clone())));
+
+    let mut group = c.benchmark_group("ban_and_check_token");
+    group.throughput(Throughput::Elements(
+        (CONCURRENT_USERS * (1 + CHECKS_PER_TOKEN)) as u64,
+    ));
+    group.bench_function(BenchmarkId::new("global_lock", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| ban_and_check_token_with_global_lock(locked_store.clone()))
+    });
+    group.bench_function(BenchmarkId::new("multiplexed", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| ban_and_check_token(store.clone()))
+    });
+    group.finish();
+
+    let store = Arc::new(RedisTwoFACodeStore::new(
+        conn.clone(),
+        TWO_FA_CODE_TTL_SECONDS,
+    ));
+    let locked_store = Arc::new(Mutex::new(RedisTwoFACodeStore::new(
+        conn,
+        TWO_FA_CODE_TTL_SECONDS,
+    )));
+
+    let mut group = c.benchmark_group("add_and_take_2fa_code");
+    group.throughput(Throughput::Elements((CONCURRENT_USERS * 3) as u64));
+    group.bench_function(BenchmarkId::new("global_lock", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| add_and_take_2fa_code_with_global_lock(locked_store.clone()))
+    });
+    group.bench_function(BenchmarkId::new("multiplexed", CONCURRENT_USERS), |b| {
+        b.to_async(&runtime)
+            .iter(|| add_and_take_2fa_code(store.clone()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_signup_and_login, bench_redis_stores);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_sms_channel_requires_phone_number;
+ALTER TABLE users DROP COLUMN IF EXISTS two_fa_channel;
+ALTER TABLE users DROP COLUMN IF EXISTS phone_number;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS phone_number TEXT,
+    ADD COLUMN IF NOT EXISTS two_fa_channel TEXT NOT NULL DEFAULT 'email';
+
+-- Codes can only go by SMS to users with a phone number
+ALTER TABLE users
+    ADD CONSTRAINT users_sms_channel_requires_phone_number
+    CHECK (two_fa_channel <> 'sms' OR phone_number IS NOT NULL);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS two_fa_attempts;
+DROP TABLE IF EXISTS banned_tokens;
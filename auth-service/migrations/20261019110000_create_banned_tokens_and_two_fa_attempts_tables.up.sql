@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS banned_tokens (
+    -- SHA-256 of the token, the token itself is never stored
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS banned_tokens_expires_at_idx ON banned_tokens (expires_at);
+
+CREATE TABLE IF NOT EXISTS two_fa_attempts (
+    login_attempt_id TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL,
+    code TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS two_fa_attempts_expires_at_idx ON two_fa_attempts (expires_at);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE banned_tokens RENAME COLUMN jti TO token_hash;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+ALTER TABLE audit_events DROP COLUMN IF EXISTS target;
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS locked_until,
+    DROP COLUMN IF EXISTS failed_login_attempts,
+    DROP COLUMN IF EXISTS is_admin,
+    DROP COLUMN IF EXISTS disabled;
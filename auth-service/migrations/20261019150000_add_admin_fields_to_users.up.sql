@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Disabled users can't log in, admins can use the /admin routes
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Consecutive failed logins, the account is locked until `locked_until` once they add up
+    ADD COLUMN IF NOT EXISTS failed_login_attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
+
+-- The user an admin action was applied to, `actor` being the admin
+ALTER TABLE audit_events ADD COLUMN IF NOT EXISTS target TEXT;
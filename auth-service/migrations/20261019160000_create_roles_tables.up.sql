@@ -0,0 +1,29 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS roles (
+    name TEXT NOT NULL PRIMARY KEY
+);
+
+-- Permissions are named `<resource>:<action>`, e.g. `users:read`
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    email TEXT NOT NULL REFERENCES users (email) ON DELETE CASCADE,
+    role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+    PRIMARY KEY (email, role)
+);
+
+INSERT INTO roles (name) VALUES ('admin') ON CONFLICT DO NOTHING;
+INSERT INTO role_permissions (role, permission)
+VALUES ('admin', 'users:read'), ('admin', 'users:write')
+ON CONFLICT DO NOTHING;
+
+-- Admins keep their access as holders of the admin role
+INSERT INTO user_roles (email, role)
+SELECT email, 'admin' FROM users WHERE is_admin
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE users DROP COLUMN IF EXISTS is_admin;
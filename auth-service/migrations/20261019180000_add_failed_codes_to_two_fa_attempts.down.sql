@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE two_fa_attempts DROP COLUMN IF EXISTS failed_codes;
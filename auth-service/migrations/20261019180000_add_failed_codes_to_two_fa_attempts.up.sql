@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Wrong codes entered for the attempt, which is deleted after `two_fa.max_failed_codes`
+ALTER TABLE two_fa_attempts ADD COLUMN IF NOT EXISTS failed_codes INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS email_outbox;
+DROP TABLE IF EXISTS two_fa_attempts;
+DROP TABLE IF EXISTS banned_tokens;
+DROP TABLE IF EXISTS users;
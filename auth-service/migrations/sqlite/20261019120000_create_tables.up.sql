@@ -0,0 +1,50 @@
+-- Add up migration script here
+-- Timestamps are stored as Unix time in microseconds so they compare as numbers
+CREATE TABLE IF NOT EXISTS users (
+    email TEXT NOT NULL PRIMARY KEY,
+    password_hash TEXT NOT NULL,
+    requires_2fa BOOLEAN NOT NULL DEFAULT FALSE,
+    phone_number TEXT,
+    two_fa_channel TEXT NOT NULL DEFAULT 'email',
+    -- Codes can only go by SMS to users with a phone number
+    CHECK (two_fa_channel <> 'sms' OR phone_number IS NOT NULL)
+);
+
+CREATE TABLE IF NOT EXISTS banned_tokens (
+    -- SHA-256 of the token, the token itself is never stored
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS banned_tokens_expires_at_idx ON banned_tokens (expires_at);
+
+CREATE TABLE IF NOT EXISTS two_fa_attempts (
+    login_attempt_id TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL,
+    code TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS two_fa_attempts_expires_at_idx ON two_fa_attempts (expires_at);
+
+CREATE TABLE IF NOT EXISTS email_outbox (
+    id TEXT NOT NULL PRIMARY KEY,
+    idempotency_key TEXT NOT NULL UNIQUE,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    -- Bodies are cleared once delivered, they may contain 2FA codes
+    html_body TEXT,
+    text_body TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    sent_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx
+    ON email_outbox (next_attempt_at)
+    WHERE status = 'pending';
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Tokens are banned by their jti. Tokens without one are identified by their SHA-256 digest,
+-- so bans recorded before this migration still apply.
+ALTER TABLE banned_tokens RENAME COLUMN token_hash TO jti;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Tokens issued to the user before this time are rejected, NULL until they are first revoked
+ALTER TABLE users ADD COLUMN tokens_valid_after INTEGER;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE users DROP COLUMN locked_until;
+ALTER TABLE users DROP COLUMN failed_login_attempts;
+ALTER TABLE users DROP COLUMN is_admin;
+ALTER TABLE users DROP COLUMN disabled;
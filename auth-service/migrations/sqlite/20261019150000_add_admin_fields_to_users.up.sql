@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Disabled users can't log in, admins can use the /admin routes
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
+-- Consecutive failed logins, the account is locked until `locked_until` once they add up
+ALTER TABLE users ADD COLUMN failed_login_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN locked_until INTEGER;
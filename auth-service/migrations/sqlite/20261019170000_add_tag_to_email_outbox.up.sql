@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- Passed on to the email provider to group emails of one kind, e.g. "2fa-code"
+ALTER TABLE email_outbox ADD COLUMN tag TEXT;
@@ -2,33 +2,96 @@ use std::sync::Arc;
 
 use tokio::sync::RwLock;
 
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore, UserStore};
+use crate::{
+    domain::{
+        AuditEvent, AuditSink, BannedTokenStore, EmailClient, EmailOutbox, HealthCheck, SmsClient,
+        TokensValidAfterCache, TwoFACodeStore, UserStore,
+    },
+    services::{CapturingEmailClient, TokenRevocations},
+    settings::Settings,
+};
 
-pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
-pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
+// Stores handle concurrent calls themselves (connection pools, or locks around in-memory
+// maps), so they are shared without a lock of their own
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore + Send + Sync>;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore + Send + Sync>;
+pub type UserStoreType = Arc<dyn UserStore + Send + Sync>;
+pub type TokensValidAfterCacheType = Arc<dyn TokensValidAfterCache + Send + Sync>;
 pub type EmailClientType = Arc<RwLock<dyn EmailClient + Send + Sync>>;
+pub type EmailOutboxType = Arc<dyn EmailOutbox + Send + Sync>;
+pub type SmsClientType = Arc<dyn SmsClient + Send + Sync>;
+pub type HealthCheckType = Arc<dyn HealthCheck + Send + Sync>;
+pub type AuditSinkType = Arc<dyn AuditSink + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
+    pub settings: Arc<Settings>,
     pub user_store: UserStoreType,
+    // Looks up and bumps each user's `tokens_valid_after`, backed by `user_store`
+    pub token_revocations: TokenRevocations,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    pub email_outbox: EmailOutboxType,
+    // Only set when SMS delivery of 2FA codes is configured
+    pub sms_client: Option<SmsClientType>,
+    pub health_checks: Vec<HealthCheckType>,
+    pub audit_sink: AuditSinkType,
+    // Emails captured by the "capture" email provider, listed at /dev/mailbox
+    pub dev_mailbox: Option<CapturingEmailClient>,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        settings: Arc<Settings>,
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        email_outbox: EmailOutboxType,
+        health_checks: Vec<HealthCheckType>,
+        audit_sink: AuditSinkType,
     ) -> Self {
         Self {
+            settings,
+            token_revocations: TokenRevocations::new(user_store.clone()),
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
+            email_outbox,
+            sms_client: None,
+            health_checks,
+            audit_sink,
+            dev_mailbox: None,
+        }
+    }
+
+    pub fn with_sms_client(mut self, sms_client: SmsClientType) -> Self {
+        self.sms_client = Some(sms_client);
+        self
+    }
+
+    pub fn with_tokens_valid_after_cache(mut self, cache: TokensValidAfterCacheType) -> Self {
+        self.token_revocations = self.token_revocations.with_cache(cache);
+        self
+    }
+
+    pub fn with_dev_mailbox(mut self, mailbox: CapturingEmailClient) -> Self {
+        self.dev_mailbox = Some(mailbox);
+        self
+    }
+
+    // Appends an event to the audit log. A failing sink is logged but does not fail
+    // the request that triggered the event.
+    pub async fn audit(&self, event: AuditEvent) {
+        if let Err(e) = self.audit_sink.record(&event).await {
+            tracing::error!(
+                error = ?e,
+                kind = event.kind.as_str(),
+                "Failed to record audit event"
+            );
         }
     }
 }
@@ -0,0 +1,230 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::AuthAPIError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    Signup,
+    Login,
+    #[serde(rename = "2fa_code_sent")]
+    TwoFACodeSent,
+    #[serde(rename = "2fa_verified")]
+    TwoFAVerified,
+    Logout,
+    TokenBanned,
+    TokensRevoked,
+    AdminUsersListed,
+    AdminUserViewed,
+    AdminUserDisabled,
+    AdminUserEnabled,
+    #[serde(rename = "admin_2fa_required")]
+    AdminTwoFARequired,
+    AdminLockoutReset,
+    AdminSessionsRevoked,
+    AdminUserDeleted,
+}
+
+impl AuditEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Login => "login",
+            Self::TwoFACodeSent => "2fa_code_sent",
+            Self::TwoFAVerified => "2fa_verified",
+            Self::Logout => "logout",
+            Self::TokenBanned => "token_banned",
+            Self::TokensRevoked => "tokens_revoked",
+            Self::AdminUsersListed => "admin_users_listed",
+            Self::AdminUserViewed => "admin_user_viewed",
+            Self::AdminUserDisabled => "admin_user_disabled",
+            Self::AdminUserEnabled => "admin_user_enabled",
+            Self::AdminTwoFARequired => "admin_2fa_required",
+            Self::AdminLockoutReset => "admin_lockout_reset",
+            Self::AdminSessionsRevoked => "admin_sessions_revoked",
+            Self::AdminUserDeleted => "admin_user_deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+// Where a request came from, as far as the audit log is concerned.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    // Identifies the client software that started a login, so the 2FA step can be tied to it.
+    // The IP is left out on purpose: it legitimately changes mid-login on mobile networks.
+    pub fn fingerprint(&self) -> String {
+        let digest = Sha256::digest(self.user_agent.as_deref().unwrap_or_default());
+        format!("{digest:x}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub kind: AuditEventKind,
+    // The email of the user who acted, when known
+    pub actor: Option<String>,
+    // The user an admin action was applied to
+    #[serde(default)]
+    pub target: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: AuditOutcome,
+    // Why the attempt failed; never contains secrets such as passwords or codes
+    pub reason: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn success(kind: AuditEventKind, actor: Option<String>, client: &ClientInfo) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            occurred_at: Utc::now(),
+            kind,
+            actor,
+            target: None,
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+            outcome: AuditOutcome::Success,
+            reason: None,
+        }
+    }
+
+    pub fn failure(
+        kind: AuditEventKind,
+        actor: Option<String>,
+        client: &ClientInfo,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self {
+            outcome: AuditOutcome::Failure,
+            reason: Some(reason.into()),
+            ..Self::success(kind, actor, client)
+        }
+    }
+
+    pub fn with_target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    // Builds the event for the outcome of a route handler
+    pub fn from_result<T>(
+        kind: AuditEventKind,
+        actor: Option<String>,
+        client: &ClientInfo,
+        result: &Result<T, AuthAPIError>,
+    ) -> Self {
+        match result {
+            Ok(_) => Self::success(kind, actor, client),
+            Err(e) => Self::failure(kind, actor, client, e.to_string()),
+        }
+    }
+}
+
+// This trait represents the append-only destination for security audit events.
+#[async_trait::async_trait]
+pub trait AuditSink {
+    async fn record(&self, event: &AuditEvent) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> ClientInfo {
+        ClientInfo {
+            ip: Some("203.0.113.7".to_owned()),
+            user_agent: Some("curl/8.0".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_from_result_records_outcome_and_reason() {
+        let ok: Result<(), AuthAPIError> = Ok(());
+        let event = AuditEvent::from_result(
+            AuditEventKind::Login,
+            Some("test@example.com".to_owned()),
+            &client(),
+            &ok,
+        );
+
+        assert_eq!(event.outcome, AuditOutcome::Success);
+        assert_eq!(event.reason, None);
+        assert_eq!(event.ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(event.user_agent.as_deref(), Some("curl/8.0"));
+
+        let err: Result<(), AuthAPIError> = Err(AuthAPIError::IncorrectCredentials);
+        let event = AuditEvent::from_result(AuditEventKind::Login, None, &client(), &err);
+
+        assert_eq!(event.outcome, AuditOutcome::Failure);
+        assert_eq!(event.reason.as_deref(), Some("Incorrect credentials"));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_ip() {
+        let moved = ClientInfo {
+            ip: Some("198.51.100.1".to_owned()),
+            ..client()
+        };
+        let other_browser = ClientInfo {
+            user_agent: Some("Mozilla/5.0".to_owned()),
+            ..client()
+        };
+
+        assert_eq!(client().fingerprint(), moved.fingerprint());
+        assert_ne!(client().fingerprint(), other_browser.fingerprint());
+        assert_eq!(client().fingerprint().len(), 64);
+    }
+
+    #[test]
+    fn test_kind_serializes_as_its_name() {
+        for kind in [
+            AuditEventKind::Signup,
+            AuditEventKind::Login,
+            AuditEventKind::TwoFACodeSent,
+            AuditEventKind::TwoFAVerified,
+            AuditEventKind::Logout,
+            AuditEventKind::TokenBanned,
+            AuditEventKind::TokensRevoked,
+            AuditEventKind::AdminUsersListed,
+            AuditEventKind::AdminUserViewed,
+            AuditEventKind::AdminUserDisabled,
+            AuditEventKind::AdminUserEnabled,
+            AuditEventKind::AdminTwoFARequired,
+            AuditEventKind::AdminLockoutReset,
+            AuditEventKind::AdminSessionsRevoked,
+            AuditEventKind::AdminUserDeleted,
+        ] {
+            assert_eq!(
+                serde_json::to_value(kind).unwrap(),
+                serde_json::Value::String(kind.as_str().to_owned())
+            );
+        }
+    }
+}
@@ -1,20 +1,94 @@
+use std::hash::{Hash, Hasher};
+
+use chrono::{DateTime, SubsecRound, Utc};
 use color_eyre::eyre::{eyre, Context, Report, Result};
 use rand::Rng;
 use secrecy::{ExposeSecret, Secret};
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::domain::Email;
+use crate::{
+    domain::Email,
+    utils::{constant_time_eq, Claims},
+};
 
-use super::User;
+use super::{Role, User};
 
+// Stores are shared between requests without a lock, so every method takes `&self` and
+// implementations handle concurrent calls themselves.
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
 
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
 
     async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError>;
+
+    // Rejects every token issued to the user before `valid_after`, e.g. to log them out
+    // everywhere
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+
+    // Users ordered by email, starting after `query.after`
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError>;
+
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError>;
+
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError>;
+
+    // Counts a failed login. Reaching `max_attempts` consecutive failures locks the account
+    // until `lock_until` and starts the count over.
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+
+    // Clears the failed login count and any lockout
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError>;
+
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError>;
+
+    // Gives the user an existing role, doing nothing if they already have it. Roles only
+    // reach the user's tokens issued afterwards.
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError>;
+
+    // Takes a role away from the user, doing nothing if they don't have it
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError>;
+
+    // Every defined role, ordered by name
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError>;
+}
+
+// One page of users, for the admin API
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserQuery {
+    // Only users whose email contains this, ignoring case
+    pub search: Option<String>,
+    // The email of the last user of the previous page
+    pub after: Option<String>,
+    pub limit: u32,
+}
+
+impl UserQuery {
+    // `search` as a LIKE pattern, with `\` escaping the pattern's own wildcards
+    pub fn like_pattern(&self) -> Option<String> {
+        self.search.as_ref().map(|search| {
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{escaped}%")
+        })
+    }
 }
 
 #[derive(Debug, Error)]
@@ -25,6 +99,8 @@ pub enum UserStoreError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Role not found")]
+    RoleNotFound,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -36,16 +112,31 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::RoleNotFound, Self::RoleNotFound)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
 
+// Caches each user's `tokens_valid_after` in front of the user store, so that validating a
+// token does not need a database round trip. Users who never revoked their tokens are cached
+// as `DateTime::UNIX_EPOCH`.
+#[async_trait::async_trait]
+pub trait TokensValidAfterCache {
+    async fn get(&self, email: &Email) -> Result<Option<DateTime<Utc>>>;
+
+    // Caches a value read from the user store, unless a newer one was set in the meantime
+    async fn fill(&self, email: &Email, valid_after: DateTime<Utc>) -> Result<()>;
+
+    async fn set(&self, email: &Email, valid_after: DateTime<Utc>) -> Result<()>;
+}
+
+// Tokens are banned by their `jti` until they expire, the token itself is never stored.
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: &Secret<String>) -> Result<(), BannedTokenStoreError>;
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError>;
 
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -66,22 +157,63 @@ impl PartialEq for BannedTokenStoreError {
     }
 }
 
-// This trait represents the interface all concrete 2FA code stores should implement
+// This trait represents the interface all concrete 2FA code stores should implement.
+// Pending logins are keyed by their attempt ID, so one user can have several at once.
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        attempt: TwoFAAttempt,
     ) -> Result<(), TwoFACodeStoreError>;
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError>;
 
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError>;
+
+    // Removes the attempt and returns it, in one step so that of concurrent calls for the
+    // same attempt only one gets it. `None` if there was no live attempt to remove.
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError>;
+
+    // Counts a wrong code against the attempt and removes the attempt once
+    // `max_failed_codes` have been entered, returning whether it was removed. Adding a code
+    // under the same ID starts the count over; missing attempts are left alone.
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError>;
+}
+
+// A login waiting for its 2FA code
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoFAAttempt {
+    pub email: Email,
+    pub code: TwoFACode,
+    pub created_at: DateTime<Utc>,
+    // `ClientInfo::fingerprint` of the client that started the login
+    pub fingerprint: String,
+}
+
+impl TwoFAAttempt {
+    pub fn new(email: Email, code: TwoFACode, fingerprint: String) -> Self {
+        Self {
+            email,
+            code,
+            // Microseconds are the finest precision every store can keep
+            created_at: Utc::now().trunc_subsecs(6),
+            fingerprint,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -107,7 +239,15 @@ pub struct LoginAttemptId(Secret<String>);
 
 impl PartialEq for LoginAttemptId {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        constant_time_eq(self.0.expose_secret(), other.0.expose_secret())
+    }
+}
+
+impl Eq for LoginAttemptId {}
+
+impl Hash for LoginAttemptId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.expose_secret().hash(state);
     }
 }
 
@@ -136,7 +276,7 @@ pub struct TwoFACode(Secret<String>);
 
 impl PartialEq for TwoFACode {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        constant_time_eq(self.0.expose_secret(), other.0.expose_secret())
     }
 }
 
@@ -1,10 +1,50 @@
-use color_eyre::eyre::Result;
+use std::fmt;
 
-use crate::domain::Email;
+use color_eyre::eyre::{Report, Result};
+
+use crate::domain::{Email, PendingEmail};
+
+// A rendered email, ready to be handed to an email client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailContent {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
 
 // This trait represents the email client interface all
 // concrete implementations must adhere to.
 #[async_trait::async_trait]
 pub trait EmailClient {
-    async fn send_email(&self, to: &Email, subject: &str, body: &str) -> Result<()>;
+    async fn send_email(&self, to: &Email, content: &EmailContent) -> Result<()>;
+
+    // Sends emails claimed from the outbox, returning one result per email, in order.
+    // Clients with a batch API override this to send them in fewer requests.
+    async fn send_emails(&self, emails: &[PendingEmail]) -> Vec<Result<()>>
+    where
+        Self: Sync,
+    {
+        let mut results = Vec::with_capacity(emails.len());
+        for email in emails {
+            results.push(self.send_email(&email.recipient, &email.content).await);
+        }
+        results
+    }
+}
+
+// Context email clients attach to failures that retrying cannot fix, such as an invalid or
+// inactive recipient: `Err(report.wrap_err(PermanentEmailFailure))`.
+#[derive(Debug, Clone, Copy)]
+pub struct PermanentEmailFailure;
+
+impl fmt::Display for PermanentEmailFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Email cannot be delivered")
+    }
+}
+
+impl PermanentEmailFailure {
+    pub fn is_permanent(error: &Report) -> bool {
+        error.downcast_ref::<PermanentEmailFailure>().is_some()
+    }
 }
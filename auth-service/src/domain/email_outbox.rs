@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{Email, EmailContent};
+
+// An email waiting to be enqueued. Emails sharing an idempotency key are only sent once.
+#[derive(Debug, Clone)]
+pub struct OutboxEmail {
+    pub idempotency_key: String,
+    pub recipient: Email,
+    pub content: EmailContent,
+    // Groups emails of one kind in the provider's statistics, e.g. "2fa-code"
+    pub tag: Option<String>,
+}
+
+// An email claimed by a worker for delivery
+#[derive(Debug, Clone)]
+pub struct PendingEmail {
+    pub id: Uuid,
+    pub recipient: Email,
+    pub content: EmailContent,
+    pub tag: Option<String>,
+    // Delivery attempts so far, including the current one
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    Pending,
+    Sent,
+    // Gave up after too many failed attempts
+    Dead,
+}
+
+impl DeliveryState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sent => "sent",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryState {
+    type Err = EmailOutboxError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "sent" => Ok(Self::Sent),
+            "dead" => Ok(Self::Dead),
+            other => Err(EmailOutboxError::UnexpectedError(Report::msg(format!(
+                "Unknown delivery state: {other}"
+            )))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryStatus {
+    pub id: Uuid,
+    pub idempotency_key: String,
+    pub state: DeliveryState,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+// This trait represents the durable queue emails go through before delivery.
+#[async_trait::async_trait]
+pub trait EmailOutbox {
+    // Returns false if an email with the same idempotency key was already enqueued
+    async fn enqueue(&self, email: OutboxEmail) -> Result<bool, EmailOutboxError>;
+
+    async fn status(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<Option<DeliveryStatus>, EmailOutboxError>;
+
+    // Claims up to `limit` emails that are due. Claimed emails are hidden from other
+    // workers for `lease`, after which they are retried if never marked sent or failed.
+    async fn claim_due(
+        &self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<PendingEmail>, EmailOutboxError>;
+
+    async fn mark_sent(&self, id: Uuid) -> Result<(), EmailOutboxError>;
+
+    // Schedules another attempt at `retry_at`, or dead-letters the email when `None`
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxError>;
+}
+
+#[derive(Debug, Error)]
+pub enum EmailOutboxError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
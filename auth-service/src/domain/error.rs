@@ -1,3 +1,4 @@
+use auth_middleware::AuthError;
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
@@ -13,6 +14,24 @@ pub enum AuthAPIError {
     MissingToken,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Account disabled")]
+    AccountDisabled,
+    #[error("Account locked")]
+    AccountLocked,
+    #[error("Forbidden")]
+    Forbidden,
+    #[error("User not found")]
+    UserNotFound,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
+
+impl From<AuthError> for AuthAPIError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::MissingToken => AuthAPIError::MissingToken,
+            AuthError::InvalidToken => AuthAPIError::InvalidToken,
+            e @ AuthError::Unexpected(_) => AuthAPIError::UnexpectedError(e.into()),
+        }
+    }
+}
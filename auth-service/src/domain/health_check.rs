@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+use color_eyre::eyre::Result;
+
+// This trait represents a dependency the service needs in order to handle requests.
+// Implementations should give up once `timeout` has elapsed.
+#[async_trait::async_trait]
+pub trait HealthCheck {
+    fn name(&self) -> &'static str;
+
+    async fn check(&self, timeout: Duration) -> Result<()>;
+}
@@ -1,13 +1,25 @@
+mod audit;
 mod data_stores;
 mod email;
 mod email_client;
+mod email_outbox;
 mod error;
+mod health_check;
 mod password;
+mod phone_number;
+mod role;
+mod sms_client;
 mod user;
 
+pub use audit::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
+pub use email_outbox::*;
 pub use error::*;
+pub use health_check::*;
 pub use password::*;
+pub use phone_number::*;
+pub use role::*;
+pub use sms_client::*;
 pub use user::*;
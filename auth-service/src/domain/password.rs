@@ -1,6 +1,8 @@
 use color_eyre::eyre::{eyre, Result};
 use secrecy::{ExposeSecret, Secret};
 
+use crate::utils::constant_time_eq;
+
 #[derive(Debug, Clone)]
 pub struct Password(Secret<String>);
 
@@ -12,7 +14,7 @@ impl AsRef<Secret<String>> for Password {
 
 impl PartialEq for Password {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        constant_time_eq(self.0.expose_secret(), other.0.expose_secret())
     }
 }
 
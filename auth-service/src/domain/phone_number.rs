@@ -0,0 +1,93 @@
+use std::hash::{Hash, Hasher};
+
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, Secret};
+
+// Longest number allowed by E.164, country code included
+const MAX_DIGITS: usize = 15;
+
+// A phone number in E.164 format, e.g. "+14155550123"
+#[derive(Debug, Clone)]
+pub struct PhoneNumber(Secret<String>);
+
+impl AsRef<Secret<String>> for PhoneNumber {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+impl PartialEq for PhoneNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl Hash for PhoneNumber {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.expose_secret().hash(state);
+    }
+}
+
+impl Eq for PhoneNumber {}
+
+impl PhoneNumber {
+    pub fn parse(phone_number: &str) -> Result<Self> {
+        let digits = phone_number
+            .strip_prefix('+')
+            .ok_or(eyre!("Phone number must start with +"))?;
+
+        let valid = (2..=MAX_DIGITS).contains(&digits.len())
+            && digits.chars().all(|c| c.is_ascii_digit())
+            && !digits.starts_with('0');
+
+        if valid {
+            Ok(Self(Secret::new(phone_number.to_string())))
+        } else {
+            Err(eyre!("Invalid phone number format"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_phone_number() {
+        let valid_numbers = vec!["+14155550123", "+447911123456", "+33612345678", "+12"];
+
+        for number in valid_numbers {
+            let result = PhoneNumber::parse(number);
+            assert!(
+                result.is_ok(),
+                "Expected {} to be valid, but got error: {:?}",
+                number,
+                result
+            );
+            assert_eq!(result.unwrap().as_ref().expose_secret(), number);
+        }
+    }
+
+    #[test]
+    fn test_invalid_phone_number() {
+        let invalid_numbers = vec![
+            "",
+            "+",
+            "+1",
+            "14155550123",
+            "+04155550123",
+            "+1 415 555 0123",
+            "+1-415-555-0123",
+            "+1415555012345678",
+            "+1415abc0123",
+        ];
+
+        for number in invalid_numbers {
+            assert!(
+                PhoneNumber::parse(number).is_err(),
+                "Expected {} to be invalid, but it was accepted",
+                number
+            );
+        }
+    }
+}
@@ -0,0 +1,31 @@
+// Grants every permission below
+pub const ADMIN_ROLE: &str = "admin";
+
+// Permissions are named `<resource>:<action>`
+pub const USERS_READ_PERMISSION: &str = "users:read";
+pub const USERS_WRITE_PERMISSION: &str = "users:write";
+
+// A named set of permissions. Roles are defined in the `roles` and `role_permissions` tables
+// and assigned to users through `UserStore::assign_role`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Vec<String>,
+}
+
+impl Role {
+    // The role the migrations seed, which the in-memory user store starts with too
+    pub fn admin() -> Self {
+        Self {
+            name: ADMIN_ROLE.to_owned(),
+            permissions: vec![
+                USERS_READ_PERMISSION.to_owned(),
+                USERS_WRITE_PERMISSION.to_owned(),
+            ],
+        }
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
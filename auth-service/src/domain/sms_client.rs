@@ -0,0 +1,10 @@
+use color_eyre::eyre::Result;
+
+use crate::domain::PhoneNumber;
+
+// This trait represents the SMS client interface all
+// concrete implementations must adhere to.
+#[async_trait::async_trait]
+pub trait SmsClient {
+    async fn send_sms(&self, to: &PhoneNumber, body: &str) -> Result<()>;
+}
@@ -1,12 +1,57 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Report, Result};
 use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{Email, Password, PhoneNumber, Role};
 
-use crate::domain::{Email, Password};
+// Where a user receives their 2FA codes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAChannel {
+    #[default]
+    Email,
+    Sms,
+}
+
+impl TwoFAChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Sms => "sms",
+        }
+    }
+}
+
+impl FromStr for TwoFAChannel {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "email" => Ok(Self::Email),
+            "sms" => Ok(Self::Sms),
+            other => Err(eyre!("Unknown 2FA channel: {other}")),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct User {
     email: Email,
     password: Password,
     requires_2fa: bool,
+    phone_number: Option<PhoneNumber>,
+    two_fa_channel: TwoFAChannel,
+    // Tokens issued before this time are rejected, see `UserStore::set_tokens_valid_after`
+    tokens_valid_after: Option<DateTime<Utc>>,
+    // Disabled users can't log in
+    disabled: bool,
+    roles: Vec<Role>,
+    // Consecutive failed logins since the last successful one or lockout
+    failed_login_attempts: u32,
+    locked_until: Option<DateTime<Utc>>,
 }
 
 impl User {
@@ -15,7 +60,58 @@ impl User {
             email,
             password,
             requires_2fa,
+            phone_number: None,
+            two_fa_channel: TwoFAChannel::Email,
+            tokens_valid_after: None,
+            disabled: false,
+            roles: Vec::new(),
+            failed_login_attempts: 0,
+            locked_until: None,
+        }
+    }
+
+    pub fn with_phone_number(mut self, phone_number: PhoneNumber) -> Self {
+        self.phone_number = Some(phone_number);
+        self
+    }
+
+    // Codes can only go by SMS to users with a phone number
+    pub fn with_two_fa_channel(mut self, channel: TwoFAChannel) -> Result<Self> {
+        if channel == TwoFAChannel::Sms && self.phone_number.is_none() {
+            return Err(eyre!("The SMS 2FA channel requires a phone number"));
         }
+        self.two_fa_channel = channel;
+        Ok(self)
+    }
+
+    pub fn with_tokens_valid_after(mut self, valid_after: DateTime<Utc>) -> Self {
+        self.tokens_valid_after = Some(valid_after);
+        self
+    }
+
+    pub fn with_requires_2fa(mut self, requires_2fa: bool) -> Self {
+        self.requires_2fa = requires_2fa;
+        self
+    }
+
+    pub fn with_disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    pub fn with_roles(mut self, roles: Vec<Role>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    pub fn with_lockout(
+        mut self,
+        failed_login_attempts: u32,
+        locked_until: Option<DateTime<Utc>>,
+    ) -> Self {
+        self.failed_login_attempts = failed_login_attempts;
+        self.locked_until = locked_until;
+        self
     }
 
     pub fn email(&self) -> &Secret<String> {
@@ -29,4 +125,123 @@ impl User {
     pub fn requires_2fa(&self) -> bool {
         self.requires_2fa
     }
+
+    pub fn phone_number(&self) -> Option<&PhoneNumber> {
+        self.phone_number.as_ref()
+    }
+
+    pub fn two_fa_channel(&self) -> TwoFAChannel {
+        self.two_fa_channel
+    }
+
+    pub fn tokens_valid_after(&self) -> Option<DateTime<Utc>> {
+        self.tokens_valid_after
+    }
+
+    pub fn disabled(&self) -> bool {
+        self.disabled
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    // The names of the user's roles, as embedded in their tokens
+    pub fn role_names(&self) -> Vec<String> {
+        self.roles.iter().map(|role| role.name.clone()).collect()
+    }
+
+    // Every permission granted by the user's roles, sorted and without duplicates
+    pub fn permissions(&self) -> Vec<String> {
+        let mut permissions: Vec<_> = self
+            .roles
+            .iter()
+            .flat_map(|role| role.permissions.iter().cloned())
+            .collect();
+        permissions.sort();
+        permissions.dedup();
+        permissions
+    }
+
+    pub fn failed_login_attempts(&self) -> u32 {
+        self.failed_login_attempts
+    }
+
+    pub fn locked_until(&self) -> Option<DateTime<Utc>> {
+        self.locked_until
+    }
+
+    pub fn is_locked_at(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until
+            .is_some_and(|locked_until| locked_until > now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{USERS_READ_PERMISSION, USERS_WRITE_PERMISSION};
+
+    fn user() -> User {
+        User::new(
+            Email::parse("test@example.com").unwrap(),
+            Password::parse(&Secret::new("password123!".to_owned())).unwrap(),
+            true,
+        )
+    }
+
+    #[test]
+    fn test_defaults_to_email_channel() {
+        let user = user();
+
+        assert_eq!(user.two_fa_channel(), TwoFAChannel::Email);
+        assert_eq!(user.phone_number(), None);
+    }
+
+    #[test]
+    fn test_sms_channel_requires_phone_number() {
+        assert!(user().with_two_fa_channel(TwoFAChannel::Sms).is_err());
+
+        let user = user()
+            .with_phone_number(PhoneNumber::parse("+14155550123").unwrap())
+            .with_two_fa_channel(TwoFAChannel::Sms)
+            .unwrap();
+        assert_eq!(user.two_fa_channel(), TwoFAChannel::Sms);
+    }
+
+    #[test]
+    fn test_permissions_are_merged_across_roles() {
+        assert!(user().roles().is_empty());
+        assert!(user().permissions().is_empty());
+
+        let auditor = Role {
+            name: "auditor".to_owned(),
+            permissions: vec!["audit:read".to_owned(), USERS_READ_PERMISSION.to_owned()],
+        };
+        let user = user().with_roles(vec![Role::admin(), auditor]);
+
+        assert_eq!(user.role_names(), ["admin", "auditor"]);
+        assert_eq!(
+            user.permissions(),
+            ["audit:read", USERS_READ_PERMISSION, USERS_WRITE_PERMISSION]
+        );
+    }
+
+    #[test]
+    fn test_is_locked_until_locked_until_passes() {
+        let now = Utc::now();
+        assert!(!user().is_locked_at(now));
+
+        let user = user().with_lockout(0, Some(now + chrono::Duration::minutes(15)));
+        assert!(user.is_locked_at(now));
+        assert!(!user.is_locked_at(now + chrono::Duration::minutes(15)));
+    }
+
+    #[test]
+    fn test_channel_round_trips_through_str() {
+        for channel in [TwoFAChannel::Email, TwoFAChannel::Sms] {
+            assert_eq!(channel.as_str().parse::<TwoFAChannel>().unwrap(), channel);
+        }
+        assert!("pigeon".parse::<TwoFAChannel>().is_err());
+    }
 }
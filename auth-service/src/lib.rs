@@ -1,72 +1,194 @@
 use axum::{
-    http::{Method, StatusCode},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    http::StatusCode,
+    middleware::{self, AddExtension},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post},
     serve::Serve,
     Json, Router,
 };
-use redis::{Client, RedisResult};
+use redis::{aio::ConnectionManager, Client, RedisResult};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
-use std::error::Error;
-use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    PgPool, SqlitePool,
+};
+use std::{
+    error::Error,
+    future::{Future, IntoFuture},
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Notify;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
 
 use crate::{
     app_state::AppState,
     domain::AuthAPIError,
-    utils::{make_span_with_request_id, on_request, on_response},
+    settings::ApplicationSettings,
+    utils::{
+        create_cors_layer, init_metrics, make_span_with_request_id, on_request, on_response,
+        propagate_request_labels, shutdown_signal,
+    },
 };
 
 pub mod app_state;
 pub mod domain;
 pub mod routes;
 pub mod services;
+pub mod settings;
 pub mod utils;
 
+type ConnectInfoService = IntoMakeServiceWithConnectInfo<Router, SocketAddr>;
+
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<ConnectInfoService, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     pub address: String,
+    metrics_server: Serve<Router, Router>,
+    pub metrics_address: String,
+    drain_timeout: Duration,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            "https://lgr.ddrcode.me".parse()?,
-        ];
-
-        let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-            .allow_credentials(true)
-            .allow_origin(allowed_origins);
+    pub async fn build(
+        app_state: AppState,
+        settings: &ApplicationSettings,
+    ) -> Result<Self, Box<dyn Error>> {
+        let cors = create_cors_layer(&settings.allowed_origins)?;
+        init_metrics()?;
 
         let router = Router::new()
-            .nest_service("/", ServeDir::new("assets"))
+            .nest_service("/", ServeDir::new(&settings.assets_dir))
             .route("/signup", post(routes::signup))
             .route("/login", post(routes::login))
             .route("/logout", post(routes::logout))
+            .route("/logout-all", post(routes::logout_all))
             .route("/verify-2fa", post(routes::verify_2fa))
             .route("/verify-token", post(routes::verify_token))
+            .route("/health/live", get(routes::health_live))
+            .route("/health/ready", get(routes::health_ready))
+            .route("/admin/users", get(routes::admin_list_users))
+            .route(
+                "/admin/users/:email",
+                get(routes::admin_get_user).delete(routes::admin_delete_user),
+            )
+            .route(
+                "/admin/users/:email/disable",
+                post(routes::admin_disable_user),
+            )
+            .route(
+                "/admin/users/:email/enable",
+                post(routes::admin_enable_user),
+            )
+            .route(
+                "/admin/users/:email/require-2fa",
+                post(routes::admin_require_2fa),
+            )
+            .route(
+                "/admin/users/:email/reset-lockout",
+                post(routes::admin_reset_lockout),
+            )
+            .route(
+                "/admin/users/:email/revoke-sessions",
+                post(routes::admin_revoke_sessions),
+            );
+
+        #[cfg(feature = "dev-mailbox")]
+        let router = router.route("/dev/mailbox", get(routes::dev_mailbox));
+
+        let router = router
             .with_state(app_state)
+            .route_layer(middleware::from_fn(propagate_request_labels))
             .layer(cors)
+            .layer(PropagateRequestIdLayer::x_request_id())
             .layer(
                 TraceLayer::new_for_http()
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
                     .on_response(on_response),
-            );
+            )
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
 
-        let listener = tokio::net::TcpListener::bind(address).await?;
+        let listener = tokio::net::TcpListener::bind(settings.address()).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Peer addresses are needed for the client IP recorded in audit events
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
-        Ok(Self { server, address })
+        // Metrics stay off the public listener, which browsers and nginx can reach
+        let metrics_router = Router::new().route("/metrics", get(routes::metrics));
+        let metrics_listener = tokio::net::TcpListener::bind(settings.metrics_address()).await?;
+        let metrics_address = metrics_listener.local_addr()?.to_string();
+        let metrics_server = axum::serve(metrics_listener, metrics_router);
+
+        Ok(Self {
+            server,
+            address,
+            metrics_server,
+            metrics_address,
+            drain_timeout: settings.drain_timeout(),
+        })
     }
 
+    // Serves requests until SIGINT or SIGTERM
     pub async fn run(self) -> Result<(), std::io::Error> {
+        self.run_until(shutdown_signal()).await
+    }
+
+    // Serves requests until `shutdown` resolves, then stops accepting connections and
+    // waits up to the drain timeout for in-flight requests to complete.
+    pub async fn run_until(
+        self,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<(), std::io::Error> {
         tracing::info!("listening on {}", &self.address);
-        self.server.await
+        tracing::info!("serving metrics on {}", &self.metrics_address);
+
+        // Scrapes are cheap to retry, so the metrics listener just stops with the server
+        let metrics_server = tokio::spawn(self.metrics_server.into_future());
+
+        let draining = Arc::new(Notify::new());
+        let server = self
+            .server
+            .with_graceful_shutdown({
+                let draining = draining.clone();
+                async move {
+                    shutdown.await;
+                    tracing::info!("Shutting down, draining in-flight requests");
+                    draining.notify_one();
+                }
+            })
+            .into_future();
+
+        let drain_timeout = self.drain_timeout;
+        let result = tokio::select! {
+            result = server => {
+                tracing::info!("All connections drained");
+                result
+            }
+            _ = async {
+                draining.notified().await;
+                tokio::time::sleep(drain_timeout).await;
+            } => {
+                tracing::warn!(
+                    "Drain timeout of {:?} elapsed, stopping with requests still in flight",
+                    drain_timeout
+                );
+                Ok(())
+            }
+        };
+
+        metrics_server.abort();
+        result
     }
 }
 
@@ -87,6 +209,12 @@ impl IntoResponse for AuthAPIError {
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing token"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid token"),
+            AuthAPIError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+            // Answered like unknown emails, so locks don't reveal which emails are registered.
+            // The audit log still records the lock
+            AuthAPIError::AccountLocked => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
+            AuthAPIError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
+            AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
             AuthAPIError::UnexpectedError(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
             }
@@ -106,21 +234,50 @@ pub async fn get_postgres_pool(url: &Secret<String>) -> Result<PgPool, sqlx::Err
         .await
 }
 
+// Opens the SQLite database file at `path`, creating it when it does not exist yet
+pub async fn get_sqlite_pool(path: &str) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        // Readers are not blocked by a write in progress
+        .journal_mode(SqliteJournalMode::Wal)
+        // Writers wait for each other instead of failing straight away
+        .busy_timeout(Duration::from_secs(5));
+
+    SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+}
+
 pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     let redis_url = format!("redis://{redis_hostname}/");
     Client::open(redis_url)
 }
 
+// The connection shared by every Redis-backed store. Commands from concurrent requests are
+// multiplexed over it rather than waiting for each other, and it reconnects if dropped.
+// Clones are cheap and share the connection.
+pub async fn get_redis_connection(redis_hostname: String) -> RedisResult<ConnectionManager> {
+    get_redis_client(redis_hostname)?
+        .get_connection_manager()
+        .await
+}
+
+// Logs the error together with its chain of causes as structured fields, so the whole
+// chain stays in a single event (and a single JSON line in JSON log mode).
 fn log_error_chain(e: &(dyn Error + 'static)) {
-    let separator =
-        "\n-----------------------------------------------------------------------------------\n";
-    let mut report = format!("{separator}{e:?}\n");
+    let mut causes = Vec::new();
     let mut current = e.source();
     while let Some(cause) = current {
-        let str = format!("Caused by:\n\n{cause:?}");
-        report = format!("{report}\n{str}");
+        causes.push(cause.to_string());
         current = cause.source();
     }
-    report = format!("{report}\n{separator}");
-    tracing::error!("{}", report);
+
+    tracing::error!(
+        error.message = %e,
+        error.causes = ?causes,
+        error.details = ?e,
+        "Request failed"
+    );
 }
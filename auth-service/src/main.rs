@@ -1,49 +1,292 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use auth_service::{
-    app_state::AppState,
-    domain::Email,
-    get_postgres_pool, get_redis_client,
+    app_state::{
+        AppState, AuditSinkType, BannedTokenStoreType, EmailClientType, EmailOutboxType,
+        HealthCheckType, TokensValidAfterCacheType, TwoFACodeStoreType, UserStoreType,
+    },
+    get_postgres_pool, get_redis_connection, get_sqlite_pool,
     services::{
-        data_stores::{PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
-        PostmarkEmailClient,
+        audit_sinks::{FileAuditSink, PostgresAuditSink},
+        data_stores::{
+            HashMapTwoFACodeStore, HashSetBannedTokenStore, MeteredBannedTokenStore,
+            MeteredTwoFACodeStore, MeteredUserStore, PostgresBannedTokenStore, PostgresEmailOutbox,
+            PostgresTwoFACodeStore, PostgresUserStore, RedisBannedTokenStore,
+            RedisTokensValidAfterCache, RedisTwoFACodeStore, SqliteBannedTokenStore,
+            SqliteEmailOutbox, SqliteTwoFACodeStore, SqliteUserStore,
+        },
+        CapturingEmailClient, EmailOutboxWorker, HttpSmsClient, MeteredEmailClient,
+        PostgresHealthCheck, PostmarkEmailClient, RedisHealthCheck, SmtpEmailClient,
+        SqliteHealthCheck,
+    },
+    settings::{
+        AuditSettings, AuditSinkKind, CacheBackend, DatabaseSettings, EmailClientSettings,
+        EmailProvider, RedisSettings, Settings, SmsClientSettings, SqliteSettings, StoreBackend,
     },
-    utils::{init_tracing, prod, DB_URL, POSTMARK_AUTH_TOKEN, REDIS_HOST_NAME},
+    utils::{init_tracing, spawn_pool_metrics_task},
     Application,
 };
+use color_eyre::eyre::{Context, Result};
+use redis::aio::ConnectionManager;
 use reqwest::Client;
-use sqlx::PgPool;
-use tokio::sync::RwLock;
+use sqlx::{PgPool, SqlitePool};
+use tokio::sync::{Notify, RwLock};
+
+// How often Postgres connection pool usage is sampled for the metrics endpoint
+const POOL_METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+// Connections to the backends used by the configured stores and audit sink
+#[derive(Clone)]
+struct Backends {
+    postgres: Option<PgPool>,
+    sqlite: Option<SqlitePool>,
+    redis: Option<ConnectionManager>,
+}
+
+impl Backends {
+    async fn connect(settings: &Settings) -> Result<Self> {
+        let postgres = match settings.uses_postgres() {
+            true => Some(configure_postgresql(&settings.database).await),
+            false => None,
+        };
+        let sqlite = match &settings.sqlite {
+            Some(sqlite) if settings.stores.uses_sqlite() => Some(configure_sqlite(sqlite).await?),
+            _ => None,
+        };
+        let redis = match settings.stores.uses_redis() {
+            true => Some(configure_redis(&settings.redis).await),
+            false => None,
+        };
+
+        Ok(Self {
+            postgres,
+            sqlite,
+            redis,
+        })
+    }
+
+    fn postgres(&self) -> PgPool {
+        self.postgres
+            .clone()
+            .expect("PostgreSQL is connected when a store uses it")
+    }
+
+    fn sqlite(&self) -> SqlitePool {
+        self.sqlite
+            .clone()
+            .expect("SQLite is opened when a store uses it")
+    }
+
+    fn redis(&self) -> ConnectionManager {
+        self.redis
+            .clone()
+            .expect("Redis is connected when a store uses it")
+    }
+
+    fn health_checks(&self) -> Vec<HealthCheckType> {
+        let mut health_checks: Vec<HealthCheckType> = Vec::new();
+        if let Some(pg_pool) = &self.postgres {
+            health_checks.push(Arc::new(PostgresHealthCheck::new(pg_pool.clone())));
+        }
+        if let Some(sqlite_pool) = &self.sqlite {
+            health_checks.push(Arc::new(SqliteHealthCheck::new(sqlite_pool.clone())));
+        }
+        if let Some(redis_conn) = &self.redis {
+            health_checks.push(Arc::new(RedisHealthCheck::new(redis_conn.clone())));
+        }
+        health_checks
+    }
+
+    async fn close(self) {
+        if let Some(pg_pool) = self.postgres {
+            pg_pool.close().await;
+            tracing::info!("Closed PostgreSQL connections");
+        }
+        if let Some(sqlite_pool) = self.sqlite {
+            sqlite_pool.close().await;
+            tracing::info!("Closed SQLite connections");
+        }
+        // The stores share the connection, it closes once the last of them is dropped
+        if let Some(redis_conn) = self.redis {
+            drop(redis_conn);
+            tracing::info!("Released Redis connection");
+        }
+    }
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<()> {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Failed to initialize tracing");
 
-    let pg_pool = configure_postgresql().await;
-    let redis_conn = Arc::new(RwLock::new(configure_redis()));
+    let settings = Settings::load().wrap_err("Failed to load settings")?;
+    init_tracing(&settings.tracing).expect("Failed to initialize tracing");
+
+    // Only the backends used by the configured stores are connected to
+    let backends = Backends::connect(&settings).await?;
+    let health_checks = backends.health_checks();
 
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-    let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_conn.clone())));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_conn.clone())));
-    let email_client = Arc::new(RwLock::new(configure_postmark_email_client()));
-    let app_state = AppState::new(
+    if let Some(pg_pool) = &backends.postgres {
+        spawn_pool_metrics_task(pg_pool.clone(), POOL_METRICS_INTERVAL);
+    }
+
+    let user_store = configure_user_store(&settings, &backends);
+    let banned_token_store = configure_banned_token_store(&settings, &backends);
+    let two_fa_code_store = configure_two_fa_code_store(&settings, &backends);
+    let (email_client, dev_mailbox) = configure_email_client(&settings.email_client)?;
+    let email_outbox = configure_email_outbox(&settings, &backends);
+    let tokens_valid_after_cache = configure_tokens_valid_after_cache(&settings, &backends);
+
+    let audit_sink = configure_audit_sink(&settings.audit, &backends).await?;
+
+    let settings = Arc::new(settings);
+    let mut app_state = AppState::new(
+        settings.clone(),
         user_store,
         banned_token_store,
         two_fa_code_store,
-        email_client,
+        email_client.clone(),
+        email_outbox.clone(),
+        health_checks,
+        audit_sink,
     );
+    if let Some(cache) = tokens_valid_after_cache {
+        app_state = app_state.with_tokens_valid_after_cache(cache);
+    }
+    if let Some(sms_settings) = &settings.sms_client {
+        app_state = app_state.with_sms_client(Arc::new(configure_sms_client(sms_settings)?));
+    }
+    if let Some(mailbox) = dev_mailbox {
+        app_state = app_state.with_dev_mailbox(mailbox);
+    }
 
-    let app = Application::build(app_state, prod::APP_ADDRESS)
+    let app = Application::build(app_state, &settings.application)
         .await
         .expect("Failed to build application");
 
+    // Emails are delivered in the background, the worker keeps running until the server has
+    // drained so that codes queued by in-flight logins still go out.
+    let worker_shutdown = Arc::new(Notify::new());
+    let worker = EmailOutboxWorker::new(email_outbox, email_client, &settings.email_outbox);
+    let worker = tokio::spawn({
+        let worker_shutdown = worker_shutdown.clone();
+        worker.run_until(async move { worker_shutdown.notified().await })
+    });
+
     app.run().await.expect("Failed to run application");
+
+    worker_shutdown.notify_one();
+    if let Err(e) = worker.await {
+        tracing::error!(error = ?e, "Email outbox worker panicked");
+    }
+
+    // The server has stopped and drained, release connections in an orderly way
+    backends.close().await;
+    opentelemetry::global::shutdown_tracer_provider();
+
+    Ok(())
 }
 
-async fn configure_postgresql() -> PgPool {
+// Builds the user store selected in settings
+fn configure_user_store(settings: &Settings, backends: &Backends) -> UserStoreType {
+    match settings.stores.users {
+        StoreBackend::Postgres => Arc::new(MeteredUserStore::new(
+            PostgresUserStore::new(backends.postgres()),
+            "postgres_user",
+        )),
+        StoreBackend::Sqlite => Arc::new(MeteredUserStore::new(
+            SqliteUserStore::new(backends.sqlite()),
+            "sqlite_user",
+        )),
+        StoreBackend::Redis | StoreBackend::Memory => {
+            unreachable!("User store backend is validated on load")
+        }
+    }
+}
+
+// Builds the banned token store selected in settings and starts its sweeper if it has one
+fn configure_banned_token_store(settings: &Settings, backends: &Backends) -> BannedTokenStoreType {
+    let sweep_interval = settings.stores.sweep_interval();
+
+    match settings.stores.banned_tokens {
+        StoreBackend::Redis => Arc::new(MeteredBannedTokenStore::new(
+            RedisBannedTokenStore::new(backends.redis()),
+            "redis_banned_token",
+        )),
+        StoreBackend::Postgres => {
+            let store = PostgresBannedTokenStore::new(backends.postgres());
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredBannedTokenStore::new(store, "postgres_banned_token"))
+        }
+        StoreBackend::Sqlite => {
+            let store = SqliteBannedTokenStore::new(backends.sqlite());
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredBannedTokenStore::new(store, "sqlite_banned_token"))
+        }
+        StoreBackend::Memory => {
+            let store = HashSetBannedTokenStore::default();
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredBannedTokenStore::new(store, "memory_banned_token"))
+        }
+    }
+}
+
+// Builds the 2FA code store selected in settings and starts its sweeper if it has one
+fn configure_two_fa_code_store(settings: &Settings, backends: &Backends) -> TwoFACodeStoreType {
+    let ttl = Duration::from_secs(settings.two_fa.code_ttl_seconds);
+    let sweep_interval = settings.stores.sweep_interval();
+
+    match settings.stores.two_fa_codes {
+        StoreBackend::Redis => Arc::new(MeteredTwoFACodeStore::new(
+            RedisTwoFACodeStore::new(backends.redis(), ttl.as_secs()),
+            "redis_two_fa_code",
+        )),
+        StoreBackend::Postgres => {
+            let store = PostgresTwoFACodeStore::new(backends.postgres(), ttl);
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredTwoFACodeStore::new(store, "postgres_two_fa_code"))
+        }
+        StoreBackend::Sqlite => {
+            let store = SqliteTwoFACodeStore::new(backends.sqlite(), ttl);
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredTwoFACodeStore::new(store, "sqlite_two_fa_code"))
+        }
+        StoreBackend::Memory => {
+            let store = HashMapTwoFACodeStore::new(ttl);
+            store.spawn_sweeper(sweep_interval);
+            Arc::new(MeteredTwoFACodeStore::new(store, "memory_two_fa_code"))
+        }
+    }
+}
+
+// Builds the email outbox selected in settings
+fn configure_email_outbox(settings: &Settings, backends: &Backends) -> EmailOutboxType {
+    match settings.stores.email_outbox {
+        StoreBackend::Postgres => Arc::new(PostgresEmailOutbox::new(backends.postgres())),
+        StoreBackend::Sqlite => Arc::new(SqliteEmailOutbox::new(backends.sqlite())),
+        StoreBackend::Redis | StoreBackend::Memory => {
+            unreachable!("Email outbox backend is validated on load")
+        }
+    }
+}
+
+// Builds the tokens_valid_after cache selected in settings, if any. Entries live as long as a
+// token, after which they are read from the user store again.
+fn configure_tokens_valid_after_cache(
+    settings: &Settings,
+    backends: &Backends,
+) -> Option<TokensValidAfterCacheType> {
+    match settings.stores.tokens_valid_after_cache {
+        CacheBackend::Redis => Some(Arc::new(RedisTokensValidAfterCache::new(
+            backends.redis(),
+            settings.auth.token_ttl_seconds as u64,
+        ))),
+        CacheBackend::None => None,
+    }
+}
+
+async fn configure_postgresql(settings: &DatabaseSettings) -> PgPool {
     // Create a new database connection pool
-    let pg_pool = get_postgres_pool(&DB_URL)
+    let pg_pool = get_postgres_pool(&settings.url)
         .await
         .expect("Failed to create PostgreSQL connection pool");
 
@@ -56,23 +299,105 @@ async fn configure_postgresql() -> PgPool {
     pg_pool
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+async fn configure_sqlite(settings: &SqliteSettings) -> Result<SqlitePool> {
+    let sqlite_pool = get_sqlite_pool(&settings.path)
+        .await
+        .wrap_err_with(|| format!("Failed to open SQLite database {}", settings.path))?;
+
+    sqlx::migrate!("./migrations/sqlite")
+        .run(&sqlite_pool)
+        .await
+        .wrap_err("Failed to run SQLite migrations")?;
+
+    Ok(sqlite_pool)
+}
+
+async fn configure_audit_sink(
+    settings: &AuditSettings,
+    backends: &Backends,
+) -> Result<AuditSinkType> {
+    let audit_sink: AuditSinkType = match settings.sink {
+        AuditSinkKind::Postgres => Arc::new(PostgresAuditSink::new(backends.postgres())),
+        AuditSinkKind::File => {
+            let path = settings
+                .file_path
+                .as_deref()
+                .expect("File path is validated on load");
+            Arc::new(FileAuditSink::open(path).await?)
+        }
+    };
+
+    Ok(audit_sink)
+}
+
+async fn configure_redis(settings: &RedisSettings) -> ConnectionManager {
+    get_redis_connection(settings.host_name.to_owned())
+        .await
         .expect("Failed to get Redis connection")
 }
 
-fn configure_postmark_email_client() -> PostmarkEmailClient {
+// Also returns the captured mailbox when emails are only kept in memory for local development
+fn configure_email_client(
+    settings: &EmailClientSettings,
+) -> Result<(EmailClientType, Option<CapturingEmailClient>)> {
+    #[cfg_attr(not(feature = "dev-mailbox"), allow(unused_mut))]
+    let mut dev_mailbox = None;
+    let email_client: EmailClientType = match settings.provider {
+        EmailProvider::Postmark => Arc::new(RwLock::new(MeteredEmailClient::new(
+            configure_postmark_email_client(settings),
+            "postmark",
+        ))),
+        EmailProvider::Smtp => {
+            let smtp = settings
+                .smtp
+                .as_ref()
+                .expect("SMTP settings are validated on load");
+            let client = SmtpEmailClient::new(
+                smtp,
+                settings.sender().expect("Sender is validated on load"),
+                settings.timeout(),
+            )
+            .wrap_err("Failed to configure SMTP email client")?;
+            Arc::new(RwLock::new(MeteredEmailClient::new(client, "smtp")))
+        }
+        #[cfg(feature = "dev-mailbox")]
+        EmailProvider::Capture => {
+            let client = CapturingEmailClient::new();
+            dev_mailbox = Some(client.clone());
+            Arc::new(RwLock::new(MeteredEmailClient::new(client, "capture")))
+        }
+    };
+
+    Ok((email_client, dev_mailbox))
+}
+
+fn configure_sms_client(settings: &SmsClientSettings) -> Result<HttpSmsClient> {
+    let http_client = Client::builder()
+        .timeout(settings.timeout())
+        .build()
+        .wrap_err("Failed to build HTTP client")?;
+
+    HttpSmsClient::new(
+        &settings.base_url,
+        settings.sender.to_owned(),
+        settings.authorization_token.to_owned(),
+        http_client,
+    )
+    .wrap_err("Failed to configure SMS client")
+}
+
+fn configure_postmark_email_client(settings: &EmailClientSettings) -> PostmarkEmailClient {
     let http_client = Client::builder()
-        .timeout(prod::email_client::TIMEOUT)
+        .timeout(settings.timeout())
         .build()
         .expect("Failed to build HTTP client");
 
     PostmarkEmailClient::new(
-        prod::email_client::BASE_URL.to_owned(),
-        Email::parse(prod::email_client::SENDER).unwrap(),
-        POSTMARK_AUTH_TOKEN.to_owned(),
+        &settings.base_url,
+        settings.sender().expect("Sender is validated on load"),
+        settings.authorization_token.to_owned(),
         http_client,
     )
+    .expect("Base URL is validated on load")
+    .with_retry_policy(settings.retry.retry_policy())
 }
@@ -0,0 +1,396 @@
+use std::future::Future;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditEvent, AuditEventKind, AuthAPIError, ClientInfo, Email, TwoFAChannel, User, UserQuery,
+        UserStoreError,
+    },
+    utils::{require_permission, AuthError, AuthenticatedUser, Claims, ReadUsers, WriteUsers},
+};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 100;
+
+#[tracing::instrument(name = "Admin List Users", skip_all)]
+pub async fn admin_list_users(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Query(params): Query<ListUsersParams>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let query = UserQuery {
+        search: params.search.filter(|search| !search.is_empty()),
+        after: params.after,
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    };
+
+    let action = async {
+        let users = state
+            .user_store
+            .list_users(&query)
+            .await
+            .map_err(store_error)?;
+
+        // A full page may be followed by more users
+        let next_after = match users.len() == query.limit as usize {
+            true => users
+                .last()
+                .map(|user| user.email().expose_secret().to_owned()),
+            false => None,
+        };
+        Ok(ListUsersResponse {
+            users: users.iter().map(AdminUserView::from).collect(),
+            next_after,
+        })
+    };
+    let response = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<ReadUsers>,
+        AuditEventKind::AdminUsersListed,
+        None,
+        action,
+    )
+    .await?;
+
+    Ok(Json(response))
+}
+
+#[tracing::instrument(name = "Admin Get User", skip_all)]
+pub async fn admin_get_user(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async { get_user(&state, &email).await };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<ReadUsers>,
+        AuditEventKind::AdminUserViewed,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+// Also ends the user's sessions, their tokens would otherwise stay valid until they expire
+#[tracing::instrument(name = "Admin Disable User", skip_all)]
+pub async fn admin_disable_user(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        let parsed = parse_email(&email)?;
+        state
+            .user_store
+            .set_disabled(&parsed, true)
+            .await
+            .map_err(store_error)?;
+        state
+            .token_revocations
+            .revoke_all(&parsed)
+            .await
+            .map_err(store_error)?;
+        get_user(&state, &email).await
+    };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminUserDisabled,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+#[tracing::instrument(name = "Admin Enable User", skip_all)]
+pub async fn admin_enable_user(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        state
+            .user_store
+            .set_disabled(&parse_email(&email)?, false)
+            .await
+            .map_err(store_error)?;
+        get_user(&state, &email).await
+    };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminUserEnabled,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+// Applies from the user's next login, existing sessions are left alone
+#[tracing::instrument(name = "Admin Require 2FA", skip_all)]
+pub async fn admin_require_2fa(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        state
+            .user_store
+            .set_requires_2fa(&parse_email(&email)?, true)
+            .await
+            .map_err(store_error)?;
+        get_user(&state, &email).await
+    };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminTwoFARequired,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+#[tracing::instrument(name = "Admin Reset Lockout", skip_all)]
+pub async fn admin_reset_lockout(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        state
+            .user_store
+            .reset_lockout(&parse_email(&email)?)
+            .await
+            .map_err(store_error)?;
+        get_user(&state, &email).await
+    };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminLockoutReset,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+#[tracing::instrument(name = "Admin Revoke Sessions", skip_all)]
+pub async fn admin_revoke_sessions(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        state
+            .token_revocations
+            .revoke_all(&parse_email(&email)?)
+            .await
+            .map_err(store_error)?;
+        get_user(&state, &email).await
+    };
+    let user = run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminSessionsRevoked,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+#[tracing::instrument(name = "Admin Delete User", skip_all)]
+pub async fn admin_delete_user(
+    State(state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    Path(email): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let action = async {
+        let parsed = parse_email(&email)?;
+        // Revoking first also replaces a cached `tokens_valid_after` that still accepts the
+        // user's tokens
+        state
+            .token_revocations
+            .revoke_all(&parsed)
+            .await
+            .map_err(store_error)?;
+        state
+            .user_store
+            .delete_user(&parsed)
+            .await
+            .map_err(store_error)
+    };
+    run_admin_action(
+        &state,
+        &client,
+        user,
+        require_permission::<WriteUsers>,
+        AuditEventKind::AdminUserDeleted,
+        Some(&email),
+        action,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Runs `action` if the user passes `require`, and audits the outcome either way
+async fn run_admin_action<T>(
+    state: &AppState,
+    client: &ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    require: fn(&Claims) -> Result<(), AuthAPIError>,
+    kind: AuditEventKind,
+    target: Option<&str>,
+    action: impl Future<Output = Result<T, AuthAPIError>>,
+) -> Result<T, AuthAPIError> {
+    // Users without the permission are recorded too, as the actor of a forbidden attempt
+    let actor = user
+        .as_ref()
+        .ok()
+        .map(|AuthenticatedUser(claims)| claims.sub.clone());
+
+    let result = match user
+        .map_err(AuthAPIError::from)
+        .and_then(|AuthenticatedUser(claims)| require(&claims))
+    {
+        Ok(()) => action.await,
+        Err(e) => Err(e),
+    };
+
+    let mut event = AuditEvent::from_result(kind, actor, client, &result);
+    if let Some(target) = target {
+        event = event.with_target(target);
+    }
+    state.audit(event).await;
+
+    result
+}
+
+async fn get_user(state: &AppState, email: &str) -> Result<AdminUserView, AuthAPIError> {
+    let user = state
+        .user_store
+        .get_user(&parse_email(email)?)
+        .await
+        .map_err(store_error)?;
+
+    Ok(AdminUserView::from(&user))
+}
+
+// No user can have an invalid email
+fn parse_email(email: &str) -> Result<Email, AuthAPIError> {
+    Email::parse(email).map_err(|_| AuthAPIError::UserNotFound)
+}
+
+fn store_error(e: UserStoreError) -> AuthAPIError {
+    match e {
+        UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+        e => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersParams {
+    // Only users whose email contains this, ignoring case
+    search: Option<String>,
+    // `nextAfter` of the previous page
+    after: Option<String>,
+    limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListUsersResponse {
+    pub users: Vec<AdminUserView>,
+    // Pass as `after` to get the next page, absent on the last one
+    #[serde(rename = "nextAfter")]
+    pub next_after: Option<String>,
+}
+
+// What admins see of a user, everything but the password hash
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserView {
+    pub email: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    #[serde(rename = "twoFAChannel")]
+    pub two_fa_channel: TwoFAChannel,
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Option<String>,
+    pub disabled: bool,
+    pub roles: Vec<String>,
+    // Granted by `roles`
+    pub permissions: Vec<String>,
+    #[serde(rename = "failedLoginAttempts")]
+    pub failed_login_attempts: u32,
+    #[serde(rename = "lockedUntil")]
+    pub locked_until: Option<DateTime<Utc>>,
+    #[serde(rename = "tokensValidAfter")]
+    pub tokens_valid_after: Option<DateTime<Utc>>,
+}
+
+impl From<&User> for AdminUserView {
+    fn from(user: &User) -> Self {
+        Self {
+            email: user.email().expose_secret().to_owned(),
+            requires_2fa: user.requires_2fa(),
+            two_fa_channel: user.two_fa_channel(),
+            phone_number: user
+                .phone_number()
+                .map(|phone| phone.as_ref().expose_secret().to_owned()),
+            disabled: user.disabled(),
+            roles: user.role_names(),
+            permissions: user.permissions(),
+            failed_login_attempts: user.failed_login_attempts(),
+            locked_until: user.locked_until(),
+            tokens_valid_after: user.tokens_valid_after(),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::app_state::AppState;
+
+// Lists the emails captured by the "capture" provider, newest first, so 2FA can be
+// completed locally without a real mail provider. Only compiled with the dev-mailbox feature.
+#[tracing::instrument(name = "Dev mailbox", skip_all)]
+pub async fn dev_mailbox(
+    State(state): State<AppState>,
+    Query(query): Query<MailboxQuery>,
+) -> Response {
+    let Some(mailbox) = &state.dev_mailbox else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let emails: Vec<_> = mailbox
+        .all()
+        .into_iter()
+        .rev()
+        .filter(|email| query.to.as_ref().is_none_or(|to| &email.recipient == to))
+        .collect();
+
+    Json(emails).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct MailboxQuery {
+    // Only list emails sent to this address
+    to: Option<String>,
+}
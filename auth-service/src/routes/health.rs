@@ -0,0 +1,83 @@
+use std::{collections::BTreeMap, time::Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+
+// Liveness only reports that the process is serving requests; it never touches dependencies
+// so the orchestrator doesn't restart us because Postgres or Redis is down.
+pub async fn health_live() -> impl IntoResponse {
+    Json(HealthResponse {
+        status: HealthStatus::Up,
+        checks: BTreeMap::new(),
+    })
+}
+
+#[tracing::instrument(name = "Health Ready", skip_all)]
+pub async fn health_ready(State(state): State<AppState>) -> impl IntoResponse {
+    let timeout = state.settings.health.check_timeout();
+
+    let results = futures::future::join_all(state.health_checks.iter().map(|health_check| async move {
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout, health_check.check(timeout)).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let dependency = match result {
+            Ok(Ok(())) => DependencyHealth {
+                status: HealthStatus::Up,
+                latency_ms,
+                error: None,
+            },
+            Ok(Err(e)) => {
+                tracing::warn!(dependency = health_check.name(), error = ?e, "Health check failed");
+                DependencyHealth {
+                    status: HealthStatus::Down,
+                    latency_ms,
+                    error: Some(e.to_string()),
+                }
+            }
+            Err(_) => {
+                tracing::warn!(dependency = health_check.name(), "Health check timed out");
+                DependencyHealth {
+                    status: HealthStatus::Down,
+                    latency_ms,
+                    error: Some(format!("Timed out after {timeout:?}")),
+                }
+            }
+        };
+
+        (health_check.name().to_owned(), dependency)
+    }))
+    .await;
+
+    let checks: BTreeMap<_, _> = results.into_iter().collect();
+    let (status_code, status) = if checks.values().all(|c| c.status == HealthStatus::Up) {
+        (StatusCode::OK, HealthStatus::Up)
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Down)
+    };
+
+    (status_code, Json(HealthResponse { status, checks }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
@@ -1,20 +1,53 @@
+use std::time::Duration;
+
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
+use chrono::{TimeDelta, Utc};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
+    app_state::{AppState, SmsClientType},
+    domain::{
+        AuditEvent, AuditEventKind, AuthAPIError, ClientInfo, Email, LoginAttemptId, OutboxEmail,
+        PhoneNumber, TwoFAAttempt, TwoFAChannel, TwoFACode, User, UserStoreError,
+    },
+    services::{TwoFACodeEmail, TwoFACodeSms},
     utils::generate_auth_cookie,
 };
 
+// Tag of the 2FA emails, grouping them in the email provider's statistics
+const TWO_FA_EMAIL_TAG: &str = "2fa-code";
+
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
+    client: ClientInfo,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let actor = request.email.expose_secret().to_owned();
+    let result = authenticate(&state, &client, jar, request).await;
+
+    state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::Login,
+            Some(actor),
+            &client,
+            &result,
+        ))
+        .await;
+
+    result
+}
+
+async fn authenticate(
+    state: &AppState,
+    client: &ClientInfo,
+    jar: CookieJar,
+    request: LoginRequest,
+) -> Result<(CookieJar, (StatusCode, Json<LoginResponse>)), AuthAPIError> {
     // Validate email format
     let email = Email::parse(request.email.expose_secret())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
@@ -24,57 +57,117 @@ pub async fn login(
         return Err(AuthAPIError::InvalidCredentials);
     }
 
-    // Get user and validate password
-    let user_store = state.user_store.read().await;
+    // Validate the password before anything else: the store runs the password hash even for
+    // unknown emails, so existing and missing accounts take equally long to reject
+    let validated = state
+        .user_store
+        .validate_user(&email, request.password.expose_secret())
+        .await;
 
-    let user = user_store
+    let user = state
+        .user_store
         .get_user(&email)
         .await
         .map_err(|_| AuthAPIError::IncorrectCredentials)?;
 
-    user_store
-        .validate_user(&email, request.password.expose_secret())
-        .await
-        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    // A locked account rejects the right password too, so guesses can't be confirmed, and is
+    // answered like an unknown email
+    if user.is_locked_at(Utc::now()) {
+        return Err(AuthAPIError::AccountLocked);
+    }
+    match validated {
+        Ok(()) => {}
+        Err(UserStoreError::InvalidCredentials) => {
+            record_failed_login(state, &email).await?;
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+        Err(_) => return Err(AuthAPIError::IncorrectCredentials),
+    }
+    // Only revealed to someone who knows the password
+    if user.disabled() {
+        return Err(AuthAPIError::AccountDisabled);
+    }
+    if user.failed_login_attempts() > 0 {
+        state
+            .user_store
+            .reset_lockout(&email)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    }
 
     // Handle authentication based on 2FA requirement
     match user.requires_2fa() {
         true => {
-            let (jar, response) = handle_2fa(&email, &state, jar).await?;
+            let (jar, response) = handle_2fa(&user, &email, state, client, jar).await?;
             Ok((jar, (StatusCode::PARTIAL_CONTENT, response)))
         }
         false => {
-            let (jar, response) = handle_no_2fa(&email, jar).await?;
+            let (jar, response) = handle_no_2fa(&user, state, jar).await?;
             Ok((jar, (StatusCode::OK, response)))
         }
     }
 }
 
+async fn record_failed_login(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    let lockout = &state.settings.lockout;
+    let lock_until = Utc::now()
+        + TimeDelta::from_std(lockout.duration())
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    state
+        .user_store
+        .record_failed_login(email, lockout.max_failed_attempts, lock_until)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
 #[tracing::instrument(name = "Handle 2FA", skip_all)]
 async fn handle_2fa(
+    user: &User,
     email: &Email,
     state: &AppState,
+    client: &ClientInfo,
     jar: CookieJar,
 ) -> Result<(CookieJar, Json<LoginResponse>), AuthAPIError> {
     // Generate new random login attempt ID and 2FA code
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
-    // Store ID and code in 2FA code store.
+    // Store the pending login under its own ID, so earlier attempts stay valid
+    let attempt = TwoFAAttempt::new(email.clone(), two_fa_code.clone(), client.fingerprint());
     state
         .two_fa_code_store
-        .write()
-        .await
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(login_attempt_id.clone(), attempt)
         .await
         .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
 
-    // Send 2FA code via the email client. Return AuthAPIError::UnexpectedError if it fails.
-    let email_client = state.email_client.write().await;
-    email_client
-        .send_email(email, "Your 2FA code", two_fa_code.as_ref().expose_secret())
-        .await
-        .map_err(AuthAPIError::UnexpectedError)?;
+    // Deliver the code over the user's preferred channel
+    let sent = match (
+        user.two_fa_channel(),
+        user.phone_number(),
+        &state.sms_client,
+    ) {
+        (TwoFAChannel::Sms, Some(phone_number), Some(sms_client)) => {
+            send_2fa_sms(state, sms_client, phone_number, &two_fa_code).await
+        }
+        (TwoFAChannel::Sms, _, _) => {
+            tracing::warn!("SMS delivery is not configured, sending the 2FA code by email");
+            queue_2fa_email(state, client, email, &login_attempt_id, &two_fa_code).await
+        }
+        (TwoFAChannel::Email, _, _) => {
+            queue_2fa_email(state, client, email, &login_attempt_id, &two_fa_code).await
+        }
+    };
+
+    state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::TwoFACodeSent,
+            Some(email.as_ref().expose_secret().to_owned()),
+            client,
+            &sent,
+        ))
+        .await;
+    sent?;
 
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_string(),
@@ -84,12 +177,76 @@ async fn handle_2fa(
     Ok((jar, response))
 }
 
+// Queues the 2FA code for delivery. The outbox worker sends it and retries failures, so
+// a slow or unavailable email provider does not hold up the login.
+async fn queue_2fa_email(
+    state: &AppState,
+    client: &ClientInfo,
+    email: &Email,
+    login_attempt_id: &LoginAttemptId,
+    two_fa_code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    let content = TwoFACodeEmail {
+        branding: &state.settings.email_templates,
+        code: two_fa_code.as_ref().expose_secret(),
+        expires_in: Duration::from_secs(state.settings.two_fa.code_ttl_seconds),
+        ip: client.ip.as_deref(),
+        device: client.user_agent.as_deref(),
+    }
+    .render()
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_outbox
+        .enqueue(OutboxEmail {
+            idempotency_key: two_fa_email_key(login_attempt_id),
+            recipient: email.clone(),
+            content,
+            tag: Some(TWO_FA_EMAIL_TAG.to_owned()),
+        })
+        .await
+        .map(|_| ())
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))
+}
+
+// SMS codes are sent inline, the user is waiting on their phone for it
+async fn send_2fa_sms(
+    state: &AppState,
+    sms_client: &SmsClientType,
+    phone_number: &PhoneNumber,
+    two_fa_code: &TwoFACode,
+) -> Result<(), AuthAPIError> {
+    let body = TwoFACodeSms {
+        branding: &state.settings.email_templates,
+        code: two_fa_code.as_ref().expose_secret(),
+        expires_in: Duration::from_secs(state.settings.two_fa.code_ttl_seconds),
+    }
+    .render()
+    .map_err(AuthAPIError::UnexpectedError)?;
+
+    sms_client
+        .send_sms(phone_number, &body)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)
+}
+
+// Idempotency key of the 2FA email for a login attempt, so each code is only ever sent once.
+// Hashed, as the attempt ID together with the code in the email would complete the login.
+pub fn two_fa_email_key(login_attempt_id: &LoginAttemptId) -> String {
+    format!(
+        "2fa:{:x}",
+        Sha256::digest(login_attempt_id.as_ref().expose_secret())
+    )
+}
+
 #[tracing::instrument(name = "Handle No 2FA", skip_all)]
 async fn handle_no_2fa(
-    email: &Email,
+    user: &User,
+    state: &AppState,
     jar: CookieJar,
 ) -> Result<(CookieJar, Json<LoginResponse>), AuthAPIError> {
-    let auth_cookie = generate_auth_cookie(email).map_err(AuthAPIError::UnexpectedError)?;
+    let auth_cookie =
+        generate_auth_cookie(user, &state.settings.auth).map_err(AuthAPIError::UnexpectedError)?;
 
     let updated_jar = jar.add(auth_cookie);
 
@@ -1,38 +1,66 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
 use axum_extra::extract::CookieJar;
-use secrecy::Secret;
 
-use crate::{app_state::AppState, domain::AuthAPIError, utils::validate_token};
+use crate::{
+    app_state::AppState,
+    domain::{AuditEvent, AuditEventKind, AuthAPIError, BannedTokenStoreError, ClientInfo},
+    utils::{create_removal_cookie, AuthError, AuthenticatedUser, Claims},
+};
 
 pub async fn logout(
     State(app_state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
     jar: CookieJar,
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
-    // Retrieve JWT cookie
-    // If no cookie exists, user is already logged out - return error
-    let cookie = jar
-        .get(crate::utils::JWT_COOKIE_NAME)
-        .ok_or(AuthAPIError::MissingToken)?;
+    let result = ban_token(&app_state, &client, user).await;
+    let actor = result.as_ref().ok().map(|claims| claims.sub.clone());
 
-    let token = cookie.value().to_string();
+    app_state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::Logout,
+            actor,
+            &client,
+            &result,
+        ))
+        .await;
+    result?;
 
-    // Validate the token
-    // If token is invalid, user is already logged out - return error
-    let _ = validate_token(&token)
-        .await
-        .map_err(|_| AuthAPIError::InvalidToken)?;
+    // Remove the cookie with the attributes it was set with and return success
+    let cookie_settings = &app_state.settings.auth.cookie;
+    let updated_jar = jar.remove(create_removal_cookie(cookie_settings));
+
+    Ok((updated_jar, StatusCode::OK.into_response()))
+}
+
+async fn ban_token(
+    app_state: &AppState,
+    client: &ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+) -> Result<Claims, AuthAPIError> {
+    // Without a valid token the user is already logged out - return error
+    let AuthenticatedUser(claims) = user?;
 
     // Add token to banned token store
-    app_state
+    let banned = app_state
         .banned_token_store
-        .write()
-        .await
-        .add_token(&Secret::new(token.to_string()))
+        .add_token(&claims)
         .await
-        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+        .map_err(|e| match e {
+            // The token was already used to log out
+            BannedTokenStoreError::TokenAlreadyExists => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        });
 
-    // Remove the cookie and return success
-    let updated_jar = jar.remove(crate::utils::JWT_COOKIE_NAME);
+    app_state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::TokenBanned,
+            Some(claims.sub.clone()),
+            client,
+            &banned,
+        ))
+        .await;
+    banned?;
 
-    Ok((updated_jar, StatusCode::OK.into_response()))
+    Ok(claims)
 }
@@ -0,0 +1,56 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEvent, AuditEventKind, AuthAPIError, ClientInfo, Email, UserStoreError},
+    utils::{create_removal_cookie, AuthError, AuthenticatedUser, Claims},
+};
+
+// Logs the user out of every session, including the one making the request, by rejecting all
+// tokens issued to them so far
+#[tracing::instrument(name = "Logout All", skip_all)]
+pub async fn logout_all(
+    State(app_state): State<AppState>,
+    client: ClientInfo,
+    user: Result<AuthenticatedUser, AuthError>,
+    jar: CookieJar,
+) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
+    let result = revoke_tokens(&app_state, user).await;
+    let actor = result.as_ref().ok().map(|claims| claims.sub.clone());
+
+    app_state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::TokensRevoked,
+            actor,
+            &client,
+            &result,
+        ))
+        .await;
+    result?;
+
+    let cookie_settings = &app_state.settings.auth.cookie;
+    let updated_jar = jar.remove(create_removal_cookie(cookie_settings));
+
+    Ok((updated_jar, StatusCode::OK.into_response()))
+}
+
+// A token that was used to log out can't be used to revoke the others
+async fn revoke_tokens(
+    app_state: &AppState,
+    user: Result<AuthenticatedUser, AuthError>,
+) -> Result<Claims, AuthAPIError> {
+    let AuthenticatedUser(claims) = user?;
+
+    let email = Email::parse(&claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    app_state
+        .token_revocations
+        .revoke_all(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            e => AuthAPIError::UnexpectedError(e.into()),
+        })?;
+
+    Ok(claims)
+}
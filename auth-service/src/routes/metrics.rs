@@ -0,0 +1,11 @@
+use axum::{http::header, response::IntoResponse};
+
+use crate::utils::render_metrics;
+
+#[tracing::instrument(name = "Metrics", skip_all)]
+pub async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render_metrics(),
+    )
+}
@@ -1,11 +1,23 @@
+mod admin;
+#[cfg(feature = "dev-mailbox")]
+mod dev_mailbox;
+mod health;
 mod login;
 mod logout;
+mod logout_all;
+mod metrics;
 mod signup;
 mod verify_2fa;
 mod verify_token;
 
+pub use admin::*;
+#[cfg(feature = "dev-mailbox")]
+pub use dev_mailbox::*;
+pub use health::*;
 pub use login::*;
 pub use logout::*;
+pub use logout_all::*;
+pub use metrics::*;
 pub use signup::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
@@ -4,28 +4,55 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password, User, UserStoreError},
+    domain::{
+        AuditEvent, AuditEventKind, AuthAPIError, ClientInfo, Email, Password, PhoneNumber,
+        TwoFAChannel, User, UserStoreError,
+    },
 };
 
 #[tracing::instrument(name = "Signup", skip_all)]
 pub async fn signup(
     State(state): State<AppState>,
+    client: ClientInfo,
     Json(request): Json<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
+    let actor = request.email.expose_secret().to_owned();
+    let result = create_user(&state, request).await;
+
+    state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::Signup,
+            Some(actor),
+            &client,
+            &result,
+        ))
+        .await;
+    result?;
+
+    let response = Json(SignupResponse {
+        message: "User created successfully".to_string(),
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+async fn create_user(state: &AppState, request: SignupRequest) -> Result<(), AuthAPIError> {
     let email = Email::parse(request.email.expose_secret())
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
     let password =
         Password::parse(&request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
-    let user = User::new(email, password, request.requires_2fa);
-
-    match state.user_store.write().await.add_user(user).await {
-        Ok(_) => {
-            let response = Json(SignupResponse {
-                message: "User created successfully".to_string(),
-            });
+    let mut user = User::new(email, password, request.requires_2fa);
+    if let Some(phone_number) = &request.phone_number {
+        let phone_number = PhoneNumber::parse(phone_number.expose_secret())
+            .map_err(|_| AuthAPIError::InvalidCredentials)?;
+        user = user.with_phone_number(phone_number);
+    }
+    let user = user
+        .with_two_fa_channel(request.two_fa_channel)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-            Ok((StatusCode::CREATED, response))
-        }
+    match state.user_store.add_user(user).await {
+        Ok(_) => Ok(()),
         Err(UserStoreError::UserAlreadyExists) => Err(AuthAPIError::UserAlreadyExists),
         Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
     }
@@ -37,6 +64,12 @@ pub struct SignupRequest {
     password: Secret<String>,
     #[serde(rename = "requires2FA")]
     requires_2fa: bool,
+    // E.164, e.g. "+14155550123"
+    #[serde(rename = "phoneNumber")]
+    phone_number: Option<Secret<String>>,
+    // Where 2FA codes are sent, "sms" requires a phone number
+    #[serde(rename = "twoFAChannel", default)]
+    two_fa_channel: TwoFAChannel,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
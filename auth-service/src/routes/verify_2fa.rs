@@ -1,56 +1,135 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use color_eyre::eyre::{eyre, Context};
+use secrecy::ExposeSecret;
 use serde::Deserialize;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
-    utils::generate_auth_cookie,
+    domain::{
+        AuditEvent, AuditEventKind, AuthAPIError, ClientInfo, LoginAttemptId, TwoFAAttempt,
+        TwoFACode,
+    },
+    utils::{constant_time_eq, generate_auth_cookie},
 };
 
 #[tracing::instrument(name = "Verify 2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>,
+    client: ClientInfo,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>,
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
-    let email = Email::parse(&request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    // The user is only known once the attempt has been found
+    let attempt = find_attempt(&state, request).await;
+    let actor = attempt
+        .as_ref()
+        .ok()
+        .map(|(_, _, attempt)| attempt.email.as_ref().expose_secret().to_owned());
 
+    let result = match attempt {
+        Ok((login_attempt_id, two_fa_code, attempt)) => {
+            verify_code(&state, &client, jar, login_attempt_id, two_fa_code, attempt).await
+        }
+        Err(e) => Err(e),
+    };
+
+    state
+        .audit(AuditEvent::from_result(
+            AuditEventKind::TwoFAVerified,
+            actor,
+            &client,
+            &result,
+        ))
+        .await;
+
+    Ok((result?, StatusCode::OK.into_response()))
+}
+
+async fn find_attempt(
+    state: &AppState,
+    request: Verify2FARequest,
+) -> Result<(LoginAttemptId, TwoFACode, TwoFAAttempt), AuthAPIError> {
     let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id)
         .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
     let two_fa_code =
         TwoFACode::parse(request.two_fa_code).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
-    let stored_code = two_fa_code_store
-        .get_code(&email)
+    let attempt = state
+        .two_fa_code_store
+        .get_code(&login_attempt_id)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    Ok((login_attempt_id, two_fa_code, attempt))
+}
+
+async fn verify_code(
+    state: &AppState,
+    client: &ClientInfo,
+    jar: CookieJar,
+    login_attempt_id: LoginAttemptId,
+    two_fa_code: TwoFACode,
+    attempt: TwoFAAttempt,
+) -> Result<CookieJar, AuthAPIError> {
+    // The code only completes the login from the client that started it
+    let code_matches = attempt.code == two_fa_code;
+    let same_client = constant_time_eq(&attempt.fingerprint, &client.fingerprint());
+    if !(code_matches && same_client) {
+        record_failed_code(state, &login_attempt_id).await?;
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // The user may have been disabled while the code was on its way
+    let user = state
+        .user_store
+        .get_user(&attempt.email)
         .await
         .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    if user.disabled() {
+        return Err(AuthAPIError::AccountDisabled);
+    }
 
-    if stored_code != (login_attempt_id, two_fa_code) {
+    // Only the request that removes the attempt completes the login, so a code can't be
+    // used by two concurrent requests
+    let taken = state
+        .two_fa_code_store
+        .take_code(&login_attempt_id)
+        .await
+        .wrap_err("Failed to remove 2FA code")
+        .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
+    if !taken.is_some_and(|taken| taken.code == two_fa_code) {
         return Err(AuthAPIError::IncorrectCredentials);
     }
 
-    let auth_cookie = generate_auth_cookie(&email)
+    let auth_cookie = generate_auth_cookie(&user, &state.settings.auth)
         .wrap_err("Failed to generate auth cookie")
         .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
 
-    let updated_jar = jar.add(auth_cookie);
+    Ok(jar.add(auth_cookie))
+}
 
-    two_fa_code_store
-        .remove_code(&email)
+// Codes can't be guessed: after `two_fa.max_failed_codes` misses the login has to start over
+async fn record_failed_code(
+    state: &AppState,
+    login_attempt_id: &LoginAttemptId,
+) -> Result<(), AuthAPIError> {
+    let discarded = state
+        .two_fa_code_store
+        .record_failed_code(login_attempt_id, state.settings.two_fa.max_failed_codes)
         .await
-        .wrap_err("Failed to remove 2FA code")
+        .wrap_err("Failed to record failed 2FA code")
         .map_err(|e| AuthAPIError::UnexpectedError(eyre!(e)))?;
+    if discarded {
+        tracing::info!("Discarded 2FA attempt after too many wrong codes");
+    }
 
-    Ok((updated_jar, StatusCode::OK.into_response()))
+    Ok(())
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Verify2FARequest {
-    email: String,
     #[serde(rename = "loginAttemptId")]
     login_attempt_id: String,
     #[serde(rename = "2FACode")]
@@ -1,31 +1,24 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
-use secrecy::Secret;
+use axum::{extract::State, http::HeaderMap, response::IntoResponse, Json};
 use serde::Deserialize;
 
-use crate::{app_state::AppState, domain::AuthAPIError, utils::validate_token};
+use crate::{
+    app_state::AppState,
+    domain::AuthAPIError,
+    utils::{AppStateVerifier, TokenVerifier},
+};
 
+// Responds with the token's claims, so callers can authorize without decoding it
 #[tracing::instrument(name = "Verify Token", skip_all)]
 pub async fn verify_token(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(request): Json<VerifyTokenRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    validate_token(&request.token)
-        .await
-        .map_err(|_| AuthAPIError::InvalidToken)?;
+    let claims = AppStateVerifier(state)
+        .verify(&request.token, &headers)
+        .await?;
 
-    let is_banned = state
-        .banned_token_store
-        .read()
-        .await
-        .is_token_banned(&Secret::new(request.token.to_string()))
-        .await
-        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
-
-    if is_banned {
-        return Err(AuthAPIError::InvalidToken);
-    }
-
-    Ok(StatusCode::OK.into_response())
+    Ok(Json(claims))
 }
 
 #[derive(Deserialize)]
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use color_eyre::eyre::{Context, Result};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+use crate::domain::{AuditEvent, AuditSink};
+
+// Appends one JSON object per line to a file, for deployments that ship audit
+// events with a log collector instead of keeping them in Postgres.
+pub struct FileAuditSink {
+    file: Mutex<File>,
+}
+
+impl FileAuditSink {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await
+            .wrap_err_with(|| format!("Failed to open audit log {}", path.display()))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for FileAuditSink {
+    #[tracing::instrument(name = "Recording audit event in file", skip_all)]
+    async fn record(&self, event: &AuditEvent) -> Result<()> {
+        let mut line = serde_json::to_vec(event).wrap_err("Failed to serialize audit event")?;
+        line.push(b'\n');
+
+        // A single write per event keeps lines intact when the file is shared
+        let mut file = self.file.lock().await;
+        file.write_all(&line)
+            .await
+            .wrap_err("Failed to write audit event")?;
+        file.flush().await.wrap_err("Failed to flush audit log")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{AuditEventKind, AuditOutcome, ClientInfo};
+
+    #[tokio::test]
+    async fn test_events_are_appended_as_json_lines() {
+        let path = std::env::temp_dir().join(format!("audit-{}.log", Uuid::new_v4()));
+        let client = ClientInfo {
+            ip: Some("127.0.0.1".to_owned()),
+            user_agent: None,
+        };
+        let events = [
+            AuditEvent::success(
+                AuditEventKind::Signup,
+                Some("test@example.com".to_owned()),
+                &client,
+            ),
+            AuditEvent::failure(
+                AuditEventKind::Login,
+                None,
+                &client,
+                "Incorrect credentials",
+            ),
+        ];
+
+        let sink = FileAuditSink::open(&path).await.unwrap();
+        for event in &events {
+            sink.record(event).await.unwrap();
+        }
+        drop(sink);
+
+        // Reopening must append rather than truncate
+        let sink = FileAuditSink::open(&path).await.unwrap();
+        sink.record(&events[0]).await.unwrap();
+
+        let contents = tokio::fs::read_to_string(&path).await.unwrap();
+        let recorded = contents
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEvent>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            recorded,
+            vec![events[0].clone(), events[1].clone(), events[0].clone()]
+        );
+        assert_eq!(recorded[1].outcome, AuditOutcome::Failure);
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+mod file_audit_sink;
+mod postgres_audit_sink;
+
+pub use file_audit_sink::*;
+pub use postgres_audit_sink::*;
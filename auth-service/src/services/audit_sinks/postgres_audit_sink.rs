@@ -0,0 +1,42 @@
+use color_eyre::eyre::{Context, Result};
+use sqlx::PgPool;
+
+use crate::domain::{AuditEvent, AuditSink};
+
+pub struct PostgresAuditSink {
+    pool: PgPool,
+}
+
+impl PostgresAuditSink {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for PostgresAuditSink {
+    #[tracing::instrument(name = "Recording audit event in PostgreSQL", skip_all)]
+    async fn record(&self, event: &AuditEvent) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_events
+                (id, occurred_at, kind, actor, target, ip, user_agent, outcome, reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            event.id,
+            event.occurred_at,
+            event.kind.as_str(),
+            event.actor,
+            event.target,
+            event.ip,
+            event.user_agent,
+            event.outcome.as_str(),
+            event.reason
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to insert audit event")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Result;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+
+use crate::domain::{Email, EmailClient, EmailContent};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapturedEmail {
+    pub recipient: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+// An email client keeping every message in memory instead of sending it. Clones share
+// the same mailbox, so one handle can be given to the app and another kept for inspection.
+#[derive(Debug, Clone, Default)]
+pub struct CapturingEmailClient {
+    mailbox: Arc<Mutex<Vec<CapturedEmail>>>,
+}
+
+impl CapturingEmailClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Every captured email, oldest first
+    pub fn all(&self) -> Vec<CapturedEmail> {
+        self.lock().clone()
+    }
+
+    pub fn sent_to(&self, recipient: &Email) -> Vec<CapturedEmail> {
+        let recipient = recipient.as_ref().expose_secret();
+        self.lock()
+            .iter()
+            .filter(|email| &email.recipient == recipient)
+            .cloned()
+            .collect()
+    }
+
+    pub fn latest_to(&self, recipient: &Email) -> Option<CapturedEmail> {
+        self.sent_to(recipient).pop()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<CapturedEmail>> {
+        // A panic while holding the lock cannot leave the Vec half-updated
+        self.mailbox.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for CapturingEmailClient {
+    async fn send_email(&self, to: &Email, content: &EmailContent) -> Result<()> {
+        let email = CapturedEmail {
+            recipient: to.as_ref().expose_secret().to_owned(),
+            subject: content.subject.clone(),
+            html_body: content.html_body.clone(),
+            text_body: content.text_body.clone(),
+            sent_at: Utc::now(),
+        };
+
+        tracing::debug!(subject = %email.subject, "Captured email");
+        self.lock().push(email);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(subject: &str) -> EmailContent {
+        EmailContent {
+            subject: subject.to_owned(),
+            html_body: format!("<p>{subject}</p>"),
+            text_body: subject.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_captures_sent_emails_in_order() {
+        let client = CapturingEmailClient::new();
+        let alice = Email::parse("alice@example.com").unwrap();
+        let bob = Email::parse("bob@example.com").unwrap();
+
+        client.send_email(&alice, &content("first")).await.unwrap();
+        client.send_email(&bob, &content("second")).await.unwrap();
+        client.send_email(&alice, &content("third")).await.unwrap();
+
+        assert_eq!(client.len(), 3);
+        let subjects: Vec<_> = client.all().into_iter().map(|e| e.subject).collect();
+        assert_eq!(subjects, ["first", "second", "third"]);
+
+        assert_eq!(client.sent_to(&alice).len(), 2);
+        let latest = client.latest_to(&alice).unwrap();
+        assert_eq!(latest.subject, "third");
+        assert_eq!(latest.recipient, "alice@example.com");
+        assert_eq!(latest.html_body, "<p>third</p>");
+        assert!(latest.sent_at <= Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_the_mailbox() {
+        let client = CapturingEmailClient::new();
+        let handle = client.clone();
+        let alice = Email::parse("alice@example.com").unwrap();
+
+        client.send_email(&alice, &content("hello")).await.unwrap();
+        assert_eq!(handle.len(), 1);
+
+        handle.clear();
+        assert!(client.is_empty());
+        assert_eq!(client.latest_to(&alice), None);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+
+use crate::utils::{Clock, SystemClock};
+
+// In-memory map whose entries expire at a time given on insert, mirroring Redis `SET EXAT`.
+// Expired entries read as absent straight away and are freed by `remove_expired`, which the
+// sweeper task calls periodically.
+pub(crate) struct ExpiringMap<K, V> {
+    entries: Arc<Mutex<HashMap<K, Entry<V>>>>,
+    clock: Arc<dyn Clock>,
+}
+
+struct Entry<V> {
+    value: V,
+    expires_at: DateTime<Utc>,
+}
+
+impl<K, V> ExpiringMap<K, V>
+where
+    K: Eq + Hash + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // The current time according to the map's clock
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn insert(&self, key: K, value: V, expires_at: DateTime<Utc>) {
+        self.lock().insert(key, Entry { value, expires_at });
+    }
+
+    // Inserts `value` unless a live entry exists for `key`, returning whether it was inserted.
+    // An expired entry that has not been swept yet is replaced.
+    pub fn insert_if_absent(&self, key: K, value: V, expires_at: DateTime<Utc>) -> bool {
+        let now = self.clock.now();
+        let mut entries = self.lock();
+        if entries
+            .get(&key)
+            .is_some_and(|entry| entry.expires_at > now)
+        {
+            return false;
+        }
+        entries.insert(key, Entry { value, expires_at });
+        true
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let now = self.clock.now();
+        self.lock()
+            .get(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.value.clone())
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Applies `f` to the live entry for `key` and returns its result, `None` if there is no
+    // live entry
+    pub fn update<R>(&self, key: &K, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        let now = self.clock.now();
+        self.lock()
+            .get_mut(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| f(&mut entry.value))
+    }
+
+    // Returns the removed entry's value, unless it had expired
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let now = self.clock.now();
+        self.lock()
+            .remove(key)
+            .filter(|entry| entry.expires_at > now)
+            .map(|entry| entry.value)
+    }
+
+    // Frees expired entries, returning how many were removed
+    pub fn remove_expired(&self) -> usize {
+        remove_expired(&self.entries, self.clock.now())
+    }
+
+    // Entries held in memory, including expired ones not swept yet
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    // Runs `remove_expired` every `interval` until the map is dropped
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let entries = Arc::downgrade(&self.entries);
+        let clock = self.clock.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                let Some(entries) = Weak::upgrade(&entries) else {
+                    break;
+                };
+                let removed = remove_expired(&entries, clock.now());
+                if removed > 0 {
+                    tracing::debug!(removed, "Swept expired entries");
+                }
+            }
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<K, Entry<V>>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K, V> Default for ExpiringMap<K, V>
+where
+    K: Eq + Hash + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn remove_expired<K, V>(entries: &Mutex<HashMap<K, Entry<V>>>, now: DateTime<Utc>) -> usize {
+    let mut entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+    let before = entries.len();
+    entries.retain(|_, entry| entry.expires_at > now);
+    before - entries.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ManualClock;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    fn map(clock: &ManualClock) -> ExpiringMap<&'static str, u32> {
+        ExpiringMap::new().with_clock(Arc::new(clock.clone()))
+    }
+
+    fn in_ttl(clock: &ManualClock) -> DateTime<Utc> {
+        clock.now() + chrono::Duration::from_std(TTL).unwrap()
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let clock = ManualClock::default();
+        let map = map(&clock);
+        map.insert("a", 1, in_ttl(&clock));
+
+        clock.advance(TTL - Duration::from_secs(1));
+        assert_eq!(map.get(&"a"), Some(1));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(map.get(&"a"), None);
+        assert!(!map.contains_key(&"a"));
+    }
+
+    #[test]
+    fn test_insert_replaces_expiry() {
+        let clock = ManualClock::default();
+        let map = map(&clock);
+        map.insert("a", 1, in_ttl(&clock));
+
+        clock.advance(TTL / 2);
+        map.insert("a", 2, in_ttl(&clock));
+        clock.advance(TTL / 2);
+
+        assert_eq!(map.get(&"a"), Some(2));
+    }
+
+    #[test]
+    fn test_insert_if_absent_keeps_live_entries() {
+        let clock = ManualClock::default();
+        let map = map(&clock);
+
+        assert!(map.insert_if_absent("a", 1, in_ttl(&clock)));
+        assert!(!map.insert_if_absent("a", 2, in_ttl(&clock)));
+        assert_eq!(map.get(&"a"), Some(1));
+
+        // An expired entry counts as absent
+        clock.advance(TTL);
+        assert!(map.insert_if_absent("a", 3, in_ttl(&clock)));
+        assert_eq!(map.get(&"a"), Some(3));
+    }
+
+    #[test]
+    fn test_remove_expired_only_frees_expired_entries() {
+        let clock = ManualClock::default();
+        let map = map(&clock);
+        map.insert("old", 1, in_ttl(&clock));
+        clock.advance(TTL / 2);
+        map.insert("new", 2, in_ttl(&clock));
+        clock.advance(TTL / 2);
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.remove_expired(), 1);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&"new"), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_sweeper_frees_expired_entries() {
+        let clock = ManualClock::default();
+        let map = map(&clock);
+        let sweeper = map.spawn_sweeper(Duration::from_millis(10));
+        map.insert("a", 1, in_ttl(&clock));
+
+        clock.advance(TTL);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(map.len(), 0);
+
+        // The sweeper stops once the map is gone
+        drop(map);
+        tokio::time::timeout(Duration::from_secs(1), sweeper)
+            .await
+            .expect("Sweeper did not stop")
+            .unwrap();
+    }
+}
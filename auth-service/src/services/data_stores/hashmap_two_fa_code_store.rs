@@ -1,136 +1,199 @@
-use std::collections::HashMap;
+use std::{sync::Arc, time::Duration};
 
-use crate::domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+use chrono::{DateTime, Utc};
+
+use tokio::task::JoinHandle;
+
+use super::expiring_map::ExpiringMap;
+use crate::{
+    domain::{LoginAttemptId, TwoFAAttempt, TwoFACodeStore, TwoFACodeStoreError},
+    utils::Clock,
+};
 
-#[derive(Default)]
 pub struct HashMapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    attempts: ExpiringMap<LoginAttemptId, PendingAttempt>,
+    ttl: chrono::Duration,
+}
+
+#[derive(Clone)]
+struct PendingAttempt {
+    attempt: TwoFAAttempt,
+    failed_codes: u32,
+}
+
+impl HashMapTwoFACodeStore {
+    // Pending logins are forgotten `ttl` after they started, like with the Redis store
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            attempts: ExpiringMap::new(),
+            ttl: chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.attempts = self.attempts.with_clock(clock);
+        self
+    }
+
+    // Frees expired attempts, returning how many were removed
+    pub fn remove_expired(&self) -> usize {
+        self.attempts.remove_expired()
+    }
+
+    // Calls `remove_expired` every `interval` until the store is dropped
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        self.attempts.spawn_sweeper(interval)
+    }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashMapTwoFACodeStore {
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        attempt: TwoFAAttempt,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        let expires_at = self
+            .attempts
+            .now()
+            .checked_add_signed(self.ttl)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        let pending = PendingAttempt {
+            attempt,
+            failed_codes: 0,
+        };
+        self.attempts.insert(login_attempt_id, pending, expires_at);
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        self.codes.remove(email);
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.attempts.remove(login_attempt_id);
         Ok(())
     }
 
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        self.codes
-            .get(email)
-            .cloned()
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+        self.attempts
+            .get(login_attempt_id)
+            .map(|pending| pending.attempt)
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
     }
+
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError> {
+        Ok(self
+            .attempts
+            .remove(login_attempt_id)
+            .map(|pending| pending.attempt))
+    }
+
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        let failed_codes = self.attempts.update(login_attempt_id, |pending| {
+            pending.failed_codes += 1;
+            pending.failed_codes
+        });
+        if failed_codes.is_some_and(|failed_codes| failed_codes >= max_failed_codes) {
+            return Ok(self.attempts.remove(login_attempt_id).is_some());
+        }
+        Ok(false)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        domain::{Email, TwoFACode},
+        utils::ManualClock,
+    };
+
+    const TTL: Duration = Duration::from_secs(600);
 
-    fn get_test_email() -> Email {
-        Email::parse("test@example.com").unwrap()
+    fn store() -> HashMapTwoFACodeStore {
+        HashMapTwoFACodeStore::new(TTL)
     }
 
     fn get_test_login_attempt_id() -> LoginAttemptId {
         LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440000".to_string()).unwrap()
     }
 
-    fn get_test_two_fa_code() -> TwoFACode {
-        TwoFACode::parse("123456".to_string()).unwrap()
+    fn get_test_attempt(email: &str, code: &str) -> TwoFAAttempt {
+        TwoFAAttempt::new(
+            Email::parse(email).unwrap(),
+            TwoFACode::parse(code.to_string()).unwrap(),
+            "fingerprint".to_owned(),
+        )
     }
 
     #[tokio::test]
     async fn test_add_code_success() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
+        let store = store();
         let login_attempt_id = get_test_login_attempt_id();
-        let code = get_test_two_fa_code();
+        let attempt = get_test_attempt("test@example.com", "123456");
 
         let result = store
-            .add_code(email, login_attempt_id.clone(), code.clone())
+            .add_code(login_attempt_id.clone(), attempt.clone())
             .await;
 
         assert!(result.is_ok());
 
-        // Verify the code was actually stored
-        let email_for_get = get_test_email();
-        let stored = store.get_code(&email_for_get).await.unwrap();
-        assert_eq!(stored.0, login_attempt_id);
-        assert_eq!(stored.1, code);
+        // Verify the attempt was actually stored
+        let stored = store.get_code(&login_attempt_id).await.unwrap();
+        assert_eq!(stored, attempt);
     }
 
     #[tokio::test]
-    async fn test_add_code_overwrites_existing() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email1 = get_test_email();
-        let email2 = get_test_email();
+    async fn test_concurrent_attempts_for_same_email_coexist() {
+        let store = store();
         let first_attempt_id =
             LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440001".to_string()).unwrap();
         let second_attempt_id =
             LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440002".to_string()).unwrap();
-        let first_code = TwoFACode::parse("111111".to_string()).unwrap();
-        let second_code = TwoFACode::parse("222222".to_string()).unwrap();
+        let first_attempt = get_test_attempt("test@example.com", "111111");
+        let second_attempt = get_test_attempt("test@example.com", "222222");
 
-        // Add first code
         store
-            .add_code(email1, first_attempt_id, first_code)
+            .add_code(first_attempt_id.clone(), first_attempt.clone())
             .await
             .unwrap();
-
-        // Add second code for same email (should overwrite)
         store
-            .add_code(email2, second_attempt_id.clone(), second_code.clone())
+            .add_code(second_attempt_id.clone(), second_attempt.clone())
             .await
             .unwrap();
 
-        // Verify only the second code exists
-        let email_for_get = get_test_email();
-        let stored = store.get_code(&email_for_get).await.unwrap();
-        assert_eq!(stored.0, second_attempt_id);
-        assert_eq!(stored.1, second_code);
-    }
-
-    #[tokio::test]
-    async fn test_get_code_success() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
-        let login_attempt_id = get_test_login_attempt_id();
-        let code = get_test_two_fa_code();
-
-        store
-            .add_code(email, login_attempt_id.clone(), code.clone())
-            .await
-            .unwrap();
-
-        let email_for_get = get_test_email();
-        let result = store.get_code(&email_for_get).await;
-
-        assert!(result.is_ok());
-        let (stored_attempt_id, stored_code) = result.unwrap();
-        assert_eq!(stored_attempt_id, login_attempt_id);
-        assert_eq!(stored_code, code);
+        // A second login does not replace the first one
+        assert_eq!(
+            store.get_code(&first_attempt_id).await.unwrap(),
+            first_attempt
+        );
+        assert_eq!(
+            store.get_code(&second_attempt_id).await.unwrap(),
+            second_attempt
+        );
+
+        // Completing one leaves the other pending
+        store.remove_code(&first_attempt_id).await.unwrap();
+        assert!(store.get_code(&first_attempt_id).await.is_err());
+        assert!(store.get_code(&second_attempt_id).await.is_ok());
     }
 
     #[tokio::test]
     async fn test_get_code_not_found() {
-        let store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
+        let store = store();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&get_test_login_attempt_id()).await;
 
-        assert!(result.is_err());
         assert!(matches!(
             result.unwrap_err(),
             TwoFACodeStoreError::LoginAttemptIdNotFound
@@ -139,28 +202,24 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove_code_success() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
+        let store = store();
         let login_attempt_id = get_test_login_attempt_id();
-        let code = get_test_two_fa_code();
 
-        // Add code first
-        store.add_code(email, login_attempt_id, code).await.unwrap();
-
-        // Verify it exists
-        let email_for_get1 = get_test_email();
-        assert!(store.get_code(&email_for_get1).await.is_ok());
+        store
+            .add_code(
+                login_attempt_id.clone(),
+                get_test_attempt("test@example.com", "123456"),
+            )
+            .await
+            .unwrap();
+        assert!(store.get_code(&login_attempt_id).await.is_ok());
 
-        // Remove the code
-        let email_for_remove = get_test_email();
-        let result = store.remove_code(&email_for_remove).await;
+        let result = store.remove_code(&login_attempt_id).await;
 
         assert!(result.is_ok());
 
         // Verify it's gone
-        let email_for_get2 = get_test_email();
-        let get_result = store.get_code(&email_for_get2).await;
-        assert!(get_result.is_err());
+        let get_result = store.get_code(&login_attempt_id).await;
         assert!(matches!(
             get_result.unwrap_err(),
             TwoFACodeStoreError::LoginAttemptIdNotFound
@@ -169,68 +228,65 @@ mod tests {
 
     #[tokio::test]
     async fn test_remove_code_not_found() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
+        let store = store();
 
-        // Remove code that doesn't exist (should succeed)
-        let result = store.remove_code(&email).await;
+        // Remove an attempt that doesn't exist (should succeed)
+        let result = store.remove_code(&get_test_login_attempt_id()).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
-    async fn test_multiple_emails() {
-        let mut store = HashMapTwoFACodeStore::default();
-        let email1 = Email::parse("user1@example.com").unwrap();
-        let email2 = Email::parse("user2@example.com").unwrap();
-        let attempt1 =
-            LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440001".to_string()).unwrap();
-        let attempt2 =
-            LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440002".to_string()).unwrap();
-        let code1 = TwoFACode::parse("111111".to_string()).unwrap();
-        let code2 = TwoFACode::parse("222222".to_string()).unwrap();
+    async fn test_attempt_expires_after_ttl() {
+        let clock = ManualClock::default();
+        let store = store().with_clock(Arc::new(clock.clone()));
+        let login_attempt_id = get_test_login_attempt_id();
 
-        // Add codes for both emails
-        store
-            .add_code(email1, attempt1.clone(), code1.clone())
-            .await
-            .unwrap();
         store
-            .add_code(email2, attempt2.clone(), code2.clone())
+            .add_code(
+                login_attempt_id.clone(),
+                get_test_attempt("test@example.com", "123456"),
+            )
             .await
             .unwrap();
 
-        // Verify both exist independently
-        let email1_for_get = Email::parse("user1@example.com").unwrap();
-        let email2_for_get = Email::parse("user2@example.com").unwrap();
-        let result1 = store.get_code(&email1_for_get).await.unwrap();
-        let result2 = store.get_code(&email2_for_get).await.unwrap();
+        clock.advance(TTL - Duration::from_secs(1));
+        assert!(store.get_code(&login_attempt_id).await.is_ok());
 
-        assert_eq!(result1.0, attempt1);
-        assert_eq!(result1.1, code1);
-        assert_eq!(result2.0, attempt2);
-        assert_eq!(result2.1, code2);
-
-        // Remove one, verify the other still exists
-        let email1_for_remove = Email::parse("user1@example.com").unwrap();
-        store.remove_code(&email1_for_remove).await.unwrap();
-
-        let email1_for_final_check = Email::parse("user1@example.com").unwrap();
-        let email2_for_final_check = Email::parse("user2@example.com").unwrap();
-        assert!(store.get_code(&email1_for_final_check).await.is_err());
-        assert!(store.get_code(&email2_for_final_check).await.is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            store.get_code(&login_attempt_id).await.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        ));
     }
 
     #[tokio::test]
-    async fn test_default_implementation() {
-        let store = HashMapTwoFACodeStore::default();
-        let email = get_test_email();
+    async fn test_remove_expired_frees_only_expired_attempts() {
+        let clock = ManualClock::default();
+        let store = store().with_clock(Arc::new(clock.clone()));
+        let first_attempt_id =
+            LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440001".to_string()).unwrap();
+        let second_attempt_id =
+            LoginAttemptId::parse("550e8400-e29b-41d4-a716-446655440002".to_string()).unwrap();
 
-        // Default store should be empty
-        let result = store.get_code(&email).await;
-        assert!(result.is_err());
-        assert!(matches!(
-            result.unwrap_err(),
-            TwoFACodeStoreError::LoginAttemptIdNotFound
-        ));
+        store
+            .add_code(
+                first_attempt_id,
+                get_test_attempt("test@example.com", "111111"),
+            )
+            .await
+            .unwrap();
+        clock.advance(TTL / 2);
+        store
+            .add_code(
+                second_attempt_id.clone(),
+                get_test_attempt("test@example.com", "222222"),
+            )
+            .await
+            .unwrap();
+        clock.advance(TTL / 2);
+
+        assert_eq!(store.remove_expired(), 1);
+        assert_eq!(store.remove_expired(), 0);
+        assert!(store.get_code(&second_attempt_id).await.is_ok());
     }
 }
@@ -1,28 +1,65 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{PoisonError, RwLock},
+};
 
+use chrono::{DateTime, Utc};
 use secrecy::ExposeSecret;
 
-use crate::domain::{Email, User, UserStore, UserStoreError};
+use crate::{
+    domain::{Email, Role, User, UserQuery, UserStore, UserStoreError},
+    utils::constant_time_eq,
+};
 
-#[derive(Default)]
 pub struct HashMapUserStore {
-    users: HashMap<String, User>,
+    users: RwLock<HashMap<String, User>>,
+    // Fixed, like the roles the migrations seed
+    roles: Vec<Role>,
+}
+
+impl Default for HashMapUserStore {
+    fn default() -> Self {
+        Self {
+            users: RwLock::default(),
+            roles: vec![Role::admin()],
+        }
+    }
+}
+
+impl HashMapUserStore {
+    fn update_user(
+        &self,
+        email: &Email,
+        update: impl FnOnce(User) -> User,
+    ) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().unwrap_or_else(PoisonError::into_inner);
+        let user = users
+            .get_mut(email.as_ref().expose_secret())
+            .ok_or(UserStoreError::UserNotFound)?;
+        *user = update(user.clone());
+
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashMapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(user.email().expose_secret()) {
-            return Err(UserStoreError::UserAlreadyExists);
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let mut users = self.users.write().unwrap_or_else(PoisonError::into_inner);
+        match users.entry(user.email().expose_secret().to_owned()) {
+            Entry::Occupied(_) => Err(UserStoreError::UserAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(user);
+                Ok(())
+            }
         }
-        self.users
-            .insert(user.email().expose_secret().to_owned(), user);
-        Ok(())
     }
 
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
         let user = self
             .users
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
             .get(email.as_ref().expose_secret())
             .ok_or(UserStoreError::UserNotFound)?
             .clone();
@@ -32,12 +69,124 @@ impl UserStore for HashMapUserStore {
 
     async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
         let user = self.get_user(email).await?;
-        if user.password().expose_secret() == password {
+        if constant_time_eq(user.password().expose_secret(), password) {
             Ok(())
         } else {
             Err(UserStoreError::InvalidCredentials)
         }
     }
+
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| user.with_tokens_valid_after(valid_after))
+    }
+
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError> {
+        let search = query.search.as_ref().map(|search| search.to_lowercase());
+        let users = self.users.read().unwrap_or_else(PoisonError::into_inner);
+
+        let mut matching: Vec<(&String, &User)> = users
+            .iter()
+            .filter(|(email, _)| {
+                query
+                    .after
+                    .as_ref()
+                    .is_none_or(|after| email.as_str() > after.as_str())
+            })
+            .filter(|(email, _)| {
+                search
+                    .as_ref()
+                    .is_none_or(|search| email.to_lowercase().contains(search))
+            })
+            .collect();
+        matching.sort_by_key(|(email, _)| *email);
+
+        Ok(matching
+            .into_iter()
+            .take(query.limit as usize)
+            .map(|(_, user)| user.clone())
+            .collect())
+    }
+
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| user.with_disabled(disabled))
+    }
+
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| user.with_requires_2fa(requires_2fa))
+    }
+
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| {
+            let attempts = user.failed_login_attempts() + 1;
+            match attempts >= max_attempts {
+                true => user.with_lockout(0, Some(lock_until)),
+                false => {
+                    let locked_until = user.locked_until();
+                    user.with_lockout(attempts, locked_until)
+                }
+            }
+        })
+    }
+
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| user.with_lockout(0, None))
+    }
+
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        self.users
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(email.as_ref().expose_secret())
+            .map(|_| ())
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let role = self
+            .roles
+            .iter()
+            .find(|r| r.name == role)
+            .ok_or(UserStoreError::RoleNotFound)?
+            .clone();
+
+        self.update_user(email, |user| {
+            let mut roles = user.roles().to_vec();
+            if !roles.contains(&role) {
+                roles.push(role);
+                roles.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+            user.with_roles(roles)
+        })
+    }
+
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        self.update_user(email, |user| {
+            let roles = user
+                .roles()
+                .iter()
+                .filter(|r| r.name != role)
+                .cloned()
+                .collect();
+            user.with_roles(roles)
+        })
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError> {
+        Ok(self.roles.clone())
+    }
 }
 
 #[cfg(test)]
@@ -49,19 +198,19 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_user() {
-        let mut store = HashMapUserStore::default();
+        let store = HashMapUserStore::default();
         let user = User::new(
             Email::parse("test@example.com").unwrap(),
             Password::parse(&Secret::new("password123!".to_string())).unwrap(),
             false,
         );
         assert_eq!(store.add_user(user).await.unwrap(), ());
-        assert_eq!(store.users.len(), 1);
+        assert_eq!(store.users.read().unwrap().len(), 1);
     }
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut store = HashMapUserStore::default();
+        let store = HashMapUserStore::default();
         let user = User::new(
             Email::parse("test@example.com").unwrap(),
             Password::parse(&Secret::new("password123!".to_string())).unwrap(),
@@ -77,7 +226,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_validate_user() {
-        let mut store = HashMapUserStore::default();
+        let store = HashMapUserStore::default();
         let user = User::new(
             Email::parse("test@example.com").unwrap(),
             Password::parse(&Secret::new("password123!".to_string())).unwrap(),
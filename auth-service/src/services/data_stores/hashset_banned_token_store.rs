@@ -1,56 +1,98 @@
-use std::collections::HashSet;
+use std::{sync::Arc, time::Duration};
 
-use secrecy::{ExposeSecret, Secret};
+use tokio::task::JoinHandle;
 
-use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+use super::expiring_map::ExpiringMap;
+use crate::{
+    domain::{BannedTokenStore, BannedTokenStoreError},
+    utils::{Claims, Clock},
+};
 
 #[derive(Default)]
 pub struct HashSetBannedTokenStore {
-    banned_tokens: HashSet<String>,
+    banned_tokens: ExpiringMap<String, ()>,
+}
+
+impl HashSetBannedTokenStore {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.banned_tokens = self.banned_tokens.with_clock(clock);
+        self
+    }
+
+    // Frees expired bans, returning how many were removed
+    pub fn remove_expired(&self) -> usize {
+        self.banned_tokens.remove_expired()
+    }
+
+    // Calls `remove_expired` every `interval` until the store is dropped
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        self.banned_tokens.spawn_sweeper(interval)
+    }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashSetBannedTokenStore {
-    async fn add_token(&mut self, token: &Secret<String>) -> Result<(), BannedTokenStoreError> {
-        if self.banned_tokens.contains(token.expose_secret()) {
-            Err(BannedTokenStoreError::TokenAlreadyExists)
-        } else {
-            self.banned_tokens.insert(token.expose_secret().to_string());
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError> {
+        // Checking and inserting under one lock lets only one of two concurrent bans succeed
+        if self
+            .banned_tokens
+            .insert_if_absent(claims.jti.clone(), (), claims.expires_at())
+        {
             Ok(())
+        } else {
+            Err(BannedTokenStoreError::TokenAlreadyExists)
         }
     }
 
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.banned_tokens.contains(token.expose_secret()))
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.banned_tokens.contains_key(&claims.jti))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::{Clock, ManualClock};
+
+    const TTL: Duration = Duration::from_secs(600);
+
+    fn claims(jti: &str) -> Claims {
+        claims_expiring(jti, &ManualClock::default())
+    }
+
+    fn claims_expiring(jti: &str, clock: &ManualClock) -> Claims {
+        Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (clock.now().timestamp() + TTL.as_secs() as i64) as usize,
+            iat: clock.now().timestamp() as usize,
+            jti: jti.to_owned(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        }
+    }
 
     #[tokio::test]
     async fn test_add_token_success() {
-        let mut store = HashSetBannedTokenStore::default();
-        let token = Secret::new("test_token_123".to_string());
+        let store = HashSetBannedTokenStore::default();
+        let claims = claims("test_jti_123");
 
-        let result = store.add_token(&token).await;
+        let result = store.add_token(&claims).await;
 
         assert!(result.is_ok());
-        assert!(store.banned_tokens.contains(token.expose_secret()));
+        assert!(store.banned_tokens.contains_key(&claims.jti));
     }
 
     #[tokio::test]
     async fn test_add_token_already_exists() {
-        let mut store = HashSetBannedTokenStore::default();
-        let token = Secret::new("duplicate_token".to_string());
+        let store = HashSetBannedTokenStore::default();
+        let claims = claims("duplicate_jti");
 
         // Add token first time - should succeed
-        let first_result = store.add_token(&token).await;
+        let first_result = store.add_token(&claims).await;
         assert!(first_result.is_ok());
 
         // Add same token again - should fail
-        let second_result = store.add_token(&token).await;
+        let second_result = store.add_token(&claims).await;
         assert!(second_result.is_err());
         assert!(matches!(
             second_result.unwrap_err(),
@@ -60,68 +102,62 @@ mod tests {
 
     #[tokio::test]
     async fn test_is_token_banned_true() {
-        let mut store = HashSetBannedTokenStore::default();
-        let token = Secret::new("banned_token".to_string());
+        let store = HashSetBannedTokenStore::default();
+        let claims = claims("banned_jti");
 
         // Add token to banned list
-        store.add_token(&token).await.unwrap();
+        store.add_token(&claims).await.unwrap();
 
         // Check if token is banned
-        let result = store.is_token_banned(&token).await;
+        let result = store.is_token_banned(&claims).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
+        assert!(result.unwrap());
     }
 
     #[tokio::test]
     async fn test_is_token_banned_false() {
         let store = HashSetBannedTokenStore::default();
-        let token = Secret::new("not_banned_token".to_string());
 
         // Check if token is banned (it shouldn't be)
-        let result = store.is_token_banned(&token).await;
+        let result = store.is_token_banned(&claims("not_banned_jti")).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
+        assert!(!result.unwrap());
     }
 
     #[tokio::test]
     async fn test_multiple_tokens() {
-        let mut store = HashSetBannedTokenStore::default();
-        let tokens = vec!["token1", "token2", "token3"];
+        let store = HashSetBannedTokenStore::default();
+        let jtis = vec!["jti1", "jti2", "jti3"];
 
         // Add multiple tokens
-        for token in &tokens {
-            let result = store.add_token(&Secret::new(token.to_string())).await;
+        for jti in &jtis {
+            let result = store.add_token(&claims(jti)).await;
             assert!(result.is_ok());
         }
 
         // Verify all tokens are banned
-        for token in &tokens {
-            let result = store.is_token_banned(&Secret::new(token.to_string())).await;
+        for jti in &jtis {
+            let result = store.is_token_banned(&claims(jti)).await;
             assert!(result.is_ok());
-            assert_eq!(result.unwrap(), true);
+            assert!(result.unwrap());
         }
 
         // Verify a non-added token is not banned
-        let result = store
-            .is_token_banned(&Secret::new("non_existent_token".to_string()))
-            .await;
+        let result = store.is_token_banned(&claims("non_existent_jti")).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
+        assert!(!result.unwrap());
     }
 
     #[tokio::test]
-    async fn test_empty_token() {
-        let mut store = HashSetBannedTokenStore::default();
-        let empty_token = Secret::new("".to_string());
-
-        // Add empty token
-        let result = store.add_token(&empty_token).await;
-        assert!(result.is_ok());
+    async fn test_token_is_banned_by_jti() {
+        let store = HashSetBannedTokenStore::default();
+        store.add_token(&claims("shared_jti")).await.unwrap();
 
-        // Check if empty token is banned
-        let result = store.is_token_banned(&empty_token).await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
+        // Other claims do not matter
+        let mut other = claims("shared_jti");
+        other.sub = "other@example.com".to_owned();
+        other.exp += 60;
+        assert!(store.is_token_banned(&other).await.unwrap());
     }
 
     #[tokio::test]
@@ -129,13 +165,28 @@ mod tests {
         let store = HashSetBannedTokenStore::default();
 
         // New store should not have any banned tokens
-        let result = store
-            .is_token_banned(&Secret::new("any_token".to_string()))
-            .await;
+        let result = store.is_token_banned(&claims("any_jti")).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
+        assert!(!result.unwrap());
+
+        // Verify the internal map is empty
+        assert_eq!(store.banned_tokens.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ban_expires_with_token() {
+        let clock = ManualClock::default();
+        let store = HashSetBannedTokenStore::default().with_clock(Arc::new(clock.clone()));
+        let claims = claims_expiring("expiring_jti", &clock);
+
+        store.add_token(&claims).await.unwrap();
+        clock.advance(TTL - Duration::from_secs(1));
+        assert!(store.is_token_banned(&claims).await.unwrap());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(!store.is_token_banned(&claims).await.unwrap());
 
-        // Verify the internal HashSet is empty
-        assert!(store.banned_tokens.is_empty());
+        // An expired ban can be added again
+        assert!(store.add_token(&claims).await.is_ok());
     }
 }
@@ -0,0 +1,272 @@
+use chrono::{DateTime, Utc};
+
+use crate::{
+    domain::{
+        BannedTokenStore, BannedTokenStoreError, Email, LoginAttemptId, Role, TwoFAAttempt,
+        TwoFACodeStore, TwoFACodeStoreError, User, UserQuery, UserStore, UserStoreError,
+    },
+    utils::{record_store_operation, Claims},
+};
+
+// Wrappers recording the latency and outcome of every call to the wrapped store.
+// `name` becomes the `store` label, e.g. "postgres_user" or "redis_banned_token".
+
+pub struct MeteredUserStore<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S> MeteredUserStore<S> {
+    pub fn new(inner: S, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: UserStore + Send + Sync> UserStore for MeteredUserStore<S> {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        record_store_operation(self.name, "add_user", self.inner.add_user(user)).await
+    }
+
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        record_store_operation(self.name, "get_user", self.inner.get_user(email)).await
+    }
+
+    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "validate_user",
+            self.inner.validate_user(email, password),
+        )
+        .await
+    }
+
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "set_tokens_valid_after",
+            self.inner.set_tokens_valid_after(email, valid_after),
+        )
+        .await
+    }
+
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError> {
+        record_store_operation(self.name, "list_users", self.inner.list_users(query)).await
+    }
+
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "set_disabled",
+            self.inner.set_disabled(email, disabled),
+        )
+        .await
+    }
+
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "set_requires_2fa",
+            self.inner.set_requires_2fa(email, requires_2fa),
+        )
+        .await
+    }
+
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "record_failed_login",
+            self.inner
+                .record_failed_login(email, max_attempts, lock_until),
+        )
+        .await
+    }
+
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError> {
+        record_store_operation(self.name, "reset_lockout", self.inner.reset_lockout(email)).await
+    }
+
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        record_store_operation(self.name, "delete_user", self.inner.delete_user(email)).await
+    }
+
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "assign_role",
+            self.inner.assign_role(email, role),
+        )
+        .await
+    }
+
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        record_store_operation(
+            self.name,
+            "remove_role",
+            self.inner.remove_role(email, role),
+        )
+        .await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError> {
+        record_store_operation(self.name, "list_roles", self.inner.list_roles()).await
+    }
+}
+
+pub struct MeteredBannedTokenStore<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S> MeteredBannedTokenStore<S> {
+    pub fn new(inner: S, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: BannedTokenStore + Send + Sync> BannedTokenStore for MeteredBannedTokenStore<S> {
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError> {
+        record_store_operation(self.name, "add_token", self.inner.add_token(claims)).await
+    }
+
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError> {
+        record_store_operation(
+            self.name,
+            "is_token_banned",
+            self.inner.is_token_banned(claims),
+        )
+        .await
+    }
+}
+
+pub struct MeteredTwoFACodeStore<S> {
+    inner: S,
+    name: &'static str,
+}
+
+impl<S> MeteredTwoFACodeStore<S> {
+    pub fn new(inner: S, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: TwoFACodeStore + Send + Sync> TwoFACodeStore for MeteredTwoFACodeStore<S> {
+    async fn add_code(
+        &self,
+        login_attempt_id: LoginAttemptId,
+        attempt: TwoFAAttempt,
+    ) -> Result<(), TwoFACodeStoreError> {
+        record_store_operation(
+            self.name,
+            "add_code",
+            self.inner.add_code(login_attempt_id, attempt),
+        )
+        .await
+    }
+
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        record_store_operation(
+            self.name,
+            "remove_code",
+            self.inner.remove_code(login_attempt_id),
+        )
+        .await
+    }
+
+    async fn get_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+        record_store_operation(self.name, "get_code", self.inner.get_code(login_attempt_id)).await
+    }
+
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError> {
+        record_store_operation(
+            self.name,
+            "take_code",
+            self.inner.take_code(login_attempt_id),
+        )
+        .await
+    }
+
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        record_store_operation(
+            self.name,
+            "record_failed_code",
+            self.inner
+                .record_failed_code(login_attempt_id, max_failed_codes),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::*;
+    use crate::{
+        domain::Password,
+        services::data_stores::{HashMapUserStore, HashSetBannedTokenStore},
+    };
+
+    #[tokio::test]
+    async fn test_metered_user_store_delegates_to_inner_store() {
+        let store = MeteredUserStore::new(HashMapUserStore::default(), "hashmap_user");
+        let email = Email::parse("test@example.com").unwrap();
+        let password = Password::parse(&Secret::new("password123!".to_owned())).unwrap();
+        let user = User::new(email.clone(), password, false);
+
+        store.add_user(user).await.unwrap();
+
+        let user = store.get_user(&email).await.unwrap();
+        assert_eq!(user.email().expose_secret(), "test@example.com");
+        assert_eq!(
+            store.add_user(user).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metered_banned_token_store_delegates_to_inner_store() {
+        let store =
+            MeteredBannedTokenStore::new(HashSetBannedTokenStore::default(), "hashset_banned");
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (chrono::Utc::now().timestamp() + 600) as usize,
+            iat: chrono::Utc::now().timestamp() as usize,
+            jti: "jti".to_owned(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        };
+
+        store.add_token(&claims).await.unwrap();
+
+        assert!(store.is_token_banned(&claims).await.unwrap());
+    }
+}
@@ -1,13 +1,35 @@
+mod expiring_map;
 mod hashmap_two_fa_code_store;
 mod hashmap_user_store;
 mod hashset_banned_token_store;
+mod metered_stores;
+mod password_hashing;
+mod postgres_banned_token_store;
+mod postgres_email_outbox;
+mod postgres_two_fa_code_store;
 mod postgres_user_store;
 mod redis_banned_token_store;
+mod redis_tokens_valid_after_cache;
 mod redis_two_fa_code_store;
+mod sqlite_banned_token_store;
+mod sqlite_email_outbox;
+mod sqlite_two_fa_code_store;
+mod sqlite_user_store;
+mod unix_micros;
+mod user_roles;
 
 pub use hashmap_two_fa_code_store::*;
 pub use hashmap_user_store::*;
 pub use hashset_banned_token_store::*;
+pub use metered_stores::*;
+pub use postgres_banned_token_store::*;
+pub use postgres_email_outbox::*;
+pub use postgres_two_fa_code_store::*;
 pub use postgres_user_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_tokens_valid_after_cache::*;
 pub use redis_two_fa_code_store::*;
+pub use sqlite_banned_token_store::*;
+pub use sqlite_email_outbox::*;
+pub use sqlite_two_fa_code_store::*;
+pub use sqlite_user_store::*;
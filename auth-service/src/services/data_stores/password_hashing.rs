@@ -0,0 +1,122 @@
+// Argon2 hashing shared by every persistent user store, so passwords hashed by one can be
+// verified by another.
+
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use color_eyre::eyre::{Context, Result};
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{User, UserStoreError},
+    utils::record_password_hash_duration,
+};
+
+// Checks `password` against a user looked up by a store's `get_user`
+pub(super) async fn verify_user_password(
+    user: Result<User, UserStoreError>,
+    password: &str,
+) -> Result<(), UserStoreError> {
+    let user = match user {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => {
+            // Spend as long as a real check would, so timing does not reveal unknown emails
+            let _ =
+                verify_password_hash(DUMMY_PASSWORD_HASH.to_owned(), password.to_string()).await;
+            return Err(UserStoreError::UserNotFound);
+        }
+        Err(e) => return Err(e),
+    };
+
+    verify_password_hash(
+        user.password().expose_secret().to_string(),
+        password.to_string(),
+    )
+    .await
+    .map_err(|_| UserStoreError::InvalidCredentials)
+}
+
+// Helper function to verify if a given password matches an expected hash
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+async fn verify_password_hash(
+    expected_password_hash: String,
+    password_candidate: String,
+) -> Result<()> {
+    let current_span: tracing::Span = tracing::Span::current();
+
+    let result = tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| {
+            let started = std::time::Instant::now();
+            let expected_password_hash: PasswordHash<'_> =
+                PasswordHash::new(&expected_password_hash)?;
+
+            let result = Argon2::default()
+                .verify_password(password_candidate.as_bytes(), &expected_password_hash)
+                .wrap_err("Failed to verify password hash");
+            record_password_hash_duration("verify", started.elapsed());
+
+            result
+        })
+    })
+    .await;
+
+    result?
+}
+
+// Helper function to hash passwords before persisting them in the database
+#[tracing::instrument(name = "Computing password hash", skip_all)]
+pub(super) async fn compute_password_hash(password: String) -> Result<String> {
+    let current_span: tracing::Span = tracing::Span::current();
+
+    let result = tokio::task::spawn_blocking(move || {
+        current_span.in_scope(|| {
+            let started = std::time::Instant::now();
+            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+
+            let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, hash_params()?)
+                .hash_password(password.as_bytes(), &salt)?
+                .to_string();
+            record_password_hash_duration("hash", started.elapsed());
+
+            Ok(password_hash)
+        })
+    })
+    .await;
+
+    result?
+}
+
+fn hash_params() -> Result<Params> {
+    Ok(Params::new(15000, 2, 1, None)?)
+}
+
+// A hash of a random password, made with `hash_params`. Verifying against it costs the same
+// as verifying a real user's password.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=15000,t=2,p=1$4/LGoh3PLG/CAn2ZYaeALQ$L5c/a3ojCW1K7U+HYFCpLvityjhPlmGMBDX5JrtLR9I";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dummy_password_hash_uses_current_params() {
+        let dummy = PasswordHash::new(DUMMY_PASSWORD_HASH).unwrap();
+
+        assert_eq!(dummy.algorithm, Algorithm::Argon2id.ident());
+        let (dummy, current) = (Params::try_from(&dummy).unwrap(), hash_params().unwrap());
+        assert_eq!(
+            (dummy.m_cost(), dummy.t_cost(), dummy.p_cost()),
+            (current.m_cost(), current.t_cost(), current.p_cost())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dummy_password_hash_never_matches() {
+        let result =
+            verify_password_hash(DUMMY_PASSWORD_HASH.to_owned(), "password123!".to_owned()).await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::{Context, Result};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+
+use crate::{
+    domain::{BannedTokenStore, BannedTokenStoreError},
+    utils::Claims,
+};
+
+pub struct PostgresBannedTokenStore {
+    pool: PgPool,
+}
+
+impl PostgresBannedTokenStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    // Deletes expired bans, returning how many were removed
+    #[tracing::instrument(name = "Removing expired banned tokens from PostgreSQL", skip_all)]
+    pub async fn remove_expired(&self) -> Result<u64> {
+        remove_expired(&self.pool).await
+    }
+
+    // Calls `remove_expired` every `interval`; the task ends when the pool is closed
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !pool.is_closed() {
+                ticks.tick().await;
+                match remove_expired(&pool).await {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::debug!(removed, "Swept expired banned tokens"),
+                    Err(e) => tracing::warn!(error = ?e, "Failed to sweep expired banned tokens"),
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for PostgresBannedTokenStore {
+    #[tracing::instrument(name = "Adding banned token to PostgreSQL", skip_all)]
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError> {
+        // An expired ban that has not been swept yet is replaced, a live one is kept
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO banned_tokens (jti, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (jti) DO UPDATE SET expires_at = EXCLUDED.expires_at
+            WHERE banned_tokens.expires_at <= $3
+            "#,
+            claims.jti,
+            claims.expires_at(),
+            Utc::now()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to ban token in PostgreSQL")
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(BannedTokenStoreError::TokenAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Checking banned token in PostgreSQL", skip_all)]
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError> {
+        let banned = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM banned_tokens WHERE jti = $1 AND expires_at > $2
+            ) AS "banned!"
+            "#,
+            claims.jti,
+            Utc::now()
+        )
+        .fetch_one(&self.pool)
+        .await
+        .wrap_err("Failed to check if token is banned in PostgreSQL")
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(banned)
+    }
+}
+
+async fn remove_expired(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query!(
+        "DELETE FROM banned_tokens WHERE expires_at <= $1",
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .wrap_err("Failed to remove expired banned tokens")?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    DeliveryStatus, Email, EmailContent, EmailOutbox, EmailOutboxError, OutboxEmail, PendingEmail,
+};
+
+pub struct PostgresEmailOutbox {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutbox {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for PostgresEmailOutbox {
+    #[tracing::instrument(name = "Enqueueing email in PostgreSQL outbox", skip_all)]
+    async fn enqueue(&self, email: OutboxEmail) -> Result<bool, EmailOutboxError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO email_outbox (id, idempotency_key, recipient, subject, html_body, text_body, tag)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (idempotency_key) DO NOTHING
+            "#,
+            Uuid::new_v4(),
+            email.idempotency_key,
+            email.recipient.as_ref().expose_secret(),
+            email.content.subject,
+            email.content.html_body,
+            email.content.text_body,
+            email.tag
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to enqueue email")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(name = "Retrieving email delivery status from PostgreSQL", skip_all)]
+    async fn status(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<Option<DeliveryStatus>, EmailOutboxError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, idempotency_key, status, attempts, last_error, next_attempt_at, created_at, sent_at
+            FROM email_outbox
+            WHERE idempotency_key = $1
+            "#,
+            idempotency_key
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve email delivery status")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        row.map(|row| {
+            Ok(DeliveryStatus {
+                id: row.id,
+                idempotency_key: row.idempotency_key,
+                state: row.status.parse()?,
+                attempts: row.attempts as u32,
+                last_error: row.last_error,
+                next_attempt_at: row.next_attempt_at,
+                created_at: row.created_at,
+                sent_at: row.sent_at,
+            })
+        })
+        .transpose()
+    }
+
+    #[tracing::instrument(name = "Claiming due emails from PostgreSQL outbox", skip_all)]
+    async fn claim_due(
+        &self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<PendingEmail>, EmailOutboxError> {
+        let lease_until = Utc::now()
+            + chrono::Duration::from_std(lease)
+                .map_err(|e| EmailOutboxError::UnexpectedError(eyre!(e)))?;
+
+        // SKIP LOCKED lets several workers poll the outbox without claiming the same email
+        let rows = sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET attempts = attempts + 1, next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= now()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, recipient, subject, html_body, text_body, tag, attempts
+            "#,
+            limit as i64,
+            lease_until
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to claim due emails")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(PendingEmail {
+                    id: row.id,
+                    recipient: Email::parse(&row.recipient)
+                        .map_err(|e| EmailOutboxError::UnexpectedError(eyre!(e)))?,
+                    content: EmailContent {
+                        subject: row.subject,
+                        html_body: row.html_body.unwrap_or_default(),
+                        text_body: row.text_body.unwrap_or_default(),
+                    },
+                    tag: row.tag,
+                    attempts: row.attempts as u32,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as sent in PostgreSQL", skip_all)]
+    async fn mark_sent(&self, id: Uuid) -> Result<(), EmailOutboxError> {
+        // The bodies may contain 2FA codes, there is no reason to keep them once delivered
+        sqlx::query!(
+            r#"
+            UPDATE email_outbox
+            SET status = 'sent', sent_at = now(), last_error = NULL, html_body = NULL, text_body = NULL
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to mark email as sent")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as failed in PostgreSQL", skip_all)]
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxError> {
+        match retry_at {
+            Some(retry_at) => {
+                sqlx::query!(
+                    "UPDATE email_outbox SET last_error = $2, next_attempt_at = $3 WHERE id = $1",
+                    id,
+                    error,
+                    retry_at
+                )
+                .execute(&self.pool)
+                .await
+            }
+            // Dead emails keep their error for inspection, but not their bodies
+            None => {
+                sqlx::query!(
+                    r#"
+                UPDATE email_outbox
+                SET status = 'dead', last_error = $2, html_body = NULL, text_body = NULL
+                WHERE id = $1
+                "#,
+                    id,
+                    error
+                )
+                .execute(&self.pool)
+                .await
+            }
+        }
+        .wrap_err("Failed to mark email as failed")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
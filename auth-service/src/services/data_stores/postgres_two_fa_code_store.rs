@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+
+use crate::domain::{
+    Email, LoginAttemptId, TwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+};
+
+pub struct PostgresTwoFACodeStore {
+    pool: PgPool,
+    ttl: chrono::Duration,
+}
+
+impl PostgresTwoFACodeStore {
+    // Pending logins are forgotten `ttl` after they started, like with the Redis store
+    pub fn new(pool: PgPool, ttl: Duration) -> Self {
+        Self {
+            pool,
+            ttl: chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX),
+        }
+    }
+
+    // Deletes expired attempts, returning how many were removed
+    #[tracing::instrument(name = "Removing expired 2FA attempts from PostgreSQL", skip_all)]
+    pub async fn remove_expired(&self) -> Result<u64> {
+        remove_expired(&self.pool).await
+    }
+
+    // Calls `remove_expired` every `interval`; the task ends when the pool is closed
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !pool.is_closed() {
+                ticks.tick().await;
+                match remove_expired(&pool).await {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::debug!(removed, "Swept expired 2FA attempts"),
+                    Err(e) => tracing::warn!(error = ?e, "Failed to sweep expired 2FA attempts"),
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for PostgresTwoFACodeStore {
+    #[tracing::instrument(name = "Adding 2FA attempt to PostgreSQL", skip_all)]
+    async fn add_code(
+        &self,
+        login_attempt_id: LoginAttemptId,
+        attempt: TwoFAAttempt,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let expires_at = Utc::now()
+            .checked_add_signed(self.ttl)
+            .unwrap_or(chrono::DateTime::<Utc>::MAX_UTC);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO two_fa_attempts
+                (login_attempt_id, email, code, fingerprint, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (login_attempt_id) DO UPDATE SET
+                email = EXCLUDED.email,
+                code = EXCLUDED.code,
+                fingerprint = EXCLUDED.fingerprint,
+                created_at = EXCLUDED.created_at,
+                expires_at = EXCLUDED.expires_at,
+                failed_codes = 0
+            "#,
+            login_attempt_id.as_ref().expose_secret(),
+            attempt.email.as_ref().expose_secret(),
+            attempt.code.as_ref().expose_secret(),
+            attempt.fingerprint,
+            attempt.created_at,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to add 2FA attempt to PostgreSQL")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Removing 2FA attempt from PostgreSQL", skip_all)]
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        sqlx::query!(
+            "DELETE FROM two_fa_attempts WHERE login_attempt_id = $1",
+            login_attempt_id.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to remove 2FA attempt from PostgreSQL")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving 2FA attempt from PostgreSQL", skip_all)]
+    async fn get_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT email, code, fingerprint, created_at
+            FROM two_fa_attempts
+            WHERE login_attempt_id = $1 AND expires_at > $2
+            "#,
+            login_attempt_id.as_ref().expose_secret(),
+            Utc::now()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve 2FA attempt from PostgreSQL")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        Ok(TwoFAAttempt {
+            email: Email::parse(&row.email)
+                .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+            code: TwoFACode::parse(row.code)
+                .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+            created_at: row.created_at,
+            fingerprint: row.fingerprint,
+        })
+    }
+
+    #[tracing::instrument(name = "Taking 2FA attempt from PostgreSQL", skip_all)]
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError> {
+        // An expired attempt is deleted too, but not returned
+        let row = sqlx::query!(
+            r#"
+            DELETE FROM two_fa_attempts
+            WHERE login_attempt_id = $1
+            RETURNING email, code, fingerprint, created_at, expires_at > $2 AS "live!"
+            "#,
+            login_attempt_id.as_ref().expose_secret(),
+            Utc::now()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to take 2FA attempt from PostgreSQL")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let Some(row) = row.filter(|row| row.live) else {
+            return Ok(None);
+        };
+
+        Ok(Some(TwoFAAttempt {
+            email: Email::parse(&row.email)
+                .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+            code: TwoFACode::parse(row.code)
+                .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+            created_at: row.created_at,
+            fingerprint: row.fingerprint,
+        }))
+    }
+
+    #[tracing::instrument(name = "Recording failed 2FA code in PostgreSQL", skip_all)]
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        // Concurrent misses each get their own count, so exactly `max_failed_codes` are allowed
+        let failed_codes = sqlx::query_scalar!(
+            r#"
+            UPDATE two_fa_attempts SET failed_codes = failed_codes + 1
+            WHERE login_attempt_id = $1 AND expires_at > $2
+            RETURNING failed_codes
+            "#,
+            login_attempt_id.as_ref().expose_secret(),
+            Utc::now()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to record failed 2FA code in PostgreSQL")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        match failed_codes {
+            Some(failed_codes) if i64::from(failed_codes) >= i64::from(max_failed_codes) => {
+                self.remove_code(login_attempt_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+async fn remove_expired(pool: &PgPool) -> Result<u64> {
+    let result = sqlx::query!(
+        "DELETE FROM two_fa_attempts WHERE expires_at <= $1",
+        Utc::now()
+    )
+    .execute(pool)
+    .await
+    .wrap_err("Failed to remove expired 2FA attempts")?;
+
+    Ok(result.rows_affected())
+}
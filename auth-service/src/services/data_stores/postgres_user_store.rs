@@ -1,12 +1,17 @@
-use argon2::{
-    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
-    PasswordVerifier, Version,
-};
-use color_eyre::eyre::{eyre, Context, Result};
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Report};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
-use crate::domain::{Email, Password, User, UserStore, UserStoreError};
+use super::{
+    password_hashing::{compute_password_hash, verify_user_password},
+    user_roles::{group_roles, roles_by_email},
+};
+use crate::domain::{
+    Email, Password, PhoneNumber, Role, User, UserQuery, UserStore, UserStoreError,
+};
 
 pub struct PostgresUserStore {
     pool: PgPool,
@@ -16,12 +21,60 @@ impl PostgresUserStore {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+
+    // The roles of each of the users, users without any are left out
+    async fn roles_of(
+        &self,
+        emails: &[String],
+    ) -> Result<HashMap<String, Vec<Role>>, UserStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT ur.email, ur.role, rp.permission AS "permission?"
+            FROM user_roles ur
+            LEFT JOIN role_permissions rp ON rp.role = ur.role
+            WHERE ur.email = ANY($1)
+            ORDER BY ur.email, ur.role, rp.permission
+            "#,
+            emails
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve user roles from PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        Ok(roles_by_email(
+            rows.into_iter()
+                .map(|row| (row.email, row.role, row.permission)),
+        ))
+    }
+
+    // Tells which of the user and the role is missing
+    async fn user_and_role_exist(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM users WHERE email = $1) AS "user_exists!",
+                EXISTS (SELECT 1 FROM roles WHERE name = $2) AS "role_exists!"
+            "#,
+            email.as_ref().expose_secret(),
+            role
+        )
+        .fetch_one(&self.pool)
+        .await
+        .wrap_err("Failed to look up user and role in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        match (row.user_exists, row.role_exists) {
+            (false, _) => Err(UserStoreError::UserNotFound),
+            (_, false) => Err(UserStoreError::RoleNotFound),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
     #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
         let password_hash = compute_password_hash(user.password().expose_secret().to_string())
             .await
             .map_err(UserStoreError::UnexpectedError)?;
@@ -30,10 +83,16 @@ impl UserStore for PostgresUserStore {
         let password_hash_str: &str = password_hash.as_ref();
 
         sqlx::query!(
-            "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3)",
+            r#"
+            INSERT INTO users (email, password_hash, requires_2fa, phone_number, two_fa_channel)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
             email_str,
             password_hash_str,
-            user.requires_2fa()
+            user.requires_2fa(),
+            user.phone_number()
+                .map(|phone| phone.as_ref().expose_secret().as_str()),
+            user.two_fa_channel().as_str()
         )
         .execute(&self.pool)
         .await
@@ -44,83 +103,292 @@ impl UserStore for PostgresUserStore {
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        let row = sqlx::query!(
-            "SELECT * FROM users WHERE email = $1",
+        let row = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT email, password_hash, requires_2fa, phone_number, two_fa_channel,
+                tokens_valid_after, disabled, failed_login_attempts, locked_until
+            FROM users
+            WHERE email = $1
+            "#,
             email.as_ref().expose_secret()
         )
-        .fetch_one(&self.pool)
+        .fetch_optional(&self.pool)
         .await
-        .map_err(|_| UserStoreError::UserNotFound)?;
-
-        let user = User::new(
-            Email::parse(&row.email).map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
-            Password::parse(&Secret::new(row.password_hash))
-                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
-            row.requires_2fa,
-        );
+        .wrap_err("Failed to retrieve user from PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
 
-        Ok(user)
+        let mut roles = self.roles_of(std::slice::from_ref(&row.email)).await?;
+        let user = User::try_from(row).map_err(UserStoreError::UnexpectedError)?;
+        Ok(user.with_roles(
+            roles
+                .remove(email.as_ref().expose_secret())
+                .unwrap_or_default(),
+        ))
     }
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)]
     async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
-        let user = self.get_user(email).await?;
+        verify_user_password(self.get_user(email).await, password).await
+    }
 
-        verify_password_hash(
-            user.password().expose_secret().to_string(),
-            password.to_string(),
+    #[tracing::instrument(name = "Revoking user tokens in PostgreSQL", skip_all)]
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET tokens_valid_after = $2 WHERE email = $1",
+            email.as_ref().expose_secret(),
+            valid_after
         )
+        .execute(&self.pool)
         .await
-        .map_err(|_| UserStoreError::InvalidCredentials)?;
+        .wrap_err("Failed to revoke user tokens in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
 
-        Ok(())
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Listing users in PostgreSQL", skip_all)]
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError> {
+        let like_pattern = query.like_pattern();
+        let rows = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT email, password_hash, requires_2fa, phone_number, two_fa_channel,
+                tokens_valid_after, disabled, failed_login_attempts, locked_until
+            FROM users
+            WHERE ($1::TEXT IS NULL OR email > $1)
+                AND ($2::TEXT IS NULL OR email ILIKE $2 ESCAPE '\')
+            ORDER BY email
+            LIMIT $3
+            "#,
+            query.after.as_deref(),
+            like_pattern.as_deref(),
+            i64::from(query.limit)
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to list users in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        let emails: Vec<_> = rows.iter().map(|row| row.email.clone()).collect();
+        let mut roles = self.roles_of(&emails).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let user_roles = roles.remove(&row.email).unwrap_or_default();
+                User::try_from(row)
+                    .map(|user| user.with_roles(user_roles))
+                    .map_err(UserStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(name = "Disabling user in PostgreSQL", skip_all)]
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET disabled = $2 WHERE email = $1",
+            email.as_ref().expose_secret(),
+            disabled
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to disable user in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Setting user 2FA requirement in PostgreSQL", skip_all)]
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET requires_2fa = $2 WHERE email = $1",
+            email.as_ref().expose_secret(),
+            requires_2fa
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to set user 2FA requirement in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Recording failed login in PostgreSQL", skip_all)]
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        // Both assignments see the count from before the update, so concurrent failures each
+        // count once
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET failed_login_attempts = CASE
+                    WHEN failed_login_attempts + 1 >= $2 THEN 0
+                    ELSE failed_login_attempts + 1
+                END,
+                locked_until = CASE
+                    WHEN failed_login_attempts + 1 >= $2 THEN $3
+                    ELSE locked_until
+                END
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            i32::try_from(max_attempts).unwrap_or(i32::MAX),
+            lock_until
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to record failed login in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Resetting user lockout in PostgreSQL", skip_all)]
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET failed_login_attempts = 0, locked_until = NULL WHERE email = $1",
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to reset user lockout in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM users WHERE email = $1",
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to delete user from PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Assigning role in PostgreSQL", skip_all)]
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO user_roles (email, role)
+            SELECT users.email, roles.name
+            FROM users, roles
+            WHERE users.email = $1 AND roles.name = $2
+            ON CONFLICT DO NOTHING
+            "#,
+            email.as_ref().expose_secret(),
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to assign role in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        // Nothing is inserted for a missing user or role, or a role the user already has
+        match result.rows_affected() {
+            0 => self.user_and_role_exist(email, role).await,
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Removing role in PostgreSQL", skip_all)]
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM user_roles WHERE email = $1 AND role = $2",
+            email.as_ref().expose_secret(),
+            role
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to remove role in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => match self.user_and_role_exist(email, role).await {
+                Err(UserStoreError::UserNotFound) => Err(UserStoreError::UserNotFound),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Listing roles in PostgreSQL", skip_all)]
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT roles.name, rp.permission AS "permission?"
+            FROM roles
+            LEFT JOIN role_permissions rp ON rp.role = roles.name
+            ORDER BY roles.name, rp.permission
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to list roles in PostgreSQL")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        Ok(group_roles(
+            rows.into_iter().map(|row| (row.name, row.permission)),
+        ))
+    }
+}
+
+// Updates report a missing user by touching no rows
+fn found(rows_affected: u64) -> Result<(), UserStoreError> {
+    match rows_affected {
+        0 => Err(UserStoreError::UserNotFound),
+        _ => Ok(()),
     }
 }
 
-// Helper function to verify if a given password matches an expected hash
-#[tracing::instrument(name = "Verify password hash", skip_all)]
-async fn verify_password_hash(
-    expected_password_hash: String,
-    password_candidate: String,
-) -> Result<()> {
-    let current_span: tracing::Span = tracing::Span::current();
-
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(&expected_password_hash)?;
-
-            Argon2::default()
-                .verify_password(password_candidate.as_bytes(), &expected_password_hash)
-                .wrap_err("Failed to verify password hash")
-        })
-    })
-    .await;
-
-    result?
+struct UserRow {
+    email: String,
+    password_hash: String,
+    requires_2fa: bool,
+    phone_number: Option<String>,
+    two_fa_channel: String,
+    tokens_valid_after: Option<DateTime<Utc>>,
+    disabled: bool,
+    failed_login_attempts: i32,
+    locked_until: Option<DateTime<Utc>>,
 }
 
-// Helper function to hash passwords before persisting them in the database
-#[tracing::instrument(name = "Computing password hash", skip_all)]
-async fn compute_password_hash(password: String) -> Result<String> {
-    let current_span: tracing::Span = tracing::Span::current();
-
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.as_bytes(), &salt)?
-            .to_string();
-
-            Ok(password_hash)
-        })
-    })
-    .await;
-
-    result?
+impl TryFrom<UserRow> for User {
+    type Error = Report;
+
+    fn try_from(row: UserRow) -> Result<Self, Self::Error> {
+        let mut user = User::new(
+            Email::parse(&row.email)?,
+            Password::parse(&Secret::new(row.password_hash))?,
+            row.requires_2fa,
+        )
+        .with_disabled(row.disabled)
+        .with_lockout(u32::try_from(row.failed_login_attempts)?, row.locked_until);
+        if let Some(phone_number) = row.phone_number {
+            user = user.with_phone_number(PhoneNumber::parse(&phone_number)?);
+        }
+        if let Some(valid_after) = row.tokens_valid_after {
+            user = user.with_tokens_valid_after(valid_after);
+        }
+
+        user.with_two_fa_channel(row.two_fa_channel.parse()?)
+    }
 }
@@ -1,21 +1,18 @@
-use std::sync::Arc;
-
+use chrono::Utc;
 use color_eyre::eyre::Context;
-use redis::{Commands, Connection};
-use secrecy::{ExposeSecret, Secret};
-use tokio::sync::RwLock;
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions, Value};
 
 use crate::{
     domain::{BannedTokenStore, BannedTokenStoreError},
-    utils::TOKEN_TTL_SECONDS,
+    utils::Claims,
 };
 
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+    pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
 }
@@ -23,27 +20,38 @@ impl RedisBannedTokenStore {
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
     #[tracing::instrument(name = "Add Token", skip_all)]
-    async fn add_token(&mut self, token: &Secret<String>) -> Result<(), BannedTokenStoreError> {
-        let key = get_key(token.expose_secret());
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError> {
+        // The ban lasts as long as the token would still be accepted
+        let remaining = (claims.expires_at() - Utc::now()).num_milliseconds();
+        if remaining <= 0 {
+            return Ok(());
+        }
 
-        self.conn
-            .write()
+        // `SET NX` makes banning atomic, so only one of several concurrent bans succeeds
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::PX(remaining as usize));
+        let result = self
+            .conn
+            .clone()
+            .set_options::<_, _, Value>(get_key(&claims.jti), true, options)
             .await
-            .set_ex::<_, _, ()>(key, true, TOKEN_TTL_SECONDS as u64)
-            .map_err(|e| BannedTokenStoreError::UnexpectedError(e.into()))?;
+            .wrap_err("Failed to ban token in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
 
-        Ok(())
+        match result {
+            Value::Nil => Err(BannedTokenStoreError::TokenAlreadyExists),
+            _ => Ok(()),
+        }
     }
 
     #[tracing::instrument(name = "Check Token", skip_all)]
-    async fn is_token_banned(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        let key = get_key(token.expose_secret());
-
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError> {
         let result = self
             .conn
-            .write()
+            .clone()
+            .exists::<_, bool>(get_key(&claims.jti))
             .await
-            .exists::<_, bool>(key)
             .wrap_err("Failed to check if token is banned in Redis")
             .map_err(BannedTokenStoreError::UnexpectedError)?;
 
@@ -53,6 +61,6 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 
-fn get_key(token: &str) -> String {
-    format!("{BANNED_TOKEN_KEY_PREFIX}{token}")
+fn get_key(jti: &str) -> String {
+    format!("{BANNED_TOKEN_KEY_PREFIX}{jti}")
 }
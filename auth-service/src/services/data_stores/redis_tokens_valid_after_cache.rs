@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context, Result};
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use secrecy::ExposeSecret;
+
+use crate::domain::{Email, TokensValidAfterCache};
+
+// Entries expire after `ttl_seconds`, the user store stays the source of truth
+pub struct RedisTokensValidAfterCache {
+    conn: ConnectionManager,
+    ttl_seconds: u64,
+}
+
+impl RedisTokensValidAfterCache {
+    pub fn new(conn: ConnectionManager, ttl_seconds: u64) -> Self {
+        Self { conn, ttl_seconds }
+    }
+
+    async fn set_with(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+        options: SetOptions,
+    ) -> Result<()> {
+        self.conn
+            .clone()
+            .set_options::<_, _, ()>(get_key(email), valid_after.timestamp_micros(), options)
+            .await
+            .wrap_err("Failed to cache tokens_valid_after in Redis")
+    }
+}
+
+#[async_trait::async_trait]
+impl TokensValidAfterCache for RedisTokensValidAfterCache {
+    #[tracing::instrument(name = "Get Cached Tokens Valid After", skip_all)]
+    async fn get(&self, email: &Email) -> Result<Option<DateTime<Utc>>> {
+        let micros = self
+            .conn
+            .clone()
+            .get::<_, Option<i64>>(get_key(email))
+            .await
+            .wrap_err("Failed to get tokens_valid_after from Redis")?;
+
+        micros
+            .map(|micros| {
+                DateTime::from_timestamp_micros(micros)
+                    .ok_or_else(|| eyre!("Cached tokens_valid_after is out of range: {micros}"))
+            })
+            .transpose()
+    }
+
+    #[tracing::instrument(name = "Fill Cached Tokens Valid After", skip_all)]
+    async fn fill(&self, email: &Email, valid_after: DateTime<Utc>) -> Result<()> {
+        // `SET NX` keeps a value set by a concurrent revocation over the one read before it
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(self.ttl_seconds as usize));
+        self.set_with(email, valid_after, options).await
+    }
+
+    #[tracing::instrument(name = "Set Cached Tokens Valid After", skip_all)]
+    async fn set(&self, email: &Email, valid_after: DateTime<Utc>) -> Result<()> {
+        let options =
+            SetOptions::default().with_expiration(SetExpiry::EX(self.ttl_seconds as usize));
+        self.set_with(email, valid_after, options).await
+    }
+}
+
+const TOKENS_VALID_AFTER_KEY_PREFIX: &str = "tokens_valid_after:";
+
+fn get_key(email: &Email) -> String {
+    format!(
+        "{TOKENS_VALID_AFTER_KEY_PREFIX}{}",
+        email.as_ref().expose_secret()
+    )
+}
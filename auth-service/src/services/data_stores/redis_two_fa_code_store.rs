@@ -1,20 +1,21 @@
-use std::sync::Arc;
-
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{eyre, Context};
-use redis::{Commands, Connection};
+use redis::{aio::ConnectionManager, AsyncCommands};
 use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
 
-use crate::domain::{Email, LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+use crate::domain::{
+    Email, LoginAttemptId, TwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+};
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
+    ttl_seconds: u64,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: ConnectionManager, ttl_seconds: u64) -> Self {
+        Self { conn, ttl_seconds }
     }
 }
 
@@ -22,24 +23,30 @@ impl RedisTwoFACodeStore {
 impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Add Code", skip_all)]
     async fn add_code(
-        &mut self,
-        email: Email,
+        &self,
         login_attempt_id: LoginAttemptId,
-        code: TwoFACode,
+        attempt: TwoFAAttempt,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-        let two_fa_tuple = TwoFATuple(
-            login_attempt_id.as_ref().expose_secret().to_string(),
-            code.as_ref().expose_secret().to_string(),
-        );
-        let two_fa_tuple_json = serde_json::to_string(&two_fa_tuple)
-            .wrap_err("Failed to serialize 2FA tuple")
+        let key = get_key(&login_attempt_id);
+        let stored_attempt = StoredAttempt {
+            email: attempt.email.as_ref().expose_secret().to_owned(),
+            code: attempt.code.as_ref().expose_secret().to_owned(),
+            created_at: attempt.created_at,
+            fingerprint: attempt.fingerprint,
+        };
+        let stored_attempt_json = serde_json::to_string(&stored_attempt)
+            .wrap_err("Failed to serialize 2FA attempt")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        self.conn
-            .write()
+        // A replaced attempt starts its count of wrong codes over
+        redis::pipe()
+            .atomic()
+            .set_ex(key, stored_attempt_json, self.ttl_seconds)
+            .ignore()
+            .del(get_failed_codes_key(&login_attempt_id))
+            .ignore()
+            .query_async::<_, ()>(&mut self.conn.clone())
             .await
-            .set_ex::<_, _, ()>(key, two_fa_tuple_json, TEN_MINUTES_IN_SECONDS)
             .wrap_err("Failed to set 2FA code in Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
@@ -47,12 +54,18 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     }
 
     #[tracing::instrument(name = "Remove Code", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let keys = [
+            get_key(login_attempt_id),
+            get_failed_codes_key(login_attempt_id),
+        ];
         self.conn
-            .write()
+            .clone()
+            .del::<_, ()>(&keys)
             .await
-            .del::<_, ()>(key)
             .wrap_err("Failed to remove 2FA code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
@@ -62,34 +75,111 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Get Code", skip_all)]
     async fn get_code(
         &self,
-        email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(email);
-        let two_fa_tuple_json = self
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+        let key = get_key(login_attempt_id);
+        let stored_attempt_json = self
             .conn
-            .write()
-            .await
+            .clone()
             .get::<_, String>(key)
+            .await
             .map_err(|_| TwoFACodeStoreError::LoginAttemptIdNotFound)?;
 
-        let two_fa_tuple: TwoFATuple = serde_json::from_str(&two_fa_tuple_json)
-            .wrap_err("Failed to deserialize 2FA tuple")
+        parse_attempt(&stored_attempt_json)
+    }
+
+    #[tracing::instrument(name = "Take Code", skip_all)]
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError> {
+        let key = get_key(login_attempt_id);
+        let stored_attempt_json = self
+            .conn
+            .clone()
+            .get_del::<_, Option<String>>(key)
+            .await
+            .wrap_err("Failed to take 2FA code from Redis")
             .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-        let login_attempt_id = LoginAttemptId::parse(two_fa_tuple.0.clone())
-            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
-        let two_fa_code = TwoFACode::parse(two_fa_tuple.1.clone())
-            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
-        Ok((login_attempt_id, two_fa_code))
+        stored_attempt_json
+            .map(|json| parse_attempt(&json))
+            .transpose()
+    }
+
+    #[tracing::instrument(name = "Record Failed Code", skip_all)]
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        let mut conn = self.conn.clone();
+        let exists = conn
+            .exists::<_, bool>(get_key(login_attempt_id))
+            .await
+            .wrap_err("Failed to look up 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        if !exists {
+            return Ok(false);
+        }
+
+        // `INCR` gives concurrent misses their own count, so exactly `max_failed_codes` are
+        // allowed. The count expires with the attempt at the latest.
+        let failed_codes_key = get_failed_codes_key(login_attempt_id);
+        let (failed_codes,) = redis::pipe()
+            .atomic()
+            .incr(&failed_codes_key, 1)
+            .expire(&failed_codes_key, self.ttl_seconds as i64)
+            .ignore()
+            .query_async::<_, (u32,)>(&mut conn)
+            .await
+            .wrap_err("Failed to count failed 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        if failed_codes < max_failed_codes {
+            return Ok(false);
+        }
+        self.remove_code(login_attempt_id).await?;
+        Ok(true)
     }
 }
 
+fn parse_attempt(stored_attempt_json: &str) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+    let stored_attempt: StoredAttempt = serde_json::from_str(stored_attempt_json)
+        .wrap_err("Failed to deserialize 2FA attempt")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+    Ok(TwoFAAttempt {
+        email: Email::parse(&stored_attempt.email)
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+        code: TwoFACode::parse(stored_attempt.code)
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?,
+        created_at: stored_attempt.created_at,
+        fingerprint: stored_attempt.fingerprint,
+    })
+}
+
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct StoredAttempt {
+    email: String,
+    code: String,
+    created_at: DateTime<Utc>,
+    fingerprint: String,
+}
+
+const TWO_FA_ATTEMPT_PREFIX: &str = "two_fa_attempt:";
+const TWO_FA_FAILED_CODES_PREFIX: &str = "two_fa_failed_codes:";
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
-const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+fn get_key(login_attempt_id: &LoginAttemptId) -> String {
+    format!(
+        "{TWO_FA_ATTEMPT_PREFIX}{}",
+        login_attempt_id.as_ref().expose_secret()
+    )
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{TWO_FA_CODE_PREFIX}{}", email.as_ref().expose_secret())
+fn get_failed_codes_key(login_attempt_id: &LoginAttemptId) -> String {
+    format!(
+        "{TWO_FA_FAILED_CODES_PREFIX}{}",
+        login_attempt_id.as_ref().expose_secret()
+    )
 }
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::{Context, Result};
+use sqlx::SqlitePool;
+use tokio::task::JoinHandle;
+
+use super::unix_micros::to_unix_micros;
+use crate::{
+    domain::{BannedTokenStore, BannedTokenStoreError},
+    utils::Claims,
+};
+
+pub struct SqliteBannedTokenStore {
+    pool: SqlitePool,
+}
+
+impl SqliteBannedTokenStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    // Deletes expired bans, returning how many were removed
+    #[tracing::instrument(name = "Removing expired banned tokens from SQLite", skip_all)]
+    pub async fn remove_expired(&self) -> Result<u64> {
+        remove_expired(&self.pool).await
+    }
+
+    // Calls `remove_expired` every `interval`; the task ends when the pool is closed
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !pool.is_closed() {
+                ticks.tick().await;
+                match remove_expired(&pool).await {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::debug!(removed, "Swept expired banned tokens"),
+                    Err(e) => tracing::warn!(error = ?e, "Failed to sweep expired banned tokens"),
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for SqliteBannedTokenStore {
+    #[tracing::instrument(name = "Adding banned token to SQLite", skip_all)]
+    async fn add_token(&self, claims: &Claims) -> Result<(), BannedTokenStoreError> {
+        let now = to_unix_micros(Utc::now());
+
+        // An expired ban that has not been swept yet is replaced, a live one is kept
+        let result = sqlx::query(
+            r#"
+            INSERT INTO banned_tokens (jti, expires_at)
+            VALUES (?1, ?2)
+            ON CONFLICT (jti) DO UPDATE SET expires_at = excluded.expires_at
+            WHERE banned_tokens.expires_at <= ?3
+            "#,
+        )
+        .bind(&claims.jti)
+        .bind(to_unix_micros(claims.expires_at()))
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to ban token in SQLite")
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(BannedTokenStoreError::TokenAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Checking banned token in SQLite", skip_all)]
+    async fn is_token_banned(&self, claims: &Claims) -> Result<bool, BannedTokenStoreError> {
+        let banned = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM banned_tokens WHERE jti = ? AND expires_at > ?
+            )
+            "#,
+        )
+        .bind(&claims.jti)
+        .bind(to_unix_micros(Utc::now()))
+        .fetch_one(&self.pool)
+        .await
+        .wrap_err("Failed to check if token is banned in SQLite")
+        .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(banned)
+    }
+}
+
+async fn remove_expired(pool: &SqlitePool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM banned_tokens WHERE expires_at <= ?")
+        .bind(to_unix_micros(Utc::now()))
+        .execute(pool)
+        .await
+        .wrap_err("Failed to remove expired banned tokens")?;
+
+    Ok(result.rows_affected())
+}
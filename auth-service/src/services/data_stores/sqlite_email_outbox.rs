@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Result};
+use secrecy::ExposeSecret;
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+use uuid::Uuid;
+
+use super::unix_micros::{duration_micros, from_unix_micros, to_unix_micros};
+use crate::domain::{
+    DeliveryStatus, Email, EmailContent, EmailOutbox, EmailOutboxError, OutboxEmail, PendingEmail,
+};
+
+pub struct SqliteEmailOutbox {
+    pool: SqlitePool,
+}
+
+impl SqliteEmailOutbox {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutbox for SqliteEmailOutbox {
+    #[tracing::instrument(name = "Enqueueing email in SQLite outbox", skip_all)]
+    async fn enqueue(&self, email: OutboxEmail) -> Result<bool, EmailOutboxError> {
+        let now = to_unix_micros(Utc::now());
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO email_outbox
+                (id, idempotency_key, recipient, subject, html_body, text_body, tag, next_attempt_at, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+            ON CONFLICT (idempotency_key) DO NOTHING
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&email.idempotency_key)
+        .bind(email.recipient.as_ref().expose_secret())
+        .bind(&email.content.subject)
+        .bind(&email.content.html_body)
+        .bind(&email.content.text_body)
+        .bind(&email.tag)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to enqueue email")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(name = "Retrieving email delivery status from SQLite", skip_all)]
+    async fn status(
+        &self,
+        idempotency_key: &str,
+    ) -> Result<Option<DeliveryStatus>, EmailOutboxError> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, idempotency_key, status, attempts, last_error, next_attempt_at, created_at, sent_at
+            FROM email_outbox
+            WHERE idempotency_key = ?
+            "#,
+        )
+        .bind(idempotency_key)
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve email delivery status")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        row.map(|row| status_from_row(&row))
+            .transpose()
+            .map_err(EmailOutboxError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Claiming due emails from SQLite outbox", skip_all)]
+    async fn claim_due(
+        &self,
+        limit: u32,
+        lease: Duration,
+    ) -> Result<Vec<PendingEmail>, EmailOutboxError> {
+        let now = to_unix_micros(Utc::now());
+
+        // SQLite serializes writers, so claiming in one statement is enough to keep several
+        // workers from claiming the same email
+        let rows = sqlx::query(
+            r#"
+            UPDATE email_outbox
+            SET attempts = attempts + 1, next_attempt_at = ?2
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= ?3
+                ORDER BY next_attempt_at
+                LIMIT ?1
+            )
+            RETURNING id, recipient, subject, html_body, text_body, tag, attempts
+            "#,
+        )
+        .bind(i64::from(limit))
+        .bind(now.saturating_add(duration_micros(lease)))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to claim due emails")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        rows.iter()
+            .map(pending_email_from_row)
+            .collect::<Result<_>>()
+            .map_err(EmailOutboxError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as sent in SQLite", skip_all)]
+    async fn mark_sent(&self, id: Uuid) -> Result<(), EmailOutboxError> {
+        // The bodies may contain 2FA codes, there is no reason to keep them once delivered
+        sqlx::query(
+            r#"
+            UPDATE email_outbox
+            SET status = 'sent', sent_at = ?2, last_error = NULL, html_body = NULL, text_body = NULL
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id.to_string())
+        .bind(to_unix_micros(Utc::now()))
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to mark email as sent")
+        .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Marking outbox email as failed in SQLite", skip_all)]
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxError> {
+        let query = match retry_at {
+            Some(retry_at) => sqlx::query(
+                "UPDATE email_outbox SET last_error = ?2, next_attempt_at = ?3 WHERE id = ?1",
+            )
+            .bind(id.to_string())
+            .bind(error)
+            .bind(to_unix_micros(retry_at)),
+            // Dead emails keep their error for inspection, but not their bodies
+            None => sqlx::query(
+                r#"
+                UPDATE email_outbox
+                SET status = 'dead', last_error = ?2, html_body = NULL, text_body = NULL
+                WHERE id = ?1
+                "#,
+            )
+            .bind(id.to_string())
+            .bind(error),
+        };
+
+        query
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to mark email as failed")
+            .map_err(EmailOutboxError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+fn status_from_row(row: &SqliteRow) -> Result<DeliveryStatus> {
+    Ok(DeliveryStatus {
+        id: row.try_get::<&str, _>("id")?.parse()?,
+        idempotency_key: row.try_get("idempotency_key")?,
+        state: row.try_get::<&str, _>("status")?.parse()?,
+        attempts: row.try_get("attempts")?,
+        last_error: row.try_get("last_error")?,
+        next_attempt_at: from_unix_micros(row.try_get("next_attempt_at")?)?,
+        created_at: from_unix_micros(row.try_get("created_at")?)?,
+        sent_at: row
+            .try_get::<Option<i64>, _>("sent_at")?
+            .map(from_unix_micros)
+            .transpose()?,
+    })
+}
+
+fn pending_email_from_row(row: &SqliteRow) -> Result<PendingEmail> {
+    Ok(PendingEmail {
+        id: row.try_get::<&str, _>("id")?.parse()?,
+        recipient: Email::parse(row.try_get("recipient")?)?,
+        content: EmailContent {
+            subject: row.try_get("subject")?,
+            html_body: row
+                .try_get::<Option<String>, _>("html_body")?
+                .unwrap_or_default(),
+            text_body: row
+                .try_get::<Option<String>, _>("text_body")?
+                .unwrap_or_default(),
+        },
+        tag: row.try_get("tag")?,
+        attempts: row.try_get("attempts")?,
+    })
+}
@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::{eyre, Context, Result};
+use secrecy::ExposeSecret;
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+use tokio::task::JoinHandle;
+
+use super::unix_micros::{duration_micros, from_unix_micros, to_unix_micros};
+use crate::domain::{
+    Email, LoginAttemptId, TwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+};
+
+pub struct SqliteTwoFACodeStore {
+    pool: SqlitePool,
+    ttl_micros: i64,
+}
+
+impl SqliteTwoFACodeStore {
+    // Pending logins are forgotten `ttl` after they started, like with the Redis store
+    pub fn new(pool: SqlitePool, ttl: Duration) -> Self {
+        Self {
+            pool,
+            ttl_micros: duration_micros(ttl),
+        }
+    }
+
+    // Deletes expired attempts, returning how many were removed
+    #[tracing::instrument(name = "Removing expired 2FA attempts from SQLite", skip_all)]
+    pub async fn remove_expired(&self) -> Result<u64> {
+        remove_expired(&self.pool).await
+    }
+
+    // Calls `remove_expired` every `interval`; the task ends when the pool is closed
+    pub fn spawn_sweeper(&self, interval: Duration) -> JoinHandle<()> {
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            while !pool.is_closed() {
+                ticks.tick().await;
+                match remove_expired(&pool).await {
+                    Ok(0) => {}
+                    Ok(removed) => tracing::debug!(removed, "Swept expired 2FA attempts"),
+                    Err(e) => tracing::warn!(error = ?e, "Failed to sweep expired 2FA attempts"),
+                }
+            }
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for SqliteTwoFACodeStore {
+    #[tracing::instrument(name = "Adding 2FA attempt to SQLite", skip_all)]
+    async fn add_code(
+        &self,
+        login_attempt_id: LoginAttemptId,
+        attempt: TwoFAAttempt,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let expires_at = to_unix_micros(Utc::now()).saturating_add(self.ttl_micros);
+
+        sqlx::query(
+            r#"
+            INSERT INTO two_fa_attempts
+                (login_attempt_id, email, code, fingerprint, created_at, expires_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (login_attempt_id) DO UPDATE SET
+                email = excluded.email,
+                code = excluded.code,
+                fingerprint = excluded.fingerprint,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at,
+                failed_codes = 0
+            "#,
+        )
+        .bind(login_attempt_id.as_ref().expose_secret())
+        .bind(attempt.email.as_ref().expose_secret())
+        .bind(attempt.code.as_ref().expose_secret())
+        .bind(&attempt.fingerprint)
+        .bind(to_unix_micros(attempt.created_at))
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to add 2FA attempt to SQLite")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Removing 2FA attempt from SQLite", skip_all)]
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        sqlx::query("DELETE FROM two_fa_attempts WHERE login_attempt_id = ?")
+            .bind(login_attempt_id.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to remove 2FA attempt from SQLite")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving 2FA attempt from SQLite", skip_all)]
+    async fn get_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<TwoFAAttempt, TwoFACodeStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT email, code, fingerprint, created_at
+            FROM two_fa_attempts
+            WHERE login_attempt_id = ? AND expires_at > ?
+            "#,
+        )
+        .bind(login_attempt_id.as_ref().expose_secret())
+        .bind(to_unix_micros(Utc::now()))
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve 2FA attempt from SQLite")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        attempt_from_row(&row).map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Taking 2FA attempt from SQLite", skip_all)]
+    async fn take_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<Option<TwoFAAttempt>, TwoFACodeStoreError> {
+        // An expired attempt is deleted too, but not returned
+        let row = sqlx::query(
+            r#"
+            DELETE FROM two_fa_attempts
+            WHERE login_attempt_id = ?
+            RETURNING email, code, fingerprint, created_at, expires_at > ? AS live
+            "#,
+        )
+        .bind(login_attempt_id.as_ref().expose_secret())
+        .bind(to_unix_micros(Utc::now()))
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to take 2FA attempt from SQLite")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let live: bool = row
+            .try_get("live")
+            .map_err(|e| TwoFACodeStoreError::UnexpectedError(eyre!(e)))?;
+        if !live {
+            return Ok(None);
+        }
+
+        attempt_from_row(&row)
+            .map(Some)
+            .map_err(TwoFACodeStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Recording failed 2FA code in SQLite", skip_all)]
+    async fn record_failed_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        max_failed_codes: u32,
+    ) -> Result<bool, TwoFACodeStoreError> {
+        // Concurrent misses each get their own count, so exactly `max_failed_codes` are allowed
+        let failed_codes: Option<i64> = sqlx::query_scalar(
+            r#"
+            UPDATE two_fa_attempts SET failed_codes = failed_codes + 1
+            WHERE login_attempt_id = ? AND expires_at > ?
+            RETURNING failed_codes
+            "#,
+        )
+        .bind(login_attempt_id.as_ref().expose_secret())
+        .bind(to_unix_micros(Utc::now()))
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to record failed 2FA code in SQLite")
+        .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        match failed_codes {
+            Some(failed_codes) if failed_codes >= i64::from(max_failed_codes) => {
+                self.remove_code(login_attempt_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+fn attempt_from_row(row: &SqliteRow) -> Result<TwoFAAttempt> {
+    Ok(TwoFAAttempt {
+        email: Email::parse(row.try_get("email")?)?,
+        code: TwoFACode::parse(row.try_get("code")?)?,
+        created_at: from_unix_micros(row.try_get("created_at")?)?,
+        fingerprint: row.try_get("fingerprint")?,
+    })
+}
+
+async fn remove_expired(pool: &SqlitePool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM two_fa_attempts WHERE expires_at <= ?")
+        .bind(to_unix_micros(Utc::now()))
+        .execute(pool)
+        .await
+        .wrap_err("Failed to remove expired 2FA attempts")?;
+
+    Ok(result.rows_affected())
+}
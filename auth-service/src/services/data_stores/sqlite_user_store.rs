@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+
+use super::{
+    password_hashing::{compute_password_hash, verify_user_password},
+    unix_micros::{from_unix_micros, to_unix_micros},
+    user_roles::{group_roles, roles_by_email},
+};
+use crate::domain::{
+    Email, Password, PhoneNumber, Role, User, UserQuery, UserStore, UserStoreError,
+};
+
+// The compile-time checked `query!` macros are tied to PostgreSQL, so the SQLite stores use
+// plain queries.
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+}
+
+impl SqliteUserStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    // The roles of each of the users, users without any are left out
+    async fn roles_of(
+        &self,
+        emails: &[String],
+    ) -> Result<HashMap<String, Vec<Role>>, UserStoreError> {
+        // The emails go in as one JSON array, SQLite has no array parameters
+        let emails = serde_json::to_string(emails)
+            .wrap_err("Failed to encode emails")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT ur.email, ur.role, rp.permission
+            FROM user_roles ur
+            LEFT JOIN role_permissions rp ON rp.role = ur.role
+            WHERE ur.email IN (SELECT value FROM json_each(?))
+            ORDER BY ur.email, ur.role, rp.permission
+            "#,
+        )
+        .bind(emails)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve user roles from SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                Ok((
+                    row.try_get("email")?,
+                    row.try_get("role")?,
+                    row.try_get("permission")?,
+                ))
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .wrap_err("Failed to read user roles from SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        Ok(roles_by_email(rows))
+    }
+
+    // Tells which of the user and the role is missing
+    async fn user_and_role_exist(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM users WHERE email = ?1) AS user_exists,
+                EXISTS (SELECT 1 FROM roles WHERE name = ?2) AS role_exists
+            "#,
+        )
+        .bind(email.as_ref().expose_secret())
+        .bind(role)
+        .fetch_one(&self.pool)
+        .await
+        .wrap_err("Failed to look up user and role in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        let exists = |column| {
+            row.try_get::<bool, _>(column)
+                .wrap_err("Failed to read user and role lookup from SQLite")
+                .map_err(UserStoreError::UnexpectedError)
+        };
+        match (exists("user_exists")?, exists("role_exists")?) {
+            (false, _) => Err(UserStoreError::UserNotFound),
+            (_, false) => Err(UserStoreError::RoleNotFound),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqliteUserStore {
+    #[tracing::instrument(name = "Adding user to SQLite", skip_all)]
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let password_hash = compute_password_hash(user.password().expose_secret().to_string())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO users (email, password_hash, requires_2fa, phone_number, two_fa_channel)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (email) DO NOTHING
+            "#,
+        )
+        .bind(user.email().expose_secret())
+        .bind(password_hash)
+        .bind(user.requires_2fa())
+        .bind(
+            user.phone_number()
+                .map(|phone| phone.as_ref().expose_secret().as_str()),
+        )
+        .bind(user.two_fa_channel().as_str())
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to add user to SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving user from SQLite", skip_all)]
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        let row = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, phone_number, two_fa_channel,
+                tokens_valid_after, disabled, failed_login_attempts, locked_until
+            FROM users
+            WHERE email = ?
+            "#,
+        )
+        .bind(email.as_ref().expose_secret())
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("Failed to retrieve user from SQLite")
+        .map_err(UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let email = email.as_ref().expose_secret();
+        let mut roles = self.roles_of(&[email.to_owned()]).await?;
+        let user = user_from_row(&row).map_err(UserStoreError::UnexpectedError)?;
+        Ok(user.with_roles(roles.remove(email).unwrap_or_default()))
+    }
+
+    #[tracing::instrument(name = "Validating user credentials in SQLite", skip_all)]
+    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
+        verify_user_password(self.get_user(email).await, password).await
+    }
+
+    #[tracing::instrument(name = "Revoking user tokens in SQLite", skip_all)]
+    async fn set_tokens_valid_after(
+        &self,
+        email: &Email,
+        valid_after: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET tokens_valid_after = ? WHERE email = ?")
+            .bind(to_unix_micros(valid_after))
+            .bind(email.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to revoke user tokens in SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Listing users in SQLite", skip_all)]
+    async fn list_users(&self, query: &UserQuery) -> Result<Vec<User>, UserStoreError> {
+        // LIKE ignores case for ASCII in SQLite
+        let rows = sqlx::query(
+            r#"
+            SELECT email, password_hash, requires_2fa, phone_number, two_fa_channel,
+                tokens_valid_after, disabled, failed_login_attempts, locked_until
+            FROM users
+            WHERE (?1 IS NULL OR email > ?1)
+                AND (?2 IS NULL OR email LIKE ?2 ESCAPE '\')
+            ORDER BY email
+            LIMIT ?3
+            "#,
+        )
+        .bind(query.after.as_deref())
+        .bind(query.like_pattern())
+        .bind(i64::from(query.limit))
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to list users in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        let users = rows
+            .iter()
+            .map(|row| user_from_row(row).map_err(UserStoreError::UnexpectedError))
+            .collect::<Result<Vec<_>, _>>()?;
+        let emails: Vec<_> = users
+            .iter()
+            .map(|user| user.email().expose_secret().to_owned())
+            .collect();
+        let mut roles = self.roles_of(&emails).await?;
+
+        Ok(users
+            .into_iter()
+            .map(|user| {
+                let user_roles = roles
+                    .remove(user.email().expose_secret())
+                    .unwrap_or_default();
+                user.with_roles(user_roles)
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(name = "Disabling user in SQLite", skip_all)]
+    async fn set_disabled(&self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET disabled = ? WHERE email = ?")
+            .bind(disabled)
+            .bind(email.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to disable user in SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Setting user 2FA requirement in SQLite", skip_all)]
+    async fn set_requires_2fa(
+        &self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET requires_2fa = ? WHERE email = ?")
+            .bind(requires_2fa)
+            .bind(email.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to set user 2FA requirement in SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Recording failed login in SQLite", skip_all)]
+    async fn record_failed_login(
+        &self,
+        email: &Email,
+        max_attempts: u32,
+        lock_until: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        // Both assignments see the count from before the update
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET failed_login_attempts = CASE
+                    WHEN failed_login_attempts + 1 >= ?2 THEN 0
+                    ELSE failed_login_attempts + 1
+                END,
+                locked_until = CASE
+                    WHEN failed_login_attempts + 1 >= ?2 THEN ?3
+                    ELSE locked_until
+                END
+            WHERE email = ?1
+            "#,
+        )
+        .bind(email.as_ref().expose_secret())
+        .bind(i64::from(max_attempts))
+        .bind(to_unix_micros(lock_until))
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to record failed login in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Resetting user lockout in SQLite", skip_all)]
+    async fn reset_lockout(&self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            "UPDATE users SET failed_login_attempts = 0, locked_until = NULL WHERE email = ?",
+        )
+        .bind(email.as_ref().expose_secret())
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to reset user lockout in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Deleting user from SQLite", skip_all)]
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query("DELETE FROM users WHERE email = ?")
+            .bind(email.as_ref().expose_secret())
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to delete user from SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        found(result.rows_affected())
+    }
+
+    #[tracing::instrument(name = "Assigning role in SQLite", skip_all)]
+    async fn assign_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO user_roles (email, role)
+            SELECT users.email, roles.name
+            FROM users, roles
+            WHERE users.email = ?1 AND roles.name = ?2
+            "#,
+        )
+        .bind(email.as_ref().expose_secret())
+        .bind(role)
+        .execute(&self.pool)
+        .await
+        .wrap_err("Failed to assign role in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        // Nothing is inserted for a missing user or role, or a role the user already has
+        match result.rows_affected() {
+            0 => self.user_and_role_exist(email, role).await,
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Removing role in SQLite", skip_all)]
+    async fn remove_role(&self, email: &Email, role: &str) -> Result<(), UserStoreError> {
+        let result = sqlx::query("DELETE FROM user_roles WHERE email = ? AND role = ?")
+            .bind(email.as_ref().expose_secret())
+            .bind(role)
+            .execute(&self.pool)
+            .await
+            .wrap_err("Failed to remove role in SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        match result.rows_affected() {
+            0 => match self.user_and_role_exist(email, role).await {
+                Err(UserStoreError::UserNotFound) => Err(UserStoreError::UserNotFound),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    #[tracing::instrument(name = "Listing roles in SQLite", skip_all)]
+    async fn list_roles(&self) -> Result<Vec<Role>, UserStoreError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT roles.name, rp.permission
+            FROM roles
+            LEFT JOIN role_permissions rp ON rp.role = roles.name
+            ORDER BY roles.name, rp.permission
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("Failed to list roles in SQLite")
+        .map_err(UserStoreError::UnexpectedError)?;
+
+        let rows = rows
+            .iter()
+            .map(|row| Ok((row.try_get("name")?, row.try_get("permission")?)))
+            .collect::<Result<Vec<_>, sqlx::Error>>()
+            .wrap_err("Failed to read roles from SQLite")
+            .map_err(UserStoreError::UnexpectedError)?;
+
+        Ok(group_roles(rows))
+    }
+}
+
+// Updates report a missing user by touching no rows
+fn found(rows_affected: u64) -> Result<(), UserStoreError> {
+    match rows_affected {
+        0 => Err(UserStoreError::UserNotFound),
+        _ => Ok(()),
+    }
+}
+
+fn user_from_row(row: &SqliteRow) -> Result<User> {
+    let mut user = User::new(
+        Email::parse(row.try_get("email")?)?,
+        Password::parse(&Secret::new(row.try_get("password_hash")?))?,
+        row.try_get("requires_2fa")?,
+    )
+    .with_disabled(row.try_get("disabled")?)
+    .with_lockout(
+        row.try_get("failed_login_attempts")?,
+        row.try_get::<Option<i64>, _>("locked_until")?
+            .map(from_unix_micros)
+            .transpose()?,
+    );
+    if let Some(phone_number) = row.try_get::<Option<&str>, _>("phone_number")? {
+        user = user.with_phone_number(PhoneNumber::parse(phone_number)?);
+    }
+    if let Some(valid_after) = row.try_get::<Option<i64>, _>("tokens_valid_after")? {
+        user = user.with_tokens_valid_after(from_unix_micros(valid_after)?);
+    }
+
+    user.with_two_fa_channel(row.try_get::<&str, _>("two_fa_channel")?.parse()?)
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+
+// SQLite has no timestamp type, so the SQLite stores keep times as Unix microseconds, which
+// compare correctly as plain integers.
+
+pub(super) fn to_unix_micros(time: DateTime<Utc>) -> i64 {
+    time.timestamp_micros()
+}
+
+pub(super) fn from_unix_micros(micros: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(micros).ok_or_else(|| eyre!("Invalid timestamp: {micros}"))
+}
+
+pub(super) fn duration_micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use crate::domain::Role;
+
+// Groups `(role, permission)` rows, ordered by role, into roles. A role without permissions
+// comes as a single row without a permission, as from a LEFT JOIN on `role_permissions`.
+pub(super) fn group_roles(rows: impl IntoIterator<Item = (String, Option<String>)>) -> Vec<Role> {
+    let mut roles: Vec<Role> = Vec::new();
+    for (name, permission) in rows {
+        if !matches!(roles.last(), Some(last) if last.name == name) {
+            roles.push(Role {
+                name,
+                permissions: Vec::new(),
+            });
+        }
+        if let (Some(permission), Some(role)) = (permission, roles.last_mut()) {
+            role.permissions.push(permission);
+        }
+    }
+    roles
+}
+
+// Groups `(email, role, permission)` rows, ordered by email and role, into each user's roles
+pub(super) fn roles_by_email(
+    rows: impl IntoIterator<Item = (String, String, Option<String>)>,
+) -> HashMap<String, Vec<Role>> {
+    let mut rows_by_email: HashMap<String, Vec<(String, Option<String>)>> = HashMap::new();
+    for (email, role, permission) in rows {
+        rows_by_email
+            .entry(email)
+            .or_default()
+            .push((role, permission));
+    }
+
+    rows_by_email
+        .into_iter()
+        .map(|(email, rows)| (email, group_roles(rows)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(email: &str, role: &str, permission: Option<&str>) -> (String, String, Option<String>) {
+        (
+            email.to_owned(),
+            role.to_owned(),
+            permission.map(str::to_owned),
+        )
+    }
+
+    #[test]
+    fn test_groups_rows_into_each_users_roles() {
+        let roles = roles_by_email([
+            row("a@example.com", "admin", Some("users:read")),
+            row("a@example.com", "admin", Some("users:write")),
+            row("a@example.com", "support", None),
+            row("b@example.com", "support", None),
+        ]);
+
+        assert_eq!(
+            roles["a@example.com"],
+            [
+                Role::admin(),
+                Role {
+                    name: "support".to_owned(),
+                    permissions: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(roles["b@example.com"].len(), 1);
+        assert!(!roles.contains_key("c@example.com"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::{future::Future, time::Duration};
+
+use chrono::Utc;
+use color_eyre::eyre::Result;
+
+use crate::{
+    app_state::{EmailClientType, EmailOutboxType},
+    domain::{PendingEmail, PermanentEmailFailure},
+    settings::EmailOutboxSettings,
+    utils::{names, RetryPolicy},
+};
+
+// Delivers emails from the outbox in the background, retrying failures with backoff.
+pub struct EmailOutboxWorker {
+    outbox: EmailOutboxType,
+    email_client: EmailClientType,
+    policy: RetryPolicy,
+    batch_size: u32,
+    poll_interval: Duration,
+    lease: Duration,
+}
+
+impl EmailOutboxWorker {
+    pub fn new(
+        outbox: EmailOutboxType,
+        email_client: EmailClientType,
+        settings: &EmailOutboxSettings,
+    ) -> Self {
+        Self {
+            outbox,
+            email_client,
+            policy: settings.retry_policy(),
+            batch_size: settings.batch_size,
+            poll_interval: settings.poll_interval(),
+            lease: settings.lease(),
+        }
+    }
+
+    // Claims and delivers one batch of due emails, returning how many were claimed
+    #[tracing::instrument(name = "Processing email outbox", skip_all)]
+    pub async fn process_due(&self) -> Result<usize> {
+        let emails = self.outbox.claim_due(self.batch_size, self.lease).await?;
+        let claimed = emails.len();
+        if emails.is_empty() {
+            return Ok(0);
+        }
+
+        let results = self.email_client.read().await.send_emails(&emails).await;
+
+        for (email, sent) in emails.into_iter().zip(results) {
+            self.record_delivery(email, sent).await?;
+        }
+
+        Ok(claimed)
+    }
+
+    // Marks the email sent, or schedules a retry or dead-letters it depending on the failure
+    async fn record_delivery(&self, email: PendingEmail, sent: Result<()>) -> Result<()> {
+        match sent {
+            Ok(()) => self.outbox.mark_sent(email.id).await,
+            Err(e)
+                if email.attempts >= self.policy.max_attempts
+                    || PermanentEmailFailure::is_permanent(&e) =>
+            {
+                tracing::error!(
+                    error = ?e,
+                    email.id = %email.id,
+                    email.attempts,
+                    "Giving up on email, moving it to the dead letters"
+                );
+                metrics::counter!(names::EMAILS_DEAD_LETTERED_TOTAL).increment(1);
+                self.outbox
+                    .mark_failed(email.id, &format!("{e:#}"), None)
+                    .await
+            }
+            Err(e) => {
+                let delay = self.policy.backoff(email.attempts);
+                tracing::warn!(
+                    error = ?e,
+                    email.id = %email.id,
+                    email.attempts,
+                    retry_in = ?delay,
+                    "Failed to send email, will retry"
+                );
+                let retry_at = Utc::now() + chrono::Duration::from_std(delay)?;
+                self.outbox
+                    .mark_failed(email.id, &format!("{e:#}"), Some(retry_at))
+                    .await
+            }
+        }?;
+
+        Ok(())
+    }
+
+    // Polls the outbox until `shutdown` completes. A batch in progress is always finished,
+    // anything left over is picked up on the next start.
+    pub async fn run_until(self, shutdown: impl Future<Output = ()>) {
+        tokio::pin!(shutdown);
+
+        loop {
+            let processed = match self.process_due().await {
+                Ok(processed) => processed,
+                Err(e) => {
+                    tracing::error!(error = ?e, "Failed to process email outbox");
+                    0
+                }
+            };
+
+            // Keep draining without waiting while full batches are coming back, but check for
+            // shutdown first so a steady backlog can't hold it up
+            let wait = match processed as u32 == self.batch_size {
+                true => Duration::ZERO,
+                false => self.poll_interval,
+            };
+
+            tokio::select! {
+                biased;
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+
+        tracing::info!("Email outbox worker stopped");
+    }
+}
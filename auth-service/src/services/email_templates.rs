@@ -0,0 +1,510 @@
+use std::{path::Path, time::Duration};
+
+use askama::{Html, MarkupDisplay, Template};
+use color_eyre::eyre::{Context, Result};
+
+use crate::{domain::EmailContent, settings::EmailTemplateSettings};
+
+const UNKNOWN: &str = "Unknown";
+
+// Files looked up in `email_templates.template_dir`, each replacing the matching built-in
+// template when present
+const SUBJECT_FILE: &str = "two_fa_code.subject.txt";
+const HTML_FILE: &str = "two_fa_code.html";
+const TEXT_FILE: &str = "two_fa_code.txt";
+const SMS_FILE: &str = "two_fa_code.sms.txt";
+
+// The email carrying a 2FA code. `ip` and `device` describe the client that started
+// the login, so that the recipient can spot attempts that were not theirs.
+pub struct TwoFACodeEmail<'a> {
+    pub branding: &'a EmailTemplateSettings,
+    pub code: &'a str,
+    pub expires_in: Duration,
+    pub ip: Option<&'a str>,
+    pub device: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "email/two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    branding: &'a EmailTemplateSettings,
+    code: &'a str,
+    expires_in: &'a str,
+    ip: &'a str,
+    device: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "email/two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    branding: &'a EmailTemplateSettings,
+    code: &'a str,
+    expires_in: &'a str,
+    ip: &'a str,
+    device: &'a str,
+}
+
+impl TwoFACodeEmail<'_> {
+    pub fn render(&self) -> Result<EmailContent> {
+        let expires_in = format_duration(self.expires_in);
+        let ip = self.ip.unwrap_or(UNKNOWN);
+        let device = self.device.unwrap_or(UNKNOWN);
+        let overrides = &self.branding.overrides;
+        let values = PlaceholderValues {
+            branding: self.branding,
+            code: self.code,
+            expires_in: &expires_in,
+            ip,
+            device,
+        };
+
+        let html_body = match &overrides.html {
+            Some(template) => template.render(&values, true),
+            None => TwoFACodeHtml {
+                branding: self.branding,
+                code: self.code,
+                expires_in: &expires_in,
+                ip,
+                device,
+            }
+            .render()
+            .wrap_err("Failed to render 2FA code HTML email")?,
+        };
+
+        let text_body = match &overrides.text {
+            Some(template) => template.render(&values, false),
+            None => TwoFACodeText {
+                branding: self.branding,
+                code: self.code,
+                expires_in: &expires_in,
+                ip,
+                device,
+            }
+            .render()
+            .wrap_err("Failed to render 2FA code text email")?,
+        };
+
+        let subject = match &overrides.subject {
+            Some(template) => template.render(&values, false),
+            None => format!("Your {} verification code", self.branding.product_name),
+        };
+
+        Ok(EmailContent {
+            subject,
+            html_body,
+            text_body,
+        })
+    }
+}
+
+// The SMS carrying a 2FA code. Kept to a single short segment, so unlike the email it
+// leaves out the client details.
+pub struct TwoFACodeSms<'a> {
+    pub branding: &'a EmailTemplateSettings,
+    pub code: &'a str,
+    pub expires_in: Duration,
+}
+
+#[derive(Template)]
+#[template(path = "sms/two_fa_code.txt")]
+struct TwoFACodeSmsText<'a> {
+    product_name: &'a str,
+    code: &'a str,
+    expires_in: &'a str,
+}
+
+impl TwoFACodeSms<'_> {
+    pub fn render(&self) -> Result<String> {
+        let expires_in = format_duration(self.expires_in);
+
+        match &self.branding.overrides.sms {
+            // Only the SMS placeholders pass validation, so the client details stay unknown
+            Some(template) => Ok(template.render(
+                &PlaceholderValues {
+                    branding: self.branding,
+                    code: self.code,
+                    expires_in: &expires_in,
+                    ip: UNKNOWN,
+                    device: UNKNOWN,
+                },
+                false,
+            )),
+            None => TwoFACodeSmsText {
+                product_name: &self.branding.product_name,
+                code: self.code,
+                expires_in: &expires_in,
+            }
+            .render()
+            .wrap_err("Failed to render 2FA code SMS"),
+        }
+    }
+}
+
+// Templates replacing the built-in ones, read from `email_templates.template_dir` at
+// startup. They are plain text with `{{ placeholder }}` substitutions, limited to the values
+// the matching built-in template gets:
+//   - email subject, HTML and text: product_name, accent_color, logo_url, support_email,
+//     code, expires_in, ip and device
+//   - SMS: product_name, code and expires_in
+// Missing optional values (logo_url, support_email) render as an empty string.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateOverrides {
+    subject: Option<OverrideTemplate>,
+    html: Option<OverrideTemplate>,
+    text: Option<OverrideTemplate>,
+    sms: Option<OverrideTemplate>,
+}
+
+impl TemplateOverrides {
+    // Reads the templates present in `dir` and reports every problem at once, so that a
+    // broken override stops the service from starting instead of failing logins
+    pub fn load(dir: &Path) -> Result<Self, Vec<String>> {
+        if !dir.is_dir() {
+            return Err(vec![format!("{} is not a directory", dir.display())]);
+        }
+
+        let mut errors = Vec::new();
+        let mut load = |file: &str, allowed: &[Placeholder], required: Option<Placeholder>| {
+            let path = dir.join(file);
+            if !path.exists() {
+                return None;
+            }
+
+            std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| OverrideTemplate::parse(&source, allowed, required))
+                .map_err(|e| errors.push(format!("{file}: {e}")))
+                .ok()
+        };
+
+        let overrides = Self {
+            subject: load(SUBJECT_FILE, &Placeholder::EMAIL, None),
+            html: load(HTML_FILE, &Placeholder::EMAIL, Some(Placeholder::Code)),
+            text: load(TEXT_FILE, &Placeholder::EMAIL, Some(Placeholder::Code)),
+            sms: load(SMS_FILE, &Placeholder::SMS, Some(Placeholder::Code)),
+        };
+
+        if errors.is_empty() {
+            Ok(overrides)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    ProductName,
+    AccentColor,
+    LogoUrl,
+    SupportEmail,
+    Code,
+    ExpiresIn,
+    Ip,
+    Device,
+}
+
+impl Placeholder {
+    const EMAIL: [Placeholder; 8] = [
+        Placeholder::ProductName,
+        Placeholder::AccentColor,
+        Placeholder::LogoUrl,
+        Placeholder::SupportEmail,
+        Placeholder::Code,
+        Placeholder::ExpiresIn,
+        Placeholder::Ip,
+        Placeholder::Device,
+    ];
+    const SMS: [Placeholder; 3] = [
+        Placeholder::ProductName,
+        Placeholder::Code,
+        Placeholder::ExpiresIn,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Placeholder::ProductName => "product_name",
+            Placeholder::AccentColor => "accent_color",
+            Placeholder::LogoUrl => "logo_url",
+            Placeholder::SupportEmail => "support_email",
+            Placeholder::Code => "code",
+            Placeholder::ExpiresIn => "expires_in",
+            Placeholder::Ip => "ip",
+            Placeholder::Device => "device",
+        }
+    }
+}
+
+struct PlaceholderValues<'a> {
+    branding: &'a EmailTemplateSettings,
+    code: &'a str,
+    expires_in: &'a str,
+    ip: &'a str,
+    device: &'a str,
+}
+
+impl PlaceholderValues<'_> {
+    fn get(&self, placeholder: Placeholder) -> &str {
+        match placeholder {
+            Placeholder::ProductName => &self.branding.product_name,
+            Placeholder::AccentColor => &self.branding.accent_color,
+            Placeholder::LogoUrl => self.branding.logo_url.as_deref().unwrap_or_default(),
+            Placeholder::SupportEmail => self.branding.support_email.as_deref().unwrap_or_default(),
+            Placeholder::Code => self.code,
+            Placeholder::ExpiresIn => self.expires_in,
+            Placeholder::Ip => self.ip,
+            Placeholder::Device => self.device,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Placeholder(Placeholder),
+}
+
+#[derive(Debug, Clone)]
+struct OverrideTemplate(Vec<Segment>);
+
+impl OverrideTemplate {
+    fn parse(
+        source: &str,
+        allowed: &[Placeholder],
+        required: Option<Placeholder>,
+    ) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                return Err("unclosed {{".to_owned());
+            };
+            let name = rest[start + 2..start + end].trim();
+            let placeholder = allowed
+                .iter()
+                .copied()
+                .find(|placeholder| placeholder.name() == name)
+                .ok_or_else(|| format!("unknown placeholder {{{{ {name} }}}}"))?;
+
+            segments.push(Segment::Text(rest[..start].to_owned()));
+            segments.push(Segment::Placeholder(placeholder));
+            rest = &rest[start + end + 2..];
+        }
+        segments.push(Segment::Text(rest.to_owned()));
+
+        if let Some(required) = required {
+            let is_used = segments
+                .iter()
+                .any(|segment| matches!(segment, Segment::Placeholder(p) if *p == required));
+            if !is_used {
+                return Err(format!("must contain {{{{ {} }}}}", required.name()));
+            }
+        }
+
+        Ok(Self(segments))
+    }
+
+    // Values are HTML-escaped with `escape_html`, like askama does for the built-in templates
+    fn render(&self, values: &PlaceholderValues, escape_html: bool) -> String {
+        self.0
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.clone(),
+                Segment::Placeholder(placeholder) if escape_html => {
+                    MarkupDisplay::new_unsafe(values.get(*placeholder), Html).to_string()
+                }
+                Segment::Placeholder(placeholder) => values.get(*placeholder).to_owned(),
+            })
+            .collect()
+    }
+}
+
+// Formats durations such as "10 minutes" or "45 seconds"
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (amount, unit) = match seconds {
+        s if s >= 3600 && s % 3600 == 0 => (s / 3600, "hour"),
+        s if s >= 60 && s % 60 == 0 => (s / 60, "minute"),
+        s => (s, "second"),
+    };
+
+    match amount {
+        1 => format!("1 {unit}"),
+        n => format!("{n} {unit}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branding() -> EmailTemplateSettings {
+        EmailTemplateSettings {
+            product_name: "Live Bootcamp".to_owned(),
+            logo_url: None,
+            support_email: Some("support@example.com".to_owned()),
+            accent_color: "#2563eb".to_owned(),
+            template_dir: None,
+            overrides: TemplateOverrides::default(),
+        }
+    }
+
+    // Overrides loaded from a temporary directory holding `files`
+    fn overrides(files: &[(&str, &str)]) -> Result<TemplateOverrides, Vec<String>> {
+        let dir = tempfile::tempdir().unwrap();
+        for (file, contents) in files {
+            std::fs::write(dir.path().join(file), contents).unwrap();
+        }
+
+        TemplateOverrides::load(dir.path())
+    }
+
+    fn email(branding: &EmailTemplateSettings) -> TwoFACodeEmail<'_> {
+        TwoFACodeEmail {
+            branding,
+            code: "123456",
+            expires_in: Duration::from_secs(600),
+            ip: Some("203.0.113.7"),
+            device: Some("Mozilla/5.0 (X11; Linux x86_64) Firefox/128.0"),
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_html() {
+        let content = email(&branding()).render().unwrap();
+
+        assert_eq!(content.subject, "Your Live Bootcamp verification code");
+        insta::assert_snapshot!(content.html_body);
+    }
+
+    #[test]
+    fn test_two_fa_code_text() {
+        let content = email(&branding()).render().unwrap();
+
+        insta::assert_snapshot!(content.text_body);
+    }
+
+    #[test]
+    fn test_two_fa_code_html_with_logo_and_unknown_client() {
+        let branding = EmailTemplateSettings {
+            logo_url: Some("https://example.com/logo.png".to_owned()),
+            support_email: None,
+            ..branding()
+        };
+        let content = TwoFACodeEmail {
+            ip: None,
+            device: None,
+            ..email(&branding)
+        }
+        .render()
+        .unwrap();
+
+        insta::assert_snapshot!(content.html_body);
+    }
+
+    #[test]
+    fn test_client_details_are_html_escaped() {
+        let branding = branding();
+        let content = TwoFACodeEmail {
+            device: Some("<script>alert(1)</script>"),
+            ..email(&branding)
+        }
+        .render()
+        .unwrap();
+
+        assert!(!content.html_body.contains("<script>"));
+        assert!(content.html_body.contains("&lt;script&gt;"));
+        // Plain-text bodies are not HTML, so they are left as is
+        assert!(content.text_body.contains("<script>alert(1)</script>"));
+    }
+
+    #[test]
+    fn test_two_fa_code_sms() {
+        let branding = branding();
+        let body = TwoFACodeSms {
+            branding: &branding,
+            code: "123456",
+            expires_in: Duration::from_secs(600),
+        }
+        .render()
+        .unwrap();
+
+        // A single GSM-7 segment
+        assert!(body.len() <= 160);
+        insta::assert_snapshot!(body);
+    }
+
+    #[test]
+    fn test_two_fa_code_overrides() {
+        let branding = EmailTemplateSettings {
+            overrides: overrides(&[
+                (SUBJECT_FILE, "{{ code }} is your {{product_name}} code"),
+                (
+                    HTML_FILE,
+                    "<h1 style=\"color: {{ accent_color }}\">{{ product_name }}</h1>\n\
+                     <p>Your code is <b>{{ code }}</b>, valid for {{ expires_in }}.</p>\n\
+                     <p>Requested from {{ ip }} ({{ device }}).</p>\n\
+                     <p>Help: {{ support_email }}</p>\n",
+                ),
+                (
+                    SMS_FILE,
+                    "{{ code }} - {{ product_name }} ({{ expires_in }})",
+                ),
+            ])
+            .unwrap(),
+            ..branding()
+        };
+        let content = TwoFACodeEmail {
+            device: Some("<script>alert(1)</script>"),
+            ..email(&branding)
+        }
+        .render()
+        .unwrap();
+        let sms = TwoFACodeSms {
+            branding: &branding,
+            code: "123456",
+            expires_in: Duration::from_secs(600),
+        }
+        .render()
+        .unwrap();
+
+        assert_eq!(content.subject, "123456 is your Live Bootcamp code");
+        insta::assert_snapshot!(content.html_body);
+        assert_eq!(sms, "123456 - Live Bootcamp (10 minutes)");
+        // Templates without an override keep the built-in one
+        assert!(content
+            .text_body
+            .contains("Use this code to finish signing in: 123456"));
+    }
+
+    #[test]
+    fn test_invalid_overrides_report_every_problem() {
+        let errors = overrides(&[
+            (HTML_FILE, "{{ branding.product_name }} {{ code }}"),
+            (TEXT_FILE, "Your code expires in {{ expires_in }}"),
+            (SMS_FILE, "{{ code }} from {{ ip }}"),
+            (SUBJECT_FILE, "Your code {{ code"),
+        ])
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            [
+                "two_fa_code.subject.txt: unclosed {{",
+                "two_fa_code.html: unknown placeholder {{ branding.product_name }}",
+                "two_fa_code.txt: must contain {{ code }}",
+                "two_fa_code.sms.txt: unknown placeholder {{ ip }}",
+            ]
+        );
+        assert!(TemplateOverrides::load(Path::new("/nonexistent")).is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(600)), "10 minutes");
+        assert_eq!(format_duration(Duration::from_secs(60)), "1 minute");
+        assert_eq!(format_duration(Duration::from_secs(90)), "90 seconds");
+        assert_eq!(format_duration(Duration::from_secs(7200)), "2 hours");
+    }
+}
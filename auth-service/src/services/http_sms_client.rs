@@ -0,0 +1,148 @@
+use color_eyre::eyre::Result;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::domain::{PhoneNumber, SmsClient};
+
+// Sends SMS through any provider accepting a JSON `{ from, to, body }` POST on `/messages`
+// with a bearer token, which most SMS APIs (or a thin proxy in front of them) offer.
+pub struct HttpSmsClient {
+    http_client: Client,
+    base_url: Url,
+    sender: String,
+    authorization_token: Secret<String>,
+}
+
+impl HttpSmsClient {
+    pub fn new(
+        base_url: &str,
+        sender: String,
+        authorization_token: Secret<String>,
+        http_client: Client,
+    ) -> Result<Self> {
+        Ok(Self {
+            http_client,
+            base_url: Url::parse(base_url)?,
+            sender,
+            authorization_token,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for HttpSmsClient {
+    #[tracing::instrument(name = "Sending SMS", skip_all)]
+    async fn send_sms(&self, to: &PhoneNumber, body: &str) -> Result<()> {
+        let url = self.base_url.join(MESSAGES_PATH)?;
+
+        let request_body = SendSmsRequest {
+            from: &self.sender,
+            to: to.as_ref().expose_secret(),
+            body,
+        };
+
+        self.http_client
+            .post(url)
+            .bearer_auth(self.authorization_token.expose_secret())
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+const MESSAGES_PATH: &str = "/messages";
+
+#[derive(serde::Serialize, Debug)]
+struct SendSmsRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wiremock::matchers::{any, body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(200);
+
+    fn phone_number() -> PhoneNumber {
+        PhoneNumber::parse("+14155550123").unwrap()
+    }
+
+    fn sms_client(base_url: String) -> HttpSmsClient {
+        let http_client = Client::builder().timeout(TIMEOUT).build().unwrap();
+        HttpSmsClient::new(
+            &base_url,
+            "LiveBootcamp".to_owned(),
+            Secret::new("sms-token".to_owned()),
+            http_client,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_sms_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(header("Authorization", "Bearer sms-token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/messages"))
+            .and(method("POST"))
+            .and(body_json(serde_json::json!({
+                "from": "LiveBootcamp",
+                "to": "+14155550123",
+                "body": "Your code is 123456",
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client
+            .send_sms(&phone_number(), "Your code is 123456")
+            .await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_sms_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), "Hello").await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_sms_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), "Hello").await;
+
+        assert!(outcome.is_err());
+    }
+}
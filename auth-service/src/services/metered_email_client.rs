@@ -0,0 +1,45 @@
+use color_eyre::eyre::Result;
+
+use crate::{
+    domain::{Email, EmailClient, EmailContent, PendingEmail},
+    utils::names,
+};
+
+// Wraps an email client, counting sent emails and send failures.
+pub struct MeteredEmailClient<C> {
+    inner: C,
+    name: &'static str,
+}
+
+impl<C> MeteredEmailClient<C> {
+    pub fn new(inner: C, name: &'static str) -> Self {
+        Self { inner, name }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C: EmailClient + Send + Sync> EmailClient for MeteredEmailClient<C> {
+    async fn send_email(&self, to: &Email, content: &EmailContent) -> Result<()> {
+        let result = self.inner.send_email(to, content).await;
+        self.record(&result);
+
+        result
+    }
+
+    async fn send_emails(&self, emails: &[PendingEmail]) -> Vec<Result<()>> {
+        let results = self.inner.send_emails(emails).await;
+        results.iter().for_each(|result| self.record(result));
+
+        results
+    }
+}
+
+impl<C> MeteredEmailClient<C> {
+    fn record(&self, result: &Result<()>) {
+        match result {
+            Ok(_) => metrics::counter!(names::EMAILS_SENT_TOTAL, "client" => self.name),
+            Err(_) => metrics::counter!(names::EMAIL_SEND_FAILURES_TOTAL, "client" => self.name),
+        }
+        .increment(1);
+    }
+}
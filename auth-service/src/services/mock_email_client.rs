@@ -1,15 +1,15 @@
 use color_eyre::eyre::Result;
 
-use crate::domain::{Email, EmailClient};
+use crate::domain::{Email, EmailClient, EmailContent};
 
 pub struct MockEmailClient;
 
 #[async_trait::async_trait]
 impl EmailClient for MockEmailClient {
-    async fn send_email(&self, to: &Email, subject: &str, body: &str) -> Result<()> {
+    async fn send_email(&self, to: &Email, content: &EmailContent) -> Result<()> {
         tracing::debug!("Sending email to: {:?}", to.as_ref());
-        tracing::debug!("Subject: {}", subject);
-        tracing::debug!("Body: {}", body);
+        tracing::debug!("Subject: {}", content.subject);
+        tracing::debug!("Body: {}", content.text_body);
         Ok(())
     }
 }
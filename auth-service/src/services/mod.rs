@@ -1,6 +1,23 @@
+pub mod audit_sinks;
+mod capturing_email_client;
 pub mod data_stores;
+mod email_outbox_worker;
+mod email_templates;
+mod health_checks;
+mod http_sms_client;
+mod metered_email_client;
 mod mock_email_client;
 mod postmark_email_client;
+mod smtp_email_client;
+mod token_revocations;
 
+pub use capturing_email_client::*;
+pub use email_outbox_worker::*;
+pub use email_templates::*;
+pub use health_checks::*;
+pub use http_sms_client::*;
+pub use metered_email_client::*;
 pub use mock_email_client::*;
 pub use postmark_email_client::*;
+pub use smtp_email_client::*;
+pub use token_revocations::*;
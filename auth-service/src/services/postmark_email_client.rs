@@ -1,67 +1,317 @@
-use color_eyre::eyre::Result;
-use reqwest::{Client, Url};
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+
+use color_eyre::eyre::{Report, Result};
+use reqwest::{Client, Response, StatusCode, Url};
 use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::domain::{Email, EmailClient};
+use crate::{
+    domain::{Email, EmailClient, EmailContent, PendingEmail, PermanentEmailFailure},
+    utils::RetryPolicy,
+};
 
 pub struct PostmarkEmailClient {
     http_client: Client,
-    base_url: String,
+    email_url: Url,
+    batch_url: Url,
     sender: Email,
     authorization_token: Secret<String>,
+    retry_policy: RetryPolicy,
 }
 
 impl PostmarkEmailClient {
     pub fn new(
-        base_url: String,
+        base_url: &str,
         sender: Email,
         authorization_token: Secret<String>,
         http_client: Client,
-    ) -> Self {
-        Self {
+    ) -> Result<Self> {
+        let base_url = Url::parse(base_url)?;
+
+        Ok(Self {
             http_client,
-            base_url,
+            email_url: base_url.join(EMAIL_PATH)?,
+            batch_url: base_url.join(BATCH_PATH)?,
             sender,
             authorization_token,
+            retry_policy: RetryPolicy::none(),
+        })
+    }
+
+    // Retries rate limited requests, server errors and timeouts. Defaults to no retries.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    #[tracing::instrument(name = "Sending email with Postmark", skip_all)]
+    pub async fn send(&self, message: &PostmarkMessage) -> Result<(), PostmarkError> {
+        let request_body = self.request_body(message);
+
+        self.with_retries(|| async {
+            self.post(&self.email_url, &request_body).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    // Sends many messages in as few requests as possible, returning one result per message,
+    // in order. A request that fails as a whole fails every message it carried, without
+    // affecting the results of the requests already accepted.
+    #[tracing::instrument(name = "Sending email batch with Postmark", skip_all)]
+    pub async fn send_batch(&self, messages: &[PostmarkMessage]) -> Vec<Result<(), PostmarkError>> {
+        let mut results = Vec::with_capacity(messages.len());
+
+        for chunk in messages.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(chunk).await {
+                Ok(chunk_results) => results.extend(chunk_results),
+                Err(e) => results.extend(std::iter::repeat_n(Err(e), chunk.len())),
+            }
         }
+
+        results
     }
-}
 
-#[async_trait::async_trait]
-impl EmailClient for PostmarkEmailClient {
-    #[tracing::instrument(name = "Sending email", skip_all)]
-    async fn send_email(&self, recipient: &Email, subject: &str, content: &str) -> Result<()> {
-        let base = Url::parse(&self.base_url)?;
-        let url = base.join("/email")?;
+    async fn send_chunk(
+        &self,
+        chunk: &[PostmarkMessage],
+    ) -> Result<Vec<Result<(), PostmarkError>>, PostmarkError> {
+        let request_body: Vec<_> = chunk
+            .iter()
+            .map(|message| self.request_body(message))
+            .collect();
 
-        let request_body = SendEmailRequest {
+        let response = self
+            .with_retries(|| self.post(&self.batch_url, &request_body))
+            .await?;
+
+        // Postmark has accepted the batch by now, so failures from here on must not lead
+        // to the chunk being sent again
+        let responses: Vec<PostmarkResponse> = response
+            .json()
+            .await
+            .map_err(|e| PostmarkError::InvalidResponse(e.to_string()))?;
+        if responses.len() != chunk.len() {
+            return Err(PostmarkError::InvalidResponse(format!(
+                "{} results for {} messages",
+                responses.len(),
+                chunk.len()
+            )));
+        }
+
+        Ok(responses
+            .into_iter()
+            .map(PostmarkResponse::into_result)
+            .collect())
+    }
+
+    fn request_body<'a>(&'a self, message: &'a PostmarkMessage) -> SendEmailRequest<'a> {
+        SendEmailRequest {
             from: self.sender.as_ref().expose_secret(),
-            to: recipient.as_ref().expose_secret(),
-            subject,
-            html_body: content,
-            text_body: content,
+            to: message.to.as_ref().expose_secret(),
+            subject: &message.content.subject,
+            html_body: &message.content.html_body,
+            text_body: &message.content.text_body,
             message_stream: MESSAGE_STREAM,
-        };
+            tag: message.tag.as_deref(),
+            metadata: &message.metadata,
+        }
+    }
 
-        let request = self
+    // Postmark answers every rejected message with a non-2xx status and a JSON error code
+    async fn post(&self, url: &Url, body: &impl Serialize) -> Result<Response, PostmarkError> {
+        let response = self
             .http_client
-            .post(url)
+            .post(url.clone())
             .header(
                 POSTMARK_AUTH_HEADER,
                 self.authorization_token.expose_secret(),
             )
-            .json(&request_body);
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| PostmarkError::Transport(Arc::new(e)))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(PostmarkError::RateLimited);
+        }
+        if status.is_server_error() {
+            return Err(PostmarkError::Unavailable(status.as_u16()));
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| PostmarkError::Transport(Arc::new(e)))?;
+        Err(match serde_json::from_str::<PostmarkResponse>(&body) {
+            Ok(response) => PostmarkError::from_api(response.error_code, response.message),
+            Err(_) => PostmarkError::Rejected {
+                code: None,
+                message: format!("HTTP {status}: {body}"),
+            },
+        })
+    }
+
+    async fn with_retries<T, F, Fut>(&self, request: F) -> Result<T, PostmarkError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, PostmarkError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if e.is_retryable() && attempt < self.retry_policy.max_attempts => {
+                    let delay = self.retry_policy.backoff_with_jitter(attempt);
+                    tracing::warn!(error = %e, attempt, retry_in = ?delay, "Postmark request failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
 
-        request.send().await?.error_for_status()?;
+#[async_trait::async_trait]
+impl EmailClient for PostmarkEmailClient {
+    #[tracing::instrument(name = "Sending email", skip_all)]
+    async fn send_email(&self, recipient: &Email, content: &EmailContent) -> Result<()> {
+        let message = PostmarkMessage::new(recipient.clone(), content.clone());
 
-        Ok(())
+        self.send(&message)
+            .await
+            .map_err(PostmarkError::into_report)
+    }
+
+    // Outbox emails go out through the batch endpoint, tagged and carrying their outbox ID
+    // so that Postmark's activity can be traced back to the outbox
+    #[tracing::instrument(name = "Sending emails", skip_all)]
+    async fn send_emails(&self, emails: &[PendingEmail]) -> Vec<Result<()>> {
+        let messages: Vec<_> = emails
+            .iter()
+            .map(|email| {
+                let message = PostmarkMessage::new(email.recipient.clone(), email.content.clone())
+                    .with_metadata(OUTBOX_ID_METADATA, email.id.to_string());
+                match &email.tag {
+                    Some(tag) => message.with_tag(tag),
+                    None => message,
+                }
+            })
+            .collect();
+
+        self.send_batch(&messages)
+            .await
+            .into_iter()
+            .map(|result| result.map_err(PostmarkError::into_report))
+            .collect()
     }
 }
 
+// An email with the Postmark-specific extras the `EmailClient` trait has no room for
+#[derive(Debug, Clone)]
+pub struct PostmarkMessage {
+    pub to: Email,
+    pub content: EmailContent,
+    // Groups messages in Postmark's statistics, e.g. "2fa-code"
+    pub tag: Option<String>,
+    // Searchable key/value pairs stored with the message
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl PostmarkMessage {
+    pub fn new(to: Email, content: EmailContent) -> Self {
+        Self {
+            to,
+            content,
+            tag: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PostmarkError {
+    #[error("Invalid email request: {0}")]
+    InvalidRequest(String),
+    #[error("Inactive recipient: {0}")]
+    InactiveRecipient(String),
+    #[error("Rejected by Postmark (error code {code:?}): {message}")]
+    Rejected { code: Option<i64>, message: String },
+    #[error("Rate limited by Postmark")]
+    RateLimited,
+    #[error("Postmark is unavailable (HTTP {0})")]
+    Unavailable(u16),
+    #[error("Failed to reach Postmark")]
+    Transport(#[source] Arc<reqwest::Error>),
+    // A request was accepted but its response can't be read, so resending it may send
+    // the same emails twice
+    #[error("Unexpected response from Postmark: {0}")]
+    InvalidResponse(String),
+}
+
+impl PostmarkError {
+    // See https://postmarkapp.com/developer/api/overview#error-codes
+    fn from_api(code: i64, message: String) -> Self {
+        match code {
+            INVALID_EMAIL_REQUEST => Self::InvalidRequest(message),
+            INACTIVE_RECIPIENT => Self::InactiveRecipient(message),
+            code => Self::Rejected {
+                code: Some(code),
+                message,
+            },
+        }
+    }
+
+    // Worth retrying shortly, the same request may well succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited | Self::Unavailable(_) | Self::Transport(_)
+        )
+    }
+
+    // The message itself cannot be delivered, retrying it will never help
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Self::InvalidRequest(_) | Self::InactiveRecipient(_))
+    }
+
+    fn into_report(self) -> Report {
+        let permanent = self.is_permanent();
+        let report = Report::new(self);
+        if permanent {
+            report.wrap_err(PermanentEmailFailure)
+        } else {
+            report
+        }
+    }
+}
+
+const EMAIL_PATH: &str = "/email";
+const BATCH_PATH: &str = "/email/batch";
 const MESSAGE_STREAM: &str = "outbound";
 const POSTMARK_AUTH_HEADER: &str = "X-Postmark-Server-Token";
+const OUTBOX_ID_METADATA: &str = "outbox_id";
+// Postmark accepts at most 500 messages per batch request
+const MAX_BATCH_SIZE: usize = 500;
+const INVALID_EMAIL_REQUEST: i64 = 300;
+const INACTIVE_RECIPIENT: i64 = 406;
 
-#[derive(serde::Serialize, Debug)]
+#[derive(Serialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
     from: &'a str,
@@ -70,29 +320,52 @@ struct SendEmailRequest<'a> {
     html_body: &'a str,
     text_body: &'a str,
     message_stream: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: &'a BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResponse {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+}
+
+impl PostmarkResponse {
+    fn into_result(self) -> Result<(), PostmarkError> {
+        match self.error_code {
+            0 => Ok(()),
+            code => Err(PostmarkError::from_api(code, self.message)),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::constants::test;
+    use std::time::Duration;
 
     use super::*;
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
     use super::PostmarkEmailClient;
 
-    // Helper function to generate a test subject
-    fn subject() -> String {
-        Sentence(1..2).fake()
-    }
+    const TIMEOUT: Duration = Duration::from_millis(200);
 
     // Helper function to generate test content
-    fn content() -> String {
-        Paragraph(1..10).fake()
+    fn content() -> EmailContent {
+        let body: String = Paragraph(1..10).fake();
+        EmailContent {
+            subject: Sentence(1..2).fake(),
+            html_body: format!("<p>{body}</p>"),
+            text_body: body,
+        }
     }
 
     // Helper function to generate a test email
@@ -102,11 +375,26 @@ mod tests {
 
     // Helper function to create a test email client
     fn email_client(base_url: String) -> PostmarkEmailClient {
-        let http_client = Client::builder()
-            .timeout(test::email_client::TIMEOUT)
-            .build()
-            .unwrap();
-        PostmarkEmailClient::new(base_url, email(), Secret::new(Faker.fake()), http_client)
+        let http_client = Client::builder().timeout(TIMEOUT).build().unwrap();
+        PostmarkEmailClient::new(&base_url, email(), Secret::new(Faker.fake()), http_client)
+            .unwrap()
+    }
+
+    // Helper function to create a test email client that retries up to `max_attempts` times
+    fn retrying_email_client(base_url: String, max_attempts: u32) -> PostmarkEmailClient {
+        email_client(base_url).with_retry_policy(RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        })
+    }
+
+    // Helper function to build a Postmark API error response
+    fn api_error(status: u16, error_code: i64) -> ResponseTemplate {
+        ResponseTemplate::new(status).set_body_json(serde_json::json!({
+            "ErrorCode": error_code,
+            "Message": "Something about the request was wrong",
+        }))
     }
 
     // Custom matcher to validate the email request body
@@ -146,9 +434,7 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content())
-            .await;
+        let outcome = email_client.send_email(&email(), &content()).await;
 
         assert!(outcome.is_ok());
     }
@@ -167,9 +453,7 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
-        let outcome = email_client
-            .send_email(&email(), &subject(), &content())
-            .await;
+        let outcome = email_client.send_email(&email(), &content()).await;
 
         assert!(outcome.is_err());
     }
@@ -189,10 +473,349 @@ mod tests {
             .await;
 
         // Execute the send_email function and check the outcome
+        let outcome = email_client.send_email(&email(), &content()).await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_an_invalid_recipient() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(any())
+            .respond_with(api_error(422, 300))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
         let outcome = email_client
-            .send_email(&email(), &subject(), &content())
+            .send(&PostmarkMessage::new(email(), content()))
             .await;
 
-        assert!(outcome.is_err());
+        let error = outcome.unwrap_err();
+        assert!(matches!(error, PostmarkError::InvalidRequest(_)));
+        assert!(!error.is_retryable());
+        assert!(error.is_permanent());
+    }
+
+    #[tokio::test]
+    async fn send_email_marks_an_inactive_recipient_as_permanent() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(any())
+            .respond_with(api_error(422, 406))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&email(), &content()).await;
+
+        assert!(PermanentEmailFailure::is_permanent(&outcome.unwrap_err()));
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_mark_other_api_errors_as_permanent() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        // ErrorCode 10 is a bad server token, a configuration problem rather than a bad message
+        Mock::given(any())
+            .respond_with(api_error(401, 10))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_email(&email(), &content())
+            .await
+            .unwrap_err();
+
+        assert!(!PermanentEmailFailure::is_permanent(&error));
+        assert!(matches!(
+            error.downcast_ref::<PostmarkError>(),
+            Some(PostmarkError::Rejected { code: Some(10), .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_server_errors() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client.send_email(&email(), &content()).await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_when_rate_limited_after_max_attempts() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(any())
+            .respond_with(api_error(429, 429))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send(&PostmarkMessage::new(email(), content()))
+            .await;
+
+        let error = outcome.unwrap_err();
+        assert!(matches!(error, PostmarkError::RateLimited));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_timeouts_as_retryable() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send(&PostmarkMessage::new(email(), content()))
+            .await;
+
+        let error = outcome.unwrap_err();
+        assert!(matches!(error, PostmarkError::Transport(_)));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_includes_tag_and_metadata() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "Tag": "2fa-code",
+                "Metadata": { "login_attempt_id": "abc" },
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let message = PostmarkMessage::new(email(), content())
+            .with_tag("2fa-code")
+            .with_metadata("login_attempt_id", "abc");
+
+        assert!(email_client.send(&message).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_omits_tag_and_metadata_when_unset() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        email_client
+            .send(&PostmarkMessage::new(email(), content()))
+            .await
+            .unwrap();
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert!(body.get("Tag").is_none());
+        assert!(body.get("Metadata").is_none());
+    }
+
+    #[tokio::test]
+    async fn send_batch_returns_a_result_per_message() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+                { "ErrorCode": 300, "Message": "Invalid 'To' address" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<_> = (0..3)
+            .map(|_| PostmarkMessage::new(email(), content()))
+            .collect();
+        let results = email_client.send_batch(&messages).await;
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(PostmarkError::InactiveRecipient(_))
+        ));
+        assert!(matches!(results[2], Err(PostmarkError::InvalidRequest(_))));
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn send_batch_retries_the_whole_request_on_server_errors() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 2);
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!([{ "ErrorCode": 0, "Message": "OK" }])),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_batch(&[PostmarkMessage::new(email(), content())])
+            .await;
+
+        assert!(matches!(results.as_slice(), [Ok(())]));
+    }
+
+    #[tokio::test]
+    async fn send_batch_does_not_retry_an_unreadable_success() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let results = email_client
+            .send_batch(&[PostmarkMessage::new(email(), content())])
+            .await;
+
+        assert!(matches!(
+            results.as_slice(),
+            [Err(PostmarkError::InvalidResponse(_))]
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_batch_fails_if_results_are_missing() {
+        let mock_server = MockServer::start().await;
+        let email_client = retrying_email_client(mock_server.uri(), 3);
+
+        Mock::given(path("/email/batch"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!([{ "ErrorCode": 0, "Message": "OK" }])),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<_> = (0..2)
+            .map(|_| PostmarkMessage::new(email(), content()))
+            .collect();
+        let results = email_client.send_batch(&messages).await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(PostmarkError::InvalidResponse(_)))));
+    }
+
+    #[tokio::test]
+    async fn send_batch_keeps_the_results_of_accepted_chunks() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let accepted = vec![serde_json::json!({ "ErrorCode": 0, "Message": "OK" }); MAX_BATCH_SIZE];
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(accepted))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let messages: Vec<_> = (0..MAX_BATCH_SIZE + 2)
+            .map(|_| PostmarkMessage::new(email(), content()))
+            .collect();
+        let results = email_client.send_batch(&messages).await;
+
+        assert_eq!(results.len(), MAX_BATCH_SIZE + 2);
+        assert!(results[..MAX_BATCH_SIZE].iter().all(Result::is_ok));
+        assert!(results[MAX_BATCH_SIZE..]
+            .iter()
+            .all(|result| matches!(result, Err(PostmarkError::Unavailable(503)))));
+    }
+
+    #[tokio::test]
+    async fn send_emails_tags_outbox_emails_and_reports_each_result() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let emails: Vec<_> = ["2fa-code", "welcome"]
+            .into_iter()
+            .map(|tag| PendingEmail {
+                id: uuid::Uuid::new_v4(),
+                recipient: email(),
+                content: content(),
+                tag: Some(tag.to_owned()),
+                attempts: 1,
+            })
+            .collect();
+        let results = email_client.send_emails(&emails).await;
+
+        assert!(results[0].is_ok());
+        assert!(PermanentEmailFailure::is_permanent(
+            results[1].as_ref().unwrap_err()
+        ));
+
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body[0]["Tag"], "2fa-code");
+        assert_eq!(body[0]["Metadata"]["outbox_id"], emails[0].id.to_string());
+        assert_eq!(body[1]["Tag"], "welcome");
     }
 }
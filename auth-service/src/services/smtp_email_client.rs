@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use color_eyre::eyre::{Report, Result};
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::{authentication::Credentials, PoolConfig},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use crate::{
+    domain::{Email, EmailClient, EmailContent, PermanentEmailFailure},
+    settings::{SmtpSettings, SmtpTls},
+};
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: &SmtpSettings, sender: Email, timeout: Duration) -> Result<Self> {
+        let builder = match settings.tls {
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+        };
+
+        let mut builder = builder
+            .port(settings.port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(settings.max_pool_size));
+
+        if let Some(username) = &settings.username {
+            builder = builder.credentials(Credentials::new(
+                username.to_owned(),
+                settings.password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender: sender.as_ref().expose_secret().parse()?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email over SMTP", skip_all)]
+    async fn send_email(&self, recipient: &Email, content: &EmailContent) -> Result<()> {
+        // multipart/alternative: clients that cannot render HTML fall back to the text part
+        let message = Message::builder()
+            .from(self.sender.clone())
+            .to(recipient.as_ref().expose_secret().parse()?)
+            .subject(&content.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                content.text_body.to_owned(),
+                content.html_body.to_owned(),
+            ))?;
+
+        // 5xx replies, e.g. an unknown mailbox, will be rejected again on every retry
+        self.transport.send(message).await.map_err(|e| {
+            let permanent = e.is_permanent();
+            let report = Report::new(e);
+            if permanent {
+                report.wrap_err(PermanentEmailFailure)
+            } else {
+                report
+            }
+        })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use color_eyre::eyre::Context;
+
+use crate::{
+    app_state::{TokensValidAfterCacheType, UserStoreType},
+    domain::{Email, UserStoreError},
+};
+
+// Reads and moves each user's `tokens_valid_after`, going through the cache when one is
+// configured. The user store is the source of truth, so a failing cache only costs speed.
+#[derive(Clone)]
+pub struct TokenRevocations {
+    user_store: UserStoreType,
+    cache: Option<TokensValidAfterCacheType>,
+}
+
+impl TokenRevocations {
+    pub fn new(user_store: UserStoreType) -> Self {
+        Self {
+            user_store,
+            cache: None,
+        }
+    }
+
+    pub fn with_cache(mut self, cache: TokensValidAfterCacheType) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Tokens issued before the returned time are rejected
+    #[tracing::instrument(name = "Get Tokens Valid After", skip_all)]
+    pub async fn tokens_valid_after(&self, email: &Email) -> Result<DateTime<Utc>, UserStoreError> {
+        if let Some(cache) = &self.cache {
+            match cache.get(email).await {
+                Ok(Some(valid_after)) => return Ok(valid_after),
+                Ok(None) => {}
+                Err(e) => tracing::warn!(error = ?e, "Failed to read tokens_valid_after cache"),
+            }
+        }
+
+        let valid_after = self
+            .user_store
+            .get_user(email)
+            .await?
+            .tokens_valid_after()
+            .unwrap_or(DateTime::UNIX_EPOCH);
+
+        if let Some(cache) = &self.cache {
+            if let Err(e) = cache.fill(email, valid_after).await {
+                tracing::warn!(error = ?e, "Failed to fill tokens_valid_after cache");
+            }
+        }
+
+        Ok(valid_after)
+    }
+
+    // Rejects every token issued to the user so far and returns the new `tokens_valid_after`.
+    // Token `iat`s are whole seconds, so the time is rounded up to the next second to also
+    // catch tokens issued earlier in the current one. Tokens issued in the rest of the second
+    // are rejected as well.
+    #[tracing::instrument(name = "Revoke Tokens", skip_all)]
+    pub async fn revoke_all(&self, email: &Email) -> Result<DateTime<Utc>, UserStoreError> {
+        let valid_after = Utc::now().trunc_subsecs(0) + TimeDelta::seconds(1);
+        self.user_store
+            .set_tokens_valid_after(email, valid_after)
+            .await?;
+
+        // A stale cache entry would keep the revoked tokens valid until it expires
+        if let Some(cache) = &self.cache {
+            cache
+                .set(email, valid_after)
+                .await
+                .wrap_err("Failed to update tokens_valid_after cache")
+                .map_err(UserStoreError::UnexpectedError)?;
+        }
+
+        Ok(valid_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::{
+        domain::{Password, User, UserStore},
+        services::data_stores::HashMapUserStore,
+    };
+
+    async fn revocations_with_user(email: &Email) -> TokenRevocations {
+        let user_store = HashMapUserStore::default();
+        let password = Password::parse(&Secret::new("password123!".to_owned())).unwrap();
+        user_store
+            .add_user(User::new(email.clone(), password, false))
+            .await
+            .unwrap();
+        TokenRevocations::new(Arc::new(user_store))
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_valid_since_epoch_until_revoked() {
+        let email = Email::parse("test@example.com").unwrap();
+        let revocations = revocations_with_user(&email).await;
+
+        let valid_after = revocations.tokens_valid_after(&email).await.unwrap();
+        assert_eq!(valid_after, DateTime::UNIX_EPOCH);
+
+        let revoked_at = revocations.revoke_all(&email).await.unwrap();
+        assert_eq!(revoked_at.timestamp_subsec_nanos(), 0);
+        assert!(revoked_at > Utc::now());
+        assert_eq!(
+            revocations.tokens_valid_after(&email).await.unwrap(),
+            revoked_at
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user_is_not_found() {
+        let revocations = revocations_with_user(&Email::parse("test@example.com").unwrap()).await;
+        let unknown = Email::parse("unknown@example.com").unwrap();
+
+        assert_eq!(
+            revocations.tokens_valid_after(&unknown).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            revocations.revoke_all(&unknown).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+}
@@ -1,37 +1,56 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum_extra::extract::cookie::Cookie;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use secrecy::ExposeSecret;
-use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
 use crate::{
-    domain::Email,
-    utils::constants::{JWT_COOKIE_NAME, JWT_SECRET},
+    domain::{Email, User},
+    services::TokenRevocations,
+    settings::{AuthSettings, CookieSettings},
 };
 
+// Shared with the services that verify these tokens
+pub use auth_middleware::Claims;
+
 #[tracing::instrument(name = "Generate Auth Cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
-    Ok(create_auth_cookie(token))
+pub fn generate_auth_cookie(user: &User, settings: &AuthSettings) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(user, settings)?;
+    Ok(create_auth_cookie(token, &settings.cookie))
 }
 
 #[tracing::instrument(name = "Create Auth Cookie", skip_all)]
-fn create_auth_cookie(token: String) -> Cookie<'static> {
-    Cookie::build((JWT_COOKIE_NAME, token))
-        .path("/")
+fn create_auth_cookie(token: String, settings: &CookieSettings) -> Cookie<'static> {
+    let mut cookie = Cookie::build((settings.name(), token))
+        .path(settings.path.clone())
         .http_only(true)
-        .same_site(SameSite::Lax)
-        .build()
+        .secure(settings.secure)
+        .same_site(settings.same_site.into());
+
+    if let Some(domain) = &settings.domain {
+        cookie = cookie.domain(domain.clone());
+    }
+    if let Some(max_age) = settings.max_age_seconds {
+        cookie = cookie.max_age(time::Duration::seconds(max_age));
+    }
+
+    cookie.build()
 }
 
-pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
+// Builds a cookie with the same attributes as the auth cookie so that browsers
+// match and clear it. Pass it to `CookieJar::remove` to emit the removal.
+pub fn create_removal_cookie(settings: &CookieSettings) -> Cookie<'static> {
+    create_auth_cookie(String::new(), settings)
+}
 
 #[tracing::instrument(name = "Generate Auth Token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .wrap_err("Failed to create 10 minute time delta")?;
+fn generate_auth_token(user: &User, settings: &AuthSettings) -> Result<String> {
+    let delta = chrono::Duration::try_seconds(settings.token_ttl_seconds)
+        .wrap_err("Failed to create token TTL time delta")?;
 
-    let expiration = chrono::Utc::now()
+    let now = chrono::Utc::now();
+    let expiration = now
         .checked_add_signed(delta)
         .ok_or(eyre!("Failed to add time delta to current time"))?
         .timestamp();
@@ -40,51 +59,136 @@ fn generate_auth_token(email: &Email) -> Result<String> {
         .try_into()
         .wrap_err("Failed to convert expiration to usize")?;
 
-    let sub = email.as_ref().expose_secret().to_string();
+    let issued_at: usize = now
+        .timestamp()
+        .try_into()
+        .wrap_err("Failed to convert issue time to usize")?;
+
+    let sub = user.email().expose_secret().to_string();
 
     let claims = Claims {
         sub,
         exp: expiration,
+        iat: issued_at,
+        jti: Uuid::new_v4().to_string(),
+        roles: user.role_names(),
+        permissions: user.permissions(),
     };
 
-    create_token(&claims)
+    create_token(&claims, settings)
 }
 
 #[tracing::instrument(name = "Validate Token", skip_all)]
-pub async fn validate_token(token: &str) -> Result<Claims> {
-    decode::<Claims>(
+pub async fn validate_token(
+    token: &str,
+    settings: &AuthSettings,
+    revocations: &TokenRevocations,
+) -> Result<Claims> {
+    // No leeway: tokens are accepted until exactly `exp`, so bans can expire at `exp` too
+    let mut validation = Validation::default();
+    validation.leeway = 0;
+
+    let mut claims = decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
-        &Validation::default(),
+        &DecodingKey::from_secret(settings.jwt_secret.expose_secret().as_bytes()),
+        &validation,
     )
     .map(|data| data.claims)
-    .map_err(|e| eyre!("Token validation error: {}", e))
+    .map_err(|e| eyre!("Token validation error: {}", e))?;
+
+    // Tokens issued before `jti` was added are identified by their SHA-256 digest instead
+    if claims.jti.is_empty() {
+        claims.jti = format!("{:x}", Sha256::digest(token));
+    }
+
+    // Tokens issued before `iat` was added count as issued at the epoch, so they are
+    // rejected as soon as the user revokes their tokens for the first time
+    let email = Email::parse(&claims.sub).wrap_err("Token subject is not a valid email")?;
+    let valid_after = revocations
+        .tokens_valid_after(&email)
+        .await
+        .wrap_err("Failed to look up when the user's tokens were revoked")?;
+    if claims.issued_at() < valid_after {
+        return Err(eyre!("Token was revoked"));
+    }
+
+    Ok(claims)
 }
 
 #[tracing::instrument(name = "Create Token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
+fn create_token(claims: &Claims, settings: &AuthSettings) -> Result<String> {
     jsonwebtoken::encode(
         &jsonwebtoken::Header::default(),
         claims,
-        &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
+        &jsonwebtoken::EncodingKey::from_secret(settings.jwt_secret.expose_secret().as_bytes()),
     )
     .map_err(|e| eyre!("Failed to create token: {}", e))
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Claims {
-    pub sub: String,
-    pub exp: usize,
-}
-
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+
+    use axum_extra::extract::cookie::SameSite;
+    use chrono::{DateTime, Utc};
+    use secrecy::Secret;
+    use serde::Serialize;
+
     use super::*;
+    use crate::{
+        domain::{
+            Password, Role, UserStore, ADMIN_ROLE, USERS_READ_PERMISSION, USERS_WRITE_PERMISSION,
+        },
+        services::data_stores::HashMapUserStore,
+        settings::SameSiteSetting,
+        utils::JWT_COOKIE_NAME,
+    };
+
+    fn settings() -> AuthSettings {
+        AuthSettings {
+            jwt_secret: Secret::new("secret".to_owned()),
+            token_ttl_seconds: 600,
+            cookie: CookieSettings {
+                name: JWT_COOKIE_NAME.to_owned(),
+                path: "/".to_owned(),
+                domain: None,
+                secure: false,
+                same_site: SameSiteSetting::Lax,
+                max_age_seconds: None,
+                host_prefix: false,
+            },
+        }
+    }
+
+    fn user(email: &Email) -> User {
+        let password = Password::parse(&Secret::new("password123!".to_owned())).unwrap();
+        User::new(email.clone(), password, false)
+    }
+
+    // Backed by a user store holding test@example.com, the subject of the tokens below
+    async fn revocations() -> TokenRevocations {
+        let user_store = HashMapUserStore::default();
+        let email = Email::parse("test@example.com").unwrap();
+        user_store.add_user(user(&email)).await.unwrap();
+        TokenRevocations::new(Arc::new(user_store))
+    }
+
+    fn token_issued_at(issued_at: DateTime<Utc>) -> String {
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (issued_at.timestamp() + 600) as usize,
+            iat: issued_at.timestamp() as usize,
+            jti: Uuid::new_v4().to_string(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        };
+        create_token(&claims, &settings()).unwrap()
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
         let email = Email::parse("test@example.com").unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(&user(&email), &settings()).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(
             cookie.value().split('.').count(),
@@ -99,7 +203,7 @@ mod tests {
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test.token.value";
-        let cookie = create_auth_cookie(token.to_string());
+        let cookie = create_auth_cookie(token.to_string(), &settings().cookie);
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value(), token);
         assert_eq!(cookie.path(), Some("/"));
@@ -107,19 +211,49 @@ mod tests {
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
     }
 
+    #[tokio::test]
+    async fn test_create_auth_cookie_with_configured_attributes() {
+        let mut settings = settings().cookie;
+        settings.path = "/auth".to_owned();
+        settings.domain = Some("ddrcode.me".to_owned());
+        settings.secure = true;
+        settings.same_site = SameSiteSetting::Strict;
+        settings.max_age_seconds = Some(600);
+
+        let cookie = create_auth_cookie("test.token.value".to_string(), &settings);
+        assert_eq!(cookie.path(), Some("/auth"));
+        assert_eq!(cookie.domain(), Some("ddrcode.me"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(cookie.max_age(), Some(time::Duration::seconds(600)));
+    }
+
+    #[tokio::test]
+    async fn test_create_auth_cookie_with_host_prefix() {
+        let mut settings = settings().cookie;
+        settings.secure = true;
+        settings.host_prefix = true;
+
+        let cookie = create_auth_cookie("test.token.value".to_string(), &settings);
+        assert_eq!(cookie.name(), format!("__Host-{JWT_COOKIE_NAME}"));
+        assert_eq!(cookie.domain(), None);
+    }
+
     #[tokio::test]
     async fn test_generate_auth_token() {
         let email = Email::parse("test@example.com").unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&user(&email), &settings()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let email = Email::parse("test@example.com").unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let token = generate_auth_token(&user(&email), &settings()).unwrap();
 
-        let result = validate_token(&token).await.unwrap();
+        let result = validate_token(&token, &settings(), &revocations().await)
+            .await
+            .unwrap();
         assert_eq!(result.sub, "test@example.com");
 
         let exp = chrono::Utc::now()
@@ -130,10 +264,151 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_generated_token_carries_user_roles_and_permissions() {
+        let email = Email::parse("test@example.com").unwrap();
+        let revocations = revocations().await;
+
+        let token = generate_auth_token(&user(&email), &settings()).unwrap();
+        let claims = validate_token(&token, &settings(), &revocations)
+            .await
+            .unwrap();
+        assert!(claims.roles.is_empty());
+        assert!(claims.permissions.is_empty());
+
+        let admin = user(&email).with_roles(vec![Role::admin()]);
+        let token = generate_auth_token(&admin, &settings()).unwrap();
+        let claims = validate_token(&token, &settings(), &revocations)
+            .await
+            .unwrap();
+        assert!(claims.has_role(ADMIN_ROLE));
+        assert!(claims.has_permission(USERS_READ_PERMISSION));
+        assert!(claims.has_permission(USERS_WRITE_PERMISSION));
+        assert!(!claims.has_permission("users:impersonate"));
+    }
+
+    #[tokio::test]
+    async fn test_generated_tokens_have_unique_jti() {
+        let email = Email::parse("test@example.com").unwrap();
+        let first = generate_auth_token(&user(&email), &settings()).unwrap();
+        let second = generate_auth_token(&user(&email), &settings()).unwrap();
+
+        let revocations = revocations().await;
+        let first = validate_token(&first, &settings(), &revocations)
+            .await
+            .unwrap();
+        let second = validate_token(&second, &settings(), &revocations)
+            .await
+            .unwrap();
+        assert!(Uuid::parse_str(&first.jti).is_ok());
+        assert_ne!(first.jti, second.jti);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_identifies_legacy_token_by_digest() {
+        #[derive(Serialize)]
+        struct LegacyClaims {
+            sub: String,
+            exp: usize,
+        }
+
+        let claims = LegacyClaims {
+            sub: "test@example.com".to_owned(),
+            exp: (chrono::Utc::now().timestamp() + 600) as usize,
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        let result = validate_token(&token, &settings(), &revocations().await)
+            .await
+            .unwrap();
+        assert_eq!(result.jti, format!("{:x}", Sha256::digest(&token)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_token_past_exp() {
+        let claims = Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (chrono::Utc::now().timestamp() - 1) as usize,
+            iat: (chrono::Utc::now().timestamp() - 601) as usize,
+            jti: Uuid::new_v4().to_string(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        };
+        let token = create_token(&claims, &settings()).unwrap();
+
+        assert!(validate_token(&token, &settings(), &revocations().await)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_token_issued_before_revocation() {
+        let revocations = revocations().await;
+        let email = Email::parse("test@example.com").unwrap();
+        let revoked_at = revocations.revoke_all(&email).await.unwrap();
+
+        // Includes tokens issued earlier in the second of the revocation
+        let earlier = token_issued_at(revoked_at - chrono::Duration::seconds(1));
+        assert!(validate_token(&earlier, &settings(), &revocations)
+            .await
+            .is_err());
+
+        let later = token_issued_at(revoked_at);
+        assert!(validate_token(&later, &settings(), &revocations)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_legacy_token_once_revoked() {
+        #[derive(Serialize)]
+        struct LegacyClaims {
+            sub: String,
+            exp: usize,
+        }
+
+        let claims = LegacyClaims {
+            sub: "test@example.com".to_owned(),
+            exp: (chrono::Utc::now().timestamp() + 600) as usize,
+        };
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+
+        let revocations = revocations().await;
+        assert!(validate_token(&token, &settings(), &revocations)
+            .await
+            .is_ok());
+
+        let email = Email::parse("test@example.com").unwrap();
+        revocations.revoke_all(&email).await.unwrap();
+        assert!(validate_token(&token, &settings(), &revocations)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_rejects_token_of_unknown_user() {
+        let email = Email::parse("unknown@example.com").unwrap();
+        let token = generate_auth_token(&user(&email), &settings()).unwrap();
+
+        assert!(validate_token(&token, &settings(), &revocations().await)
+            .await
+            .is_err());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let result = validate_token(&token).await;
+        let result = validate_token(&token, &settings(), &revocations().await).await;
         assert!(result.is_err());
     }
 }
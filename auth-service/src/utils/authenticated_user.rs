@@ -0,0 +1,183 @@
+use std::marker::PhantomData;
+
+use auth_middleware::AuthConfig;
+pub use auth_middleware::{AuthError, AuthenticatedUser, TokenVerifier};
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, ADMIN_ROLE, USERS_READ_PERMISSION, USERS_WRITE_PERMISSION},
+    utils::{validate_token, Claims},
+};
+
+// Accepts tokens that are valid, unrevoked and not banned. Used by the `AuthenticatedUser`
+// extractor and /verify-token, which other services verify their tokens through.
+pub struct AppStateVerifier(pub AppState);
+
+#[async_trait]
+impl TokenVerifier for AppStateVerifier {
+    async fn verify(&self, token: &str, _headers: &HeaderMap) -> Result<Claims, AuthError> {
+        let state = &self.0;
+        let claims = validate_token(token, &state.settings.auth, &state.token_revocations)
+            .await
+            .map_err(|_| AuthError::InvalidToken)?;
+
+        // A token that was used to log out can't be used for anything else
+        let is_banned = state
+            .banned_token_store
+            .is_token_banned(&claims)
+            .await
+            .map_err(|e| AuthError::Unexpected(e.into()))?;
+
+        match is_banned {
+            true => Err(AuthError::InvalidToken),
+            false => Ok(claims),
+        }
+    }
+}
+
+// Lets handlers take an `AuthenticatedUser`, read from the auth cookie or a bearer token.
+// Take it as `Result<AuthenticatedUser, AuthError>` to handle (e.g. audit) the rejection.
+impl FromRef<AppState> for AuthConfig {
+    fn from_ref(state: &AppState) -> Self {
+        AuthConfig::new(
+            state.settings.auth.cookie.name(),
+            AppStateVerifier(state.clone()),
+        )
+    }
+}
+
+// A role a route requires, e.g. `RequireRole<Admin>`
+pub trait RequiredRole {
+    const ROLE: &'static str;
+}
+
+// A permission a route requires, e.g. `RequirePermission<ReadUsers>`
+pub trait RequiredPermission {
+    const PERMISSION: &'static str;
+}
+
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const ROLE: &'static str = ADMIN_ROLE;
+}
+
+pub struct ReadUsers;
+
+impl RequiredPermission for ReadUsers {
+    const PERMISSION: &'static str = USERS_READ_PERMISSION;
+}
+
+pub struct WriteUsers;
+
+impl RequiredPermission for WriteUsers {
+    const PERMISSION: &'static str = USERS_WRITE_PERMISSION;
+}
+
+// An `AuthenticatedUser` whose token carries the role `R`, rejects with 403 otherwise
+pub struct RequireRole<R> {
+    pub claims: Claims,
+    _role: PhantomData<fn() -> R>,
+}
+
+#[async_trait]
+impl<R: RequiredRole> FromRequestParts<AppState> for RequireRole<R> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser(claims) = AuthenticatedUser::from_request_parts(parts, state).await?;
+        require_role::<R>(&claims)?;
+
+        Ok(Self {
+            claims,
+            _role: PhantomData,
+        })
+    }
+}
+
+// An `AuthenticatedUser` whose token carries the permission `P`, rejects with 403 otherwise
+pub struct RequirePermission<P> {
+    pub claims: Claims,
+    _permission: PhantomData<fn() -> P>,
+}
+
+#[async_trait]
+impl<P: RequiredPermission> FromRequestParts<AppState> for RequirePermission<P> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthenticatedUser(claims) = AuthenticatedUser::from_request_parts(parts, state).await?;
+        require_permission::<P>(&claims)?;
+
+        Ok(Self {
+            claims,
+            _permission: PhantomData,
+        })
+    }
+}
+
+pub fn require_role<R: RequiredRole>(claims: &Claims) -> Result<(), AuthAPIError> {
+    match claims.has_role(R::ROLE) {
+        true => Ok(()),
+        false => Err(AuthAPIError::Forbidden),
+    }
+}
+
+pub fn require_permission<P: RequiredPermission>(claims: &Claims) -> Result<(), AuthAPIError> {
+    match claims.has_permission(P::PERMISSION) {
+        true => Ok(()),
+        false => Err(AuthAPIError::Forbidden),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(roles: &[&str], permissions: &[&str]) -> Claims {
+        Claims {
+            sub: "test@example.com".to_owned(),
+            exp: usize::MAX,
+            iat: 0,
+            jti: "jti".to_owned(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_require_role() {
+        assert!(require_role::<Admin>(&claims(&[ADMIN_ROLE], &[])).is_ok());
+        assert!(matches!(
+            require_role::<Admin>(&claims(&["support"], &[USERS_READ_PERMISSION])),
+            Err(AuthAPIError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn test_require_permission() {
+        let reader = claims(&["support"], &[USERS_READ_PERMISSION]);
+
+        assert!(require_permission::<ReadUsers>(&reader).is_ok());
+        assert!(matches!(
+            require_permission::<WriteUsers>(&reader),
+            Err(AuthAPIError::Forbidden)
+        ));
+        // Having a role doesn't imply its permissions, only the token's permissions count
+        assert!(matches!(
+            require_permission::<ReadUsers>(&claims(&[ADMIN_ROLE], &[])),
+            Err(AuthAPIError::Forbidden)
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts, HeaderMap},
+};
+
+use crate::{app_state::AppState, domain::ClientInfo};
+
+const REAL_IP_HEADER: &str = "x-real-ip";
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let peer_ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip().to_string());
+
+        Ok(client_info(
+            &parts.headers,
+            peer_ip,
+            state.settings.audit.trust_proxy_headers,
+        ))
+    }
+}
+
+fn client_info(
+    headers: &HeaderMap,
+    peer_ip: Option<String>,
+    trust_proxy_headers: bool,
+) -> ClientInfo {
+    let header_value = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(ToOwned::to_owned)
+    };
+
+    let proxy_ip = trust_proxy_headers
+        .then(|| header_value(REAL_IP_HEADER))
+        .flatten();
+
+    ClientInfo {
+        ip: proxy_ip.or(peer_ip),
+        user_agent: header_value(header::USER_AGENT.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(REAL_IP_HEADER, HeaderValue::from_static("203.0.113.7"));
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl/8.0"));
+        headers
+    }
+
+    #[test]
+    fn test_proxy_header_ignored_unless_trusted() {
+        let client = client_info(&headers(), Some("10.0.0.1".to_owned()), false);
+
+        assert_eq!(client.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(client.user_agent.as_deref(), Some("curl/8.0"));
+    }
+
+    #[test]
+    fn test_trusted_proxy_header_overrides_peer_ip() {
+        let client = client_info(&headers(), Some("10.0.0.1".to_owned()), true);
+        assert_eq!(client.ip.as_deref(), Some("203.0.113.7"));
+
+        let client = client_info(&HeaderMap::new(), Some("10.0.0.1".to_owned()), true);
+        assert_eq!(client.ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(client.user_agent, None);
+    }
+}
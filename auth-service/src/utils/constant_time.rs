@@ -0,0 +1,21 @@
+use subtle::ConstantTimeEq;
+
+// Compares secrets without short-circuiting on the first differing byte, so response timing
+// does not reveal how much of a guess was right. Only the lengths may leak.
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("123456", "123456"));
+        assert!(constant_time_eq("", ""));
+        assert!(!constant_time_eq("123456", "123457"));
+        assert!(!constant_time_eq("123456", "12345"));
+        assert!(!constant_time_eq("123456", ""));
+    }
+}
@@ -0,0 +1,143 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use color_eyre::eyre::{eyre, Result};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use super::REQUEST_ID_HEADER;
+
+// A single entry of `application.allowed_origins`. Either an exact origin such as
+// `https://lgr.ddrcode.me`, or a wildcard subdomain pattern such as `https://*.ddrcode.me`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OriginPattern {
+    Exact(HeaderValue),
+    WildcardSubdomain { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    pub fn parse(origin: &str) -> Result<Self> {
+        let (scheme, authority) = origin
+            .split_once("://")
+            .ok_or(eyre!("Origin must include a scheme: {origin}"))?;
+
+        if scheme != "http" && scheme != "https" {
+            return Err(eyre!("Origin scheme must be http or https: {origin}"));
+        }
+        if authority.is_empty() || authority.contains('/') {
+            return Err(eyre!("Origin must not contain a path: {origin}"));
+        }
+
+        match authority.strip_prefix("*.") {
+            Some(suffix) => {
+                if suffix.is_empty() || suffix.contains('*') {
+                    return Err(eyre!("Invalid wildcard origin: {origin}"));
+                }
+                Ok(Self::WildcardSubdomain {
+                    scheme: scheme.to_owned(),
+                    suffix: suffix.to_ascii_lowercase(),
+                })
+            }
+            None if authority.contains('*') => Err(eyre!(
+                "Wildcards are only supported as the leftmost label: {origin}"
+            )),
+            None => Ok(Self::Exact(origin.parse()?)),
+        }
+    }
+
+    pub fn matches(&self, origin: &HeaderValue) -> bool {
+        match self {
+            Self::Exact(allowed) => allowed == origin,
+            Self::WildcardSubdomain { scheme, suffix } => {
+                let Ok(origin) = origin.to_str() else {
+                    return false;
+                };
+                let origin = origin.to_ascii_lowercase();
+
+                origin
+                    .strip_prefix(scheme.as_str())
+                    .and_then(|rest| rest.strip_prefix("://"))
+                    .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+                    .and_then(|rest| rest.strip_suffix('.'))
+                    .is_some_and(|subdomain| {
+                        !subdomain.is_empty() && subdomain.split('.').all(is_valid_label)
+                    })
+            }
+        }
+    }
+}
+
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+pub fn create_cors_layer(allowed_origins: &[String]) -> Result<CorsLayer> {
+    let patterns = allowed_origins
+        .iter()
+        .map(|origin| OriginPattern::parse(origin))
+        .collect::<Result<Vec<_>>>()?;
+
+    let allow_origin = AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+        patterns.iter().any(|pattern| pattern.matches(origin))
+    });
+
+    Ok(CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_credentials(true)
+        .allow_origin(allow_origin)
+        .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(origin: &str) -> HeaderValue {
+        HeaderValue::from_str(origin).unwrap()
+    }
+
+    #[test]
+    fn test_exact_origin_matches_only_itself() {
+        let pattern = OriginPattern::parse("https://lgr.ddrcode.me").unwrap();
+
+        assert!(pattern.matches(&header("https://lgr.ddrcode.me")));
+        assert!(!pattern.matches(&header("http://lgr.ddrcode.me")));
+        assert!(!pattern.matches(&header("https://evil.lgr.ddrcode.me")));
+    }
+
+    #[test]
+    fn test_wildcard_origin_matches_subdomains() {
+        let pattern = OriginPattern::parse("https://*.ddrcode.me").unwrap();
+
+        assert!(pattern.matches(&header("https://lgr.ddrcode.me")));
+        assert!(pattern.matches(&header("https://a.b.ddrcode.me")));
+        assert!(!pattern.matches(&header("https://ddrcode.me")));
+        assert!(!pattern.matches(&header("https://evilddrcode.me")));
+        assert!(!pattern.matches(&header("http://lgr.ddrcode.me")));
+        assert!(!pattern.matches(&header("https://lgr.ddrcode.me.evil.com")));
+    }
+
+    #[test]
+    fn test_wildcard_origin_with_port() {
+        let pattern = OriginPattern::parse("http://*.localhost:8000").unwrap();
+
+        assert!(pattern.matches(&header("http://app.localhost:8000")));
+        assert!(!pattern.matches(&header("http://app.localhost:9000")));
+    }
+
+    #[test]
+    fn test_invalid_origins() {
+        let invalid_origins = [
+            "lgr.ddrcode.me",
+            "ftp://lgr.ddrcode.me",
+            "https://lgr.ddrcode.me/path",
+            "https://lgr.*.me",
+            "https://*.",
+            "*",
+        ];
+
+        for origin in invalid_origins {
+            assert!(
+                OriginPattern::parse(origin).is_err(),
+                "Expected {origin} to be invalid"
+            );
+        }
+    }
+}
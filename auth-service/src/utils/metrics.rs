@@ -0,0 +1,173 @@
+use std::{future::Future, sync::OnceLock, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use color_eyre::eyre::{eyre, Result};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use sqlx::PgPool;
+
+pub mod names {
+    pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+    pub const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+    pub const LOGINS_TOTAL: &str = "auth_logins_total";
+    pub const TWO_FA_VERIFICATIONS_TOTAL: &str = "auth_2fa_verifications_total";
+    pub const PASSWORD_HASH_DURATION_SECONDS: &str = "password_hash_duration_seconds";
+    pub const STORE_OPERATION_DURATION_SECONDS: &str = "store_operation_duration_seconds";
+    pub const EMAILS_SENT_TOTAL: &str = "emails_sent_total";
+    pub const EMAIL_SEND_FAILURES_TOTAL: &str = "email_send_failures_total";
+    pub const EMAILS_DEAD_LETTERED_TOTAL: &str = "emails_dead_lettered_total";
+    pub const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+    pub const DB_POOL_MAX_CONNECTIONS: &str = "db_pool_max_connections";
+}
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+// Installs the global Prometheus recorder. Safe to call more than once (every
+// `Application::build` in the integration tests does), later calls reuse the first recorder.
+pub fn init_metrics() -> Result<PrometheusHandle> {
+    if let Some(handle) = PROMETHEUS_HANDLE.get() {
+        return Ok(handle.clone());
+    }
+
+    let recorder = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_owned()),
+            DURATION_BUCKETS,
+        )?
+        .build_recorder();
+    let handle = recorder.handle();
+
+    if PROMETHEUS_HANDLE.set(handle).is_ok() {
+        metrics::set_global_recorder(recorder)
+            .map_err(|e| eyre!("Failed to install metrics recorder: {e}"))?;
+    }
+
+    PROMETHEUS_HANDLE
+        .get()
+        .cloned()
+        .ok_or(eyre!("Metrics recorder was not initialized"))
+}
+
+pub fn render_metrics() -> String {
+    PROMETHEUS_HANDLE
+        .get()
+        .map(|handle| handle.render())
+        .unwrap_or_default()
+}
+
+// Labels the TraceLayer `on_response` callback needs but only has access to on the request.
+#[derive(Clone)]
+pub struct RequestLabels {
+    method: String,
+    route: String,
+}
+
+// Middleware copying the request method and matched route onto the response, so that
+// `record_http_request` can label metrics by route template rather than raw URI.
+pub async fn propagate_request_labels(request: Request<Body>, next: Next) -> Response {
+    let labels = RequestLabels {
+        method: request.method().to_string(),
+        route: request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_owned())
+            .unwrap_or(UNMATCHED_ROUTE.to_owned()),
+    };
+
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(labels);
+    response
+}
+
+pub fn record_http_request(response: &Response, latency: Duration) {
+    let status = response.status().as_u16();
+    let (method, route) = match response.extensions().get::<RequestLabels>() {
+        Some(labels) => (labels.method.clone(), labels.route.clone()),
+        None => ("UNKNOWN".to_owned(), UNMATCHED_ROUTE.to_owned()),
+    };
+
+    let labels = [
+        ("method", method),
+        ("route", route.clone()),
+        ("status", status.to_string()),
+    ];
+    metrics::counter!(names::HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    metrics::histogram!(names::HTTP_REQUEST_DURATION_SECONDS, &labels).record(latency);
+
+    match route.as_str() {
+        "/login" => {
+            let outcome = match status {
+                200 => "success",
+                206 => "2fa_required",
+                400..=499 => "failure",
+                _ => "error",
+            };
+            metrics::counter!(names::LOGINS_TOTAL, "outcome" => outcome).increment(1);
+        }
+        "/verify-2fa" => {
+            let outcome = match status {
+                200 => "success",
+                400..=499 => "failure",
+                _ => "error",
+            };
+            metrics::counter!(names::TWO_FA_VERIFICATIONS_TOTAL, "outcome" => outcome).increment(1);
+        }
+        _ => {}
+    }
+}
+
+// Times a store call and records it under the given store and operation labels.
+pub async fn record_store_operation<T, E>(
+    store: &'static str,
+    operation: &'static str,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = std::time::Instant::now();
+    let result = future.await;
+
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    metrics::histogram!(
+        names::STORE_OPERATION_DURATION_SECONDS,
+        "store" => store,
+        "operation" => operation,
+        "outcome" => outcome
+    )
+    .record(started.elapsed());
+
+    result
+}
+
+pub fn record_password_hash_duration(operation: &'static str, duration: Duration) {
+    metrics::histogram!(names::PASSWORD_HASH_DURATION_SECONDS, "operation" => operation)
+        .record(duration);
+}
+
+pub fn record_pool_usage(pool: &PgPool) {
+    let size = pool.size() as f64;
+    let idle = pool.num_idle() as f64;
+
+    metrics::gauge!(names::DB_POOL_CONNECTIONS, "state" => "idle").set(idle);
+    metrics::gauge!(names::DB_POOL_CONNECTIONS, "state" => "active").set(size - idle);
+    metrics::gauge!(names::DB_POOL_MAX_CONNECTIONS)
+        .set(pool.options().get_max_connections() as f64);
+}
+
+// Periodically samples Postgres pool usage; the task ends when the pool is closed.
+pub fn spawn_pool_metrics_task(pool: PgPool, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        while !pool.is_closed() {
+            ticker.tick().await;
+            record_pool_usage(&pool);
+        }
+    })
+}
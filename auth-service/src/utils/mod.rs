@@ -1,7 +1,22 @@
 mod auth;
+mod authenticated_user;
+mod client_info;
+mod clock;
+mod constant_time;
 pub mod constants;
+mod cors;
+mod metrics;
+mod retry;
+mod shutdown;
 mod tracing;
 
 pub use auth::*;
+pub use authenticated_user::*;
+pub use clock::*;
+pub use constant_time::*;
 pub use constants::*;
+pub use cors::*;
+pub use metrics::*;
+pub use retry::*;
+pub use shutdown::*;
 pub use tracing::*;
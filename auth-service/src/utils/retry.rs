@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+// Exponential backoff between attempts: base_delay, 2 * base_delay, 4 * base_delay...
+// capped at max_delay. Callers give up once max_attempts attempts have failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    // A single attempt, never retried
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    // Delay before the next attempt, after `attempt` attempts have failed
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        self.base_delay
+            .checked_mul(1 << exponent)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    // Like `backoff`, but picks a random delay between half and all of it, so that clients
+    // failing at the same time do not all retry at the same time
+    pub fn backoff_with_jitter(&self, attempt: u32) -> Duration {
+        let backoff = self.backoff(attempt);
+        let half = backoff / 2;
+        half + (backoff - half).mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_after_each_attempt() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(8));
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = policy();
+
+        assert_eq!(policy.backoff(5), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_jittered_backoff_stays_within_bounds() {
+        let policy = policy();
+
+        for attempt in 1..=6 {
+            let backoff = policy.backoff(attempt);
+            for _ in 0..100 {
+                let delay = policy.backoff_with_jitter(attempt);
+                assert!(delay >= backoff / 2 && delay <= backoff, "{delay:?}");
+            }
+        }
+    }
+}
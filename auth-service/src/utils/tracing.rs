@@ -1,44 +1,117 @@
 use std::time::Duration;
 
-use axum::{body::Body, extract::Request, response::Response};
+use axum::{body::Body, extract::Request, http::HeaderMap, response::Response};
 use color_eyre::eyre::Result;
+use opentelemetry::{
+    propagation::TextMapPropagator,
+    trace::{TraceContextExt, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+};
 use tracing::{Level, Span};
 use tracing_error::ErrorLayer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use uuid::Uuid;
 
-pub fn init_tracing() -> Result<()> {
-    // Create formatting layer for tracing output
-    let fmt_layer = fmt::layer().compact();
+use super::{record_http_request, REQUEST_ID_HEADER};
+use crate::settings::{LogFormat, TracingSettings};
+
+pub fn init_tracing(settings: &TracingSettings) -> Result<()> {
+    // Create formatting layer for tracing output, either compact text or
+    // one JSON object per line (including the current span's fields) for log shipping
+    let (compact_layer, json_layer) = match settings.log_format {
+        LogFormat::Compact => (Some(fmt::layer().compact()), None),
+        LogFormat::Json => (
+            None,
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            ),
+        ),
+    };
 
     // Create filter layer to control verbosity of logs
     // Get filter config from environment variables
     // If fails, defaults to "info" log level
     let filter_layer = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
 
+    // Create OpenTelemetry layer so spans carry W3C trace context. Spans are only
+    // exported when an OTLP endpoint is configured.
+    let tracer_provider = build_tracer_provider(settings)?;
+    let otel_layer =
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("auth-service"));
+    opentelemetry::global::set_tracer_provider(tracer_provider);
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
     // Build the tracing subscriber registery with the formatting layer,
-    // filter layer, and the error layer for enhanced error reporting
+    // filter layer, OpenTelemetry layer and the error layer for enhanced error reporting
     tracing_subscriber::registry()
         .with(filter_layer)
-        .with(fmt_layer)
+        .with(compact_layer)
+        .with(json_layer)
+        .with(otel_layer)
         .with(ErrorLayer::default())
         .init();
 
     Ok(())
 }
 
-// Creates a new tacing span with a unique request ID for each incoming request.
-// This helps in tracking and correlating logs for individual requests.
+fn build_tracer_provider(settings: &TracingSettings) -> Result<TracerProvider> {
+    let resource = Resource::new([KeyValue::new(
+        "service.name",
+        settings.service_name.to_owned(),
+    )]);
+    let mut builder = TracerProvider::builder().with_resource(resource);
+
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()?;
+        builder = builder.with_batch_exporter(exporter, runtime::Tokio);
+    }
+
+    Ok(builder.build())
+}
+
+// Extracts the W3C `traceparent`/`tracestate` context sent by the caller, if any.
+pub fn extract_trace_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+// Creates a new tracing span for each incoming request. The request ID comes from the
+// `X-Request-Id` header (set by `SetRequestIdLayer` when the caller did not send one),
+// and the span joins the caller's trace when a `traceparent` header is present.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = Uuid::new_v4();
-    tracing::span!(
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
         uri = tracing::field::display(request.uri()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+    );
+
+    let parent = extract_trace_context(request.headers());
+    if parent.span().span_context().is_valid() {
+        span.set_parent(parent);
+    }
+
+    span
 }
 
 // Logs an event indicating the start of a request.
@@ -48,7 +121,10 @@ pub fn on_request(_request: &Request<Body>, _span: &Span) {
 
 // Logs an event indicating the end of a request, including it's latency and status code.
 // If the status code indicates an error, logs at ERROR level.
+// Also records the request in the Prometheus metrics.
 pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
+    record_http_request(response, latency);
+
     let status = response.status();
     let status_code = status.as_u16();
     let status_code_class = status_code / 100;
@@ -72,3 +148,45 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_trace_context_from_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_static("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+        );
+
+        let context = extract_trace_context(&headers);
+        let span = context.span();
+        let span_context = span.span_context();
+
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(span_context.span_id().to_string(), "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn test_extract_trace_context_ignores_missing_or_malformed_header() {
+        let mut headers = HeaderMap::new();
+        assert!(!extract_trace_context(&headers)
+            .span()
+            .span_context()
+            .is_valid());
+
+        headers.insert("traceparent", HeaderValue::from_static("not-a-traceparent"));
+        assert!(!extract_trace_context(&headers)
+            .span()
+            .span_context()
+            .is_valid());
+    }
+}
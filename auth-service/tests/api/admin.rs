@@ -0,0 +1,441 @@
+use auth_service::{
+    domain::{ADMIN_ROLE, USERS_READ_PERMISSION},
+    routes::{AdminUserView, ListUsersResponse},
+    utils::JWT_COOKIE_NAME,
+};
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "validPass123!";
+
+#[derive(Debug, PartialEq, sqlx::FromRow)]
+struct AdminAuditRow {
+    kind: String,
+    actor: Option<String>,
+    target: Option<String>,
+    outcome: String,
+}
+
+async fn admin_audit_rows(app: &TestApp) -> Vec<AdminAuditRow> {
+    sqlx::query_as::<_, AdminAuditRow>(
+        "SELECT kind, actor, target, outcome FROM audit_events \
+         WHERE kind LIKE 'admin_%' ORDER BY occurred_at",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to read audit events")
+}
+
+fn audit_row(kind: &str, actor: &str, target: Option<&str>, outcome: &str) -> AdminAuditRow {
+    AdminAuditRow {
+        kind: kind.to_owned(),
+        actor: Some(actor.to_owned()),
+        target: target.map(str::to_owned),
+        outcome: outcome.to_owned(),
+    }
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Logs in, leaving the user's auth cookie in the app's cookie jar, and returns its token
+async fn login(app: &TestApp, email: &str) -> String {
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": PASSWORD,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+// Signs up an admin and logs in as them
+async fn login_as_admin(app: &TestApp) -> String {
+    let email = get_random_email();
+    signup(app, &email).await;
+    app.assign_role(&email, ADMIN_ROLE).await;
+    login(app, &email).await;
+    email
+}
+
+async fn verify_token_status(app: &TestApp, token: &str) -> u16 {
+    app.verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_admin_users("").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{JWT_COOKIE_NAME}=invalid_token; Path=/; HttpOnly; SameSite=Lax"),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.get_admin_users("").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_403_for_non_admins_and_audit_the_attempt() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let target = get_random_email();
+    signup(&app, &email).await;
+    signup(&app, &target).await;
+    login(&app, &email).await;
+
+    let response = app.post_admin_user_action(&target, "disable").await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let stored = app.get_admin_user(&target).await;
+    assert_eq!(stored.status().as_u16(), 403);
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_disabled", &email, Some(&target), "failure"),
+            audit_row("admin_user_viewed", &email, Some(&target), "failure"),
+        ]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_only_allow_what_the_users_permissions_grant() {
+    let mut app = TestApp::new().await;
+
+    // A role that can look at users but not change them
+    sqlx::query("INSERT INTO roles (name) VALUES ('support')")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO role_permissions (role, permission) VALUES ('support', $1)")
+        .bind(USERS_READ_PERMISSION)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let email = get_random_email();
+    let target = get_random_email();
+    signup(&app, &email).await;
+    signup(&app, &target).await;
+    app.assign_role(&email, "support").await;
+    login(&app, &email).await;
+
+    let response = app.get_admin_user(&target).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user = response
+        .json::<AdminUserView>()
+        .await
+        .expect("Could not deserialize response body to AdminUserView");
+    assert!(user.roles.is_empty());
+
+    let response = app.post_admin_user_action(&target, "disable").await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_viewed", &email, Some(&target), "success"),
+            audit_row("admin_user_disabled", &email, Some(&target), "failure"),
+        ]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_list_users_in_pages() {
+    let mut app = TestApp::new().await;
+
+    let admin = login_as_admin(&app).await;
+    // A shared prefix keeps the search to these users
+    let prefix = uuid::Uuid::new_v4().simple().to_string();
+    let emails: Vec<_> = (0..3)
+        .map(|i| format!("{prefix}-{i}@example.com"))
+        .collect();
+    for email in &emails {
+        signup(&app, email).await;
+    }
+
+    let response = app
+        .get_admin_users(&format!("?search={prefix}&limit=2"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let first_page = response
+        .json::<ListUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to ListUsersResponse");
+    let first_emails: Vec<_> = first_page.users.iter().map(|u| &u.email).collect();
+    assert_eq!(first_emails, [&emails[0], &emails[1]]);
+    assert_eq!(first_page.next_after.as_ref(), Some(&emails[1]));
+
+    let next_after = first_page.next_after.unwrap();
+    let second_page = app
+        .get_admin_users(&format!("?search={prefix}&limit=2&after={next_after}"))
+        .await
+        .json::<ListUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to ListUsersResponse");
+    let second_emails: Vec<_> = second_page.users.iter().map(|u| &u.email).collect();
+    assert_eq!(second_emails, [&emails[2]]);
+    assert_eq!(second_page.next_after, None);
+
+    let listed = admin_audit_rows(&app).await;
+    assert_eq!(listed.len(), 2);
+    assert!(listed
+        .iter()
+        .all(|row| *row == audit_row("admin_users_listed", &admin, None, "success")));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_404_for_unknown_user() {
+    let mut app = TestApp::new().await;
+
+    let admin = login_as_admin(&app).await;
+    let unknown = get_random_email();
+
+    let response = app.get_admin_user(&unknown).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(
+        app.post_admin_user_action(&unknown, "disable")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+    assert_eq!(
+        app.delete_admin_user("not-an-email")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_viewed", &admin, Some(&unknown), "failure"),
+            audit_row("admin_user_disabled", &admin, Some(&unknown), "failure"),
+            audit_row(
+                "admin_user_deleted",
+                &admin,
+                Some("not-an-email"),
+                "failure"
+            ),
+        ]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_disable_and_enable_user() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = login(&app, &email).await;
+    let admin = login_as_admin(&app).await;
+
+    let response = app.post_admin_user_action(&email, "disable").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user = response
+        .json::<AdminUserView>()
+        .await
+        .expect("Could not deserialize response body to AdminUserView");
+    assert!(user.disabled);
+    assert!(user.tokens_valid_after.is_some());
+
+    // Disabling ends the user's sessions and blocks new ones
+    assert_eq!(verify_token_status(&app, &token).await, 401);
+    let response = app
+        .login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    login(&app, &admin).await;
+    let response = app.post_admin_user_action(&email, "enable").await;
+    assert_eq!(response.status().as_u16(), 200);
+    login(&app, &email).await;
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_disabled", &admin, Some(&email), "success"),
+            audit_row("admin_user_enabled", &admin, Some(&email), "success"),
+        ]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_require_2fa_from_next_login() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let admin = login_as_admin(&app).await;
+
+    let response = app.post_admin_user_action(&email, "require-2fa").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user = response
+        .json::<AdminUserView>()
+        .await
+        .expect("Could not deserialize response body to AdminUserView");
+    assert!(user.requires_2fa);
+
+    let response = app
+        .login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [audit_row(
+            "admin_2fa_required",
+            &admin,
+            Some(&email),
+            "success"
+        )]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_reset_lockout() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let admin = login_as_admin(&app).await;
+
+    let max_attempts = app.app_state.settings.lockout.max_failed_attempts;
+    for _ in 0..max_attempts {
+        app.login(&serde_json::json!({ "email": email, "password": "wrongPass123!" }))
+            .await;
+    }
+    let user = app
+        .get_admin_user(&email)
+        .await
+        .json::<AdminUserView>()
+        .await
+        .expect("Could not deserialize response body to AdminUserView");
+    assert!(user.locked_until.is_some());
+
+    let response = app.post_admin_user_action(&email, "reset-lockout").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user = response
+        .json::<AdminUserView>()
+        .await
+        .expect("Could not deserialize response body to AdminUserView");
+    assert_eq!(user.locked_until, None);
+    assert_eq!(user.failed_login_attempts, 0);
+
+    login(&app, &email).await;
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_viewed", &admin, Some(&email), "success"),
+            audit_row("admin_lockout_reset", &admin, Some(&email), "success"),
+        ]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = login(&app, &email).await;
+    let admin = login_as_admin(&app).await;
+    assert_eq!(verify_token_status(&app, &token).await, 200);
+
+    let response = app.post_admin_user_action(&email, "revoke-sessions").await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(verify_token_status(&app, &token).await, 401);
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [audit_row(
+            "admin_sessions_revoked",
+            &admin,
+            Some(&email),
+            "success"
+        )]
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_delete_user() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = login(&app, &email).await;
+    let admin = login_as_admin(&app).await;
+
+    let response = app.delete_admin_user(&email).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert_eq!(app.get_admin_user(&email).await.status().as_u16(), 404);
+    assert_eq!(verify_token_status(&app, &token).await, 401);
+    let response = app
+        .login(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        admin_audit_rows(&app).await,
+        [
+            audit_row("admin_user_deleted", &admin, Some(&email), "success"),
+            audit_row("admin_user_viewed", &admin, Some(&email), "failure"),
+        ]
+    );
+
+    app.cleanup().await;
+}
@@ -0,0 +1,158 @@
+use crate::helpers::{get_random_email, TestApp};
+
+#[derive(Debug, PartialEq, sqlx::FromRow)]
+struct AuditRow {
+    kind: String,
+    actor: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    outcome: String,
+    reason: Option<String>,
+}
+
+async fn audit_rows(app: &TestApp) -> Vec<AuditRow> {
+    sqlx::query_as::<_, AuditRow>(
+        "SELECT kind, actor, ip, user_agent, outcome, reason FROM audit_events ORDER BY occurred_at",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to read audit events")
+}
+
+#[tokio::test]
+async fn should_record_signup_login_and_logout() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": false,
+    }))
+    .await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "audit-test/1.0")
+        .header("X-Real-IP", "203.0.113.7")
+        .json(&serde_json::json!({
+            "email": email,
+            "password": "wrongPassword123!",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.login(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+    }))
+    .await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let rows = audit_rows(&app).await;
+    let summary = rows
+        .iter()
+        .map(|row| (row.kind.as_str(), row.outcome.as_str()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        summary,
+        vec![
+            ("signup", "success"),
+            ("login", "failure"),
+            ("login", "success"),
+            ("token_banned", "success"),
+            ("logout", "success"),
+        ]
+    );
+    assert!(rows.iter().all(|row| row.actor.as_deref() == Some(&email)));
+
+    let failed_login = &rows[1];
+    assert_eq!(failed_login.ip.as_deref(), Some("203.0.113.7"));
+    assert_eq!(failed_login.user_agent.as_deref(), Some("audit-test/1.0"));
+    assert_eq!(
+        failed_login.reason.as_deref(),
+        Some("Incorrect credentials")
+    );
+
+    // Without a proxy header the peer address is recorded
+    assert_eq!(rows[0].ip.as_deref(), Some("127.0.0.1"));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_record_2fa_code_sent_and_failed_verification() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let response = app
+        .verify_2fa(&serde_json::json!({
+            "loginAttemptId": uuid::Uuid::new_v4().to_string(),
+            "2FACode": "123456",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let summary = audit_rows(&app)
+        .await
+        .into_iter()
+        .map(|row| (row.kind, row.outcome))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        summary,
+        [
+            ("signup", "success"),
+            ("2fa_code_sent", "success"),
+            ("login", "success"),
+            ("2fa_verified", "failure"),
+        ]
+        .map(|(kind, outcome)| (kind.to_owned(), outcome.to_owned()))
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn audit_events_are_append_only() {
+    let mut app = TestApp::new().await;
+
+    app.signup(&serde_json::json!({
+        "email": get_random_email(),
+        "password": "validPass123!",
+        "requires2FA": false,
+    }))
+    .await;
+
+    let result = sqlx::query("DELETE FROM audit_events")
+        .execute(&app.db_pool)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(audit_rows(&app).await.len(), 1);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,67 @@
+use reqwest::header::{ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_ORIGIN};
+
+use crate::helpers::{test_settings, TestApp};
+
+async fn preflight(app: &TestApp, origin: &str) -> reqwest::Response {
+    app.http_client
+        .request(reqwest::Method::OPTIONS, format!("{}/login", &app.address))
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", "POST")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn should_allow_configured_and_wildcard_origins() {
+    let mut settings = test_settings();
+    settings.application.allowed_origins = vec![
+        "http://localhost:8000".to_owned(),
+        "https://*.ddrcode.me".to_owned(),
+    ];
+    let mut app = TestApp::with_settings(settings).await;
+
+    for origin in ["http://localhost:8000", "https://lgr.ddrcode.me"] {
+        let response = preflight(&app, origin).await;
+
+        assert_eq!(
+            response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            origin,
+            "Failed for origin: {origin}"
+        );
+        assert_eq!(
+            response
+                .headers()
+                .get(ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+    }
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_not_allow_unknown_origins() {
+    let mut settings = test_settings();
+    settings.application.allowed_origins = vec!["https://*.ddrcode.me".to_owned()];
+    let mut app = TestApp::with_settings(settings).await;
+
+    for origin in [
+        "https://ddrcode.me",
+        "https://evil.com",
+        "http://lgr.ddrcode.me",
+    ] {
+        let response = preflight(&app, origin).await;
+
+        assert!(
+            response
+                .headers()
+                .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_none(),
+            "Failed for origin: {origin}"
+        );
+    }
+
+    app.cleanup().await;
+}
@@ -0,0 +1,75 @@
+use crate::helpers::{get_random_email, TestApp};
+
+#[cfg(feature = "dev-mailbox")]
+#[tokio::test]
+async fn should_list_captured_emails_for_recipient() {
+    use auth_service::routes::TwoFactorAuthResponse;
+
+    use crate::helpers::two_fa_code_from_email;
+
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+    let login_attempt_id = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    assert_eq!(app.deliver_emails_to_mailbox().await, 1);
+
+    let response = app.get_dev_mailbox(&email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let emails = response
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .expect("Failed to deserialize mailbox");
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0]["recipient"], email);
+    assert_eq!(emails[0]["subject"], "Your Live Bootcamp verification code");
+    assert!(emails[0]["sent_at"].is_string());
+
+    // The listed code completes the login
+    let code = two_fa_code_from_email(emails[0]["text_body"].as_str().unwrap());
+    let response = app
+        .verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Other recipients' emails are filtered out
+    let response = app.get_dev_mailbox(&get_random_email()).await;
+    let emails = response
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .expect("Failed to deserialize mailbox");
+    assert!(emails.is_empty());
+
+    app.cleanup().await;
+}
+
+#[cfg(not(feature = "dev-mailbox"))]
+#[tokio::test]
+async fn should_not_expose_dev_mailbox_without_feature() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_dev_mailbox(&get_random_email()).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.cleanup().await;
+}
@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use auth_service::{
+    domain::{DeliveryState, DeliveryStatus, Email, EmailContent, LoginAttemptId, OutboxEmail},
+    routes::{two_fa_email_key, TwoFactorAuthResponse},
+    services::EmailOutboxWorker,
+};
+use secrecy::ExposeSecret;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+use crate::helpers::{get_random_email, test_settings, AcceptBatch, TestApp};
+
+// Replaces the default Postmark stand-in with one answering `status` to every request
+async fn mount_email_response(app: &TestApp, status: u16) {
+    app.email_server.reset().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(status))
+        .mount(&app.email_server)
+        .await;
+}
+
+// Signs up a 2FA user and logs in, returning the idempotency key of the queued 2FA email
+async fn login_with_2fa(app: &TestApp) -> String {
+    let email = get_random_email();
+
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+    let login_attempt_id = LoginAttemptId::parse(body.login_attempt_id).unwrap();
+
+    two_fa_email_key(&login_attempt_id)
+}
+
+async fn delivery_status(app: &TestApp, idempotency_key: &str) -> DeliveryStatus {
+    app.app_state
+        .email_outbox
+        .status(idempotency_key)
+        .await
+        .expect("Failed to query delivery status")
+        .expect("Email was not queued")
+}
+
+async fn email_requests(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[tokio::test]
+async fn should_queue_2fa_email_when_email_provider_is_down() {
+    let mut app = TestApp::new().await;
+    mount_email_response(&app, 500).await;
+
+    let key = login_with_2fa(&app).await;
+
+    // Nothing is sent during the request itself
+    assert_eq!(email_requests(&app).await, 0);
+
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Pending);
+    assert_eq!(status.attempts, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_deliver_queued_email() {
+    let mut app = TestApp::new().await;
+
+    let key = login_with_2fa(&app).await;
+
+    assert_eq!(app.process_email_outbox().await, 1);
+    assert_eq!(email_requests(&app).await, 1);
+
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Sent);
+    assert_eq!(status.attempts, 1);
+    assert!(status.sent_at.is_some());
+
+    // Sent emails are not picked up again
+    assert_eq!(app.process_email_outbox().await, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_deliver_due_emails_in_one_batch() {
+    let mut app = TestApp::new().await;
+
+    let keys = [login_with_2fa(&app).await, login_with_2fa(&app).await];
+
+    assert_eq!(app.process_email_outbox().await, 2);
+    assert_eq!(email_requests(&app).await, 1);
+
+    for key in keys {
+        assert_eq!(delivery_status(&app, &key).await.state, DeliveryState::Sent);
+    }
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_retry_failed_email_until_delivered() {
+    let mut app = TestApp::new().await;
+    app.email_server.reset().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(AcceptBatch)
+        .mount(&app.email_server)
+        .await;
+
+    let key = login_with_2fa(&app).await;
+
+    assert_eq!(app.process_email_outbox().await, 1);
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Pending);
+    assert_eq!(status.attempts, 1);
+    assert!(status.last_error.is_some());
+
+    assert_eq!(app.process_email_outbox().await, 1);
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Sent);
+    assert_eq!(status.attempts, 2);
+    assert_eq!(status.last_error, None);
+
+    assert_eq!(email_requests(&app).await, 2);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_dead_letter_email_after_max_attempts() {
+    let mut app = TestApp::new().await;
+    mount_email_response(&app, 500).await;
+
+    let key = login_with_2fa(&app).await;
+    let max_attempts = app.app_state.settings.email_outbox.max_attempts;
+
+    for _ in 0..max_attempts {
+        assert_eq!(app.process_email_outbox().await, 1);
+    }
+
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Dead);
+    assert_eq!(status.attempts, max_attempts);
+    assert!(status.last_error.is_some());
+
+    // Dead emails are never retried
+    assert_eq!(app.process_email_outbox().await, 0);
+    assert_eq!(email_requests(&app).await, max_attempts as usize);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_dead_letter_email_to_inactive_recipient_without_retrying() {
+    let mut app = TestApp::new().await;
+    app.email_server.reset().await;
+    // The batch endpoint accepts the request and reports failures per message
+    Mock::given(any())
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "ErrorCode": 406,
+                "Message": "You tried to send to recipient(s) that have been marked as inactive.",
+            }])),
+        )
+        .mount(&app.email_server)
+        .await;
+
+    let key = login_with_2fa(&app).await;
+
+    assert_eq!(app.process_email_outbox().await, 1);
+
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Dead);
+    assert_eq!(status.attempts, 1);
+    assert!(status.last_error.unwrap().contains("Inactive recipient"));
+
+    assert_eq!(app.process_email_outbox().await, 0);
+    assert_eq!(email_requests(&app).await, 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_back_off_before_retrying() {
+    let mut settings = test_settings();
+    settings.email_outbox.base_delay_milliseconds = 60_000;
+    settings.email_outbox.max_delay_milliseconds = 60_000;
+    let mut app = TestApp::with_settings(settings).await;
+    mount_email_response(&app, 500).await;
+
+    let key = login_with_2fa(&app).await;
+
+    assert_eq!(app.process_email_outbox().await, 1);
+    let status = delivery_status(&app, &key).await;
+    assert_eq!(status.state, DeliveryState::Pending);
+    assert!(status.next_attempt_at > chrono::Utc::now() + chrono::Duration::seconds(50));
+
+    // The retry is not due yet
+    assert_eq!(app.process_email_outbox().await, 0);
+    assert_eq!(email_requests(&app).await, 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_send_email_once_per_idempotency_key() {
+    let mut app = TestApp::new().await;
+
+    let email = OutboxEmail {
+        idempotency_key: "welcome:1".to_owned(),
+        recipient: Email::parse(&get_random_email()).unwrap(),
+        content: EmailContent {
+            subject: "Welcome".to_owned(),
+            html_body: "<p>Welcome</p>".to_owned(),
+            text_body: "Welcome".to_owned(),
+        },
+        tag: None,
+    };
+
+    let outbox = &app.app_state.email_outbox;
+    assert!(outbox.enqueue(email.clone()).await.unwrap());
+    assert!(!outbox.enqueue(email.clone()).await.unwrap());
+
+    assert_eq!(app.process_email_outbox().await, 1);
+
+    // Enqueueing again after delivery is still a no-op
+    assert!(!outbox.enqueue(email).await.unwrap());
+    assert_eq!(app.process_email_outbox().await, 0);
+
+    assert_eq!(email_requests(&app).await, 1);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_none_for_unknown_idempotency_key() {
+    let mut app = TestApp::new().await;
+
+    let status = app
+        .app_state
+        .email_outbox
+        .status("2fa:unknown")
+        .await
+        .unwrap();
+    assert_eq!(status, None);
+
+    app.cleanup().await;
+}
+
+#[test]
+fn should_not_store_login_attempt_id_in_idempotency_key() {
+    let login_attempt_id = LoginAttemptId::default();
+    let key = two_fa_email_key(&login_attempt_id);
+
+    assert!(key.starts_with("2fa:"));
+    assert!(!key.contains(login_attempt_id.as_ref().expose_secret().as_str()));
+    assert_eq!(key, two_fa_email_key(&login_attempt_id));
+}
+
+#[tokio::test]
+async fn should_stop_worker_while_full_batches_keep_coming() {
+    let mut settings = test_settings();
+    // The failing email is due again straight away, so every batch comes back full
+    settings.email_outbox.batch_size = 1;
+    settings.email_outbox.max_attempts = u32::MAX;
+    let mut app = TestApp::with_settings(settings).await;
+    mount_email_response(&app, 500).await;
+
+    login_with_2fa(&app).await;
+
+    let worker = EmailOutboxWorker::new(
+        app.app_state.email_outbox.clone(),
+        app.app_state.email_client.clone(),
+        &app.app_state.settings.email_outbox,
+    );
+    let stopped = tokio::time::timeout(
+        Duration::from_secs(5),
+        worker.run_until(tokio::time::sleep(Duration::from_millis(200))),
+    )
+    .await;
+
+    assert!(stopped.is_ok(), "Worker did not stop while draining");
+    assert!(email_requests(&app).await > 1);
+
+    app.cleanup().await;
+}
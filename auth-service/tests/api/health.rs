@@ -0,0 +1,48 @@
+use auth_service::routes::{HealthResponse, HealthStatus};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn live_returns_200() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_live().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+
+    assert_eq!(body.status, HealthStatus::Up);
+    assert!(body.checks.is_empty());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn ready_returns_200_with_status_of_each_dependency() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_ready().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+
+    assert_eq!(body.status, HealthStatus::Up);
+    for dependency in ["postgres", "redis"] {
+        let check = body
+            .checks
+            .get(dependency)
+            .unwrap_or_else(|| panic!("Missing health check for {dependency}"));
+        assert_eq!(check.status, HealthStatus::Up);
+        assert!(check.error.is_none());
+    }
+
+    app.cleanup().await;
+}
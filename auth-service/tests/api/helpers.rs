@@ -1,75 +1,193 @@
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 use auth_service::{
-    app_state::AppState,
-    domain::Email,
-    get_postgres_pool, get_redis_client,
+    app_state::{
+        AppState, BannedTokenStoreType, EmailOutboxType, HealthCheckType,
+        TokensValidAfterCacheType, TwoFACodeStoreType, UserStoreType,
+    },
+    domain::{Email, LoginAttemptId, TwoFAAttempt},
+    get_postgres_pool, get_redis_connection, get_sqlite_pool,
+    routes::TwoFactorAuthResponse,
     services::{
-        data_stores::{PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
-        PostmarkEmailClient,
+        audit_sinks::PostgresAuditSink,
+        data_stores::{
+            HashMapTwoFACodeStore, HashSetBannedTokenStore, MeteredBannedTokenStore,
+            MeteredTwoFACodeStore, MeteredUserStore, PostgresBannedTokenStore, PostgresEmailOutbox,
+            PostgresTwoFACodeStore, PostgresUserStore, RedisBannedTokenStore,
+            RedisTokensValidAfterCache, RedisTwoFACodeStore, SqliteBannedTokenStore,
+            SqliteEmailOutbox, SqliteTwoFACodeStore, SqliteUserStore,
+        },
+        CapturingEmailClient, EmailOutboxWorker, HttpSmsClient, MeteredEmailClient,
+        PostgresHealthCheck, PostmarkEmailClient, RedisHealthCheck, SqliteHealthCheck,
+        TemplateOverrides,
+    },
+    settings::{
+        ApplicationSettings, AuditSettings, AuditSinkKind, AuthSettings, CacheBackend,
+        CookieSettings, DatabaseSettings, EmailClientSettings, EmailOutboxSettings, EmailProvider,
+        EmailRetrySettings, EmailTemplateSettings, HealthSettings, LockoutSettings, LogFormat,
+        RedisSettings, SameSiteSetting, Settings, SmsClientSettings, StoreBackend, StoreSettings,
+        TracingSettings, TwoFASettings,
     },
-    utils::{test, DB_URL, REDIS_HOST_NAME},
+    utils::{env, spawn_pool_metrics_task, JWT_COOKIE_NAME},
     Application,
 };
+use redis::aio::ConnectionManager;
 use reqwest::{cookie::Jar, Client};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{
     postgres::{PgConnectOptions, PgPoolOptions},
-    Connection, Executor, PgConnection, PgPool,
+    Connection, Executor, PgConnection, PgPool, Postgres, SqlitePool, Transaction,
+};
+use tempfile::TempDir;
+use tokio::{
+    sync::{Notify, RwLock},
+    task::JoinHandle,
 };
-use tokio::sync::RwLock;
 use uuid::Uuid;
 use wiremock::{
     matchers::{header_exists, method, path},
-    Mock, MockServer, ResponseTemplate,
+    Mock, MockServer, Request, Respond, ResponseTemplate,
 };
 
+const POOL_METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct TestApp {
     pub address: String,
+    pub metrics_address: String,
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
     pub email_server: MockServer,
+    pub sms_server: MockServer,
     pub app_state: AppState,
+    // Receives emails delivered with `deliver_emails_to_mailbox`
+    pub mailbox: CapturingEmailClient,
+    pub db_pool: PgPool,
+    shutdown: Arc<Notify>,
+    server: Option<JoinHandle<Result<(), std::io::Error>>>,
+    pub db_url: Secret<String>,
     pub db_name: Secret<String>,
     pub cleanup_called: bool,
+    // Holds the SQLite database file of stores using the "sqlite" backend
+    _sqlite_dir: Option<TempDir>,
+}
+
+// Connections to the backends used by the configured stores. Each test app gets its own
+// PostgreSQL database, and its own SQLite file when a store uses SQLite.
+struct Backends {
+    postgres: PgPool,
+    sqlite: Option<SqlitePool>,
+    redis: Option<ConnectionManager>,
+}
+
+impl Backends {
+    fn sqlite(&self) -> SqlitePool {
+        self.sqlite.clone().expect("SQLite is not configured")
+    }
+
+    fn redis(&self) -> ConnectionManager {
+        self.redis.clone().expect("Redis is not connected")
+    }
 }
 
 impl TestApp {
     pub async fn new() -> Self {
-        let (pg_pool, db_name) = configure_postgresql().await;
-        let redis_conn = Arc::new(RwLock::new(configure_redis()));
-
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-        let banned_token_store =
-            Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_conn.clone())));
-        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_conn.clone())));
+        Self::with_settings(test_settings()).await
+    }
 
+    // Builds the app from an in-code settings value. The email client base URL is
+    // always pointed at the mock Postmark server started for the test.
+    pub async fn with_settings(mut settings: Settings) -> Self {
         let email_server = MockServer::start().await;
-        let base_url = email_server.uri();
-        let email_client = Arc::new(RwLock::new(configure_postmark_email_client(base_url)));
+        settings.email_client.base_url = email_server.uri();
+        let sms_server = MockServer::start().await;
+        if let Some(sms_client) = &mut settings.sms_client {
+            sms_client.base_url = sms_server.uri();
+        }
+
+        let (pg_pool, db_name) = configure_postgresql(&settings.database).await;
+        let (sqlite_pool, sqlite_dir) = match settings.stores.uses_sqlite() {
+            true => {
+                let (pool, dir) = configure_sqlite().await;
+                (Some(pool), Some(dir))
+            }
+            false => (None, None),
+        };
+        let redis_conn = match settings.stores.uses_redis() {
+            true => Some(configure_redis(&settings.redis).await),
+            false => None,
+        };
+        let backends = Backends {
+            postgres: pg_pool.clone(),
+            sqlite: sqlite_pool,
+            redis: redis_conn,
+        };
+
+        let mut health_checks: Vec<HealthCheckType> =
+            vec![Arc::new(PostgresHealthCheck::new(pg_pool.clone()))];
+        if let Some(sqlite_pool) = &backends.sqlite {
+            health_checks.push(Arc::new(SqliteHealthCheck::new(sqlite_pool.clone())));
+        }
+        if let Some(redis_conn) = &backends.redis {
+            health_checks.push(Arc::new(RedisHealthCheck::new(redis_conn.clone())));
+        }
+
+        spawn_pool_metrics_task(pg_pool.clone(), POOL_METRICS_INTERVAL);
+
+        let user_store = configure_user_store(&settings, &backends);
+        let banned_token_store = configure_banned_token_store(&settings, &backends);
+        let two_fa_code_store = configure_two_fa_code_store(&settings, &backends);
+        let email_outbox = configure_email_outbox(&settings, &backends);
+        let tokens_valid_after_cache = configure_tokens_valid_after_cache(&settings, &backends);
+
+        let email_client = Arc::new(RwLock::new(MeteredEmailClient::new(
+            configure_postmark_email_client(&settings.email_client),
+            "postmark",
+        )));
 
         Mock::given(method("POST"))
-            .and(path("/email"))
+            .and(path("/email/batch"))
             .and(header_exists("X-Postmark-Server-Token"))
-            .respond_with(ResponseTemplate::new(200))
+            .respond_with(AcceptBatch)
             .mount(&email_server)
             .await;
 
+        let mailbox = CapturingEmailClient::new();
+
+        let settings = Arc::new(settings);
         let app_state = AppState::new(
+            settings.clone(),
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
-        );
-
-        let app = Application::build(app_state.clone(), test::APP_ADDRESS)
+            email_outbox,
+            health_checks,
+            Arc::new(PostgresAuditSink::new(pg_pool.clone())),
+        )
+        .with_dev_mailbox(mailbox.clone());
+        let app_state = match tokens_valid_after_cache {
+            Some(cache) => app_state.with_tokens_valid_after_cache(cache),
+            None => app_state,
+        };
+        let app_state = match &settings.sms_client {
+            Some(sms_settings) => {
+                app_state.with_sms_client(Arc::new(configure_sms_client(sms_settings)))
+            }
+            None => app_state,
+        };
+
+        let app = Application::build(app_state.clone(), &settings.application)
             .await
             .expect("Failed to build application");
 
         let address = format!("http://{}", app.address.clone());
+        let metrics_address = format!("http://{}", app.metrics_address.clone());
 
-        #[allow(clippy::let_underscore_future)]
-        let _ = tokio::spawn(app.run());
+        let shutdown = Arc::new(Notify::new());
+        let server = tokio::spawn(app.run_until({
+            let shutdown = shutdown.clone();
+            async move { shutdown.notified().await }
+        }));
 
         let cookie_jar = Arc::new(Jar::default());
         let http_client = reqwest::Client::builder()
@@ -79,12 +197,20 @@ impl TestApp {
 
         Self {
             address,
+            metrics_address,
             cookie_jar,
             http_client,
             email_server,
+            sms_server,
             app_state,
+            mailbox,
+            db_pool: pg_pool,
+            shutdown,
+            server: Some(server),
+            db_url: settings.database.url.clone(),
             db_name: Secret::new(db_name),
             cleanup_called: false,
+            _sqlite_dir: sqlite_dir,
         }
     }
 
@@ -96,6 +222,30 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn get_health_live(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/live", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_health_ready(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/metrics", &self.metrics_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn signup<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -128,6 +278,67 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    // Without the cookie jar, the way API clients call it
+    pub async fn logout_with_bearer(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/logout", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout-all", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // `query` is appended as is, e.g. "?search=example&limit=10"
+    pub async fn get_admin_users(&self, query: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users{query}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_user(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users/{email}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // `action` is one of the POST routes under a user, e.g. "disable"
+    pub async fn post_admin_user_action(&self, email: &str, action: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/admin/users/{email}/{action}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn delete_admin_user(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/users/{email}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Roles are assigned through the user store, there is no route for it
+    pub async fn assign_role(&self, email: &str, role: &str) {
+        self.app_state
+            .user_store
+            .assign_role(&Email::parse(email).unwrap(), role)
+            .await
+            .expect("Failed to assign role");
+    }
+
     pub async fn verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -152,8 +363,94 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    // Delivers one batch of due emails from the outbox. Tests drive the worker explicitly
+    // instead of running it in the background, so that deliveries happen deterministically.
+    pub async fn process_email_outbox(&self) -> usize {
+        EmailOutboxWorker::new(
+            self.app_state.email_outbox.clone(),
+            self.app_state.email_client.clone(),
+            &self.app_state.settings.email_outbox,
+        )
+        .process_due()
+        .await
+        .expect("Failed to process email outbox")
+    }
+
+    // Like `process_email_outbox`, but delivers to the in-memory `mailbox` instead of the
+    // mock Postmark server, so tests can read what the user received
+    pub async fn deliver_emails_to_mailbox(&self) -> usize {
+        EmailOutboxWorker::new(
+            self.app_state.email_outbox.clone(),
+            Arc::new(RwLock::new(self.mailbox.clone())),
+            &self.app_state.settings.email_outbox,
+        )
+        .process_due()
+        .await
+        .expect("Failed to process email outbox")
+    }
+
+    // Looks up the pending 2FA login a 206 login response refers to
+    pub async fn get_2fa_attempt(
+        &self,
+        login_response: reqwest::Response,
+    ) -> (LoginAttemptId, TwoFAAttempt) {
+        let login_attempt_id = login_response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .expect("Could not deserialize response body to TwoFactorAuthResponse")
+            .login_attempt_id;
+        let login_attempt_id = LoginAttemptId::parse(login_attempt_id).unwrap();
+
+        let attempt = self
+            .app_state
+            .two_fa_code_store
+            .get_code(&login_attempt_id)
+            .await
+            .expect("No pending 2FA attempt");
+
+        (login_attempt_id, attempt)
+    }
+
+    pub async fn get_dev_mailbox(&self, to: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/dev/mailbox", &self.address))
+            .query(&[("to", to)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Locks the users table until the returned transaction is dropped, so logins stall
+    // while looking up the user
+    pub async fn lock_users_table(&self) -> Transaction<'static, Postgres> {
+        let mut transaction = self
+            .db_pool
+            .begin()
+            .await
+            .expect("Failed to begin transaction");
+        sqlx::query("LOCK TABLE users IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .await
+            .expect("Failed to lock users table");
+        transaction
+    }
+
+    // Starts a graceful shutdown, as SIGTERM would in production
+    pub fn trigger_shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    // Waits for `Application::run_until` to return after `trigger_shutdown`
+    pub async fn wait_for_shutdown(&mut self) -> Result<(), std::io::Error> {
+        self.server
+            .take()
+            .expect("Server was already shut down")
+            .await
+            .expect("Server task panicked")
+    }
+
     pub async fn cleanup(&mut self) {
-        delete_database(&self.db_name).await;
+        delete_database(&self.db_url, &self.db_name).await;
         self.cleanup_called = true;
     }
 }
@@ -166,12 +463,144 @@ impl Drop for TestApp {
     }
 }
 
+// Answers a Postmark batch request like Postmark does when every message is accepted
+pub struct AcceptBatch;
+
+impl Respond for AcceptBatch {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let messages: Vec<serde_json::Value> =
+            serde_json::from_slice(&request.body).expect("Batch request is not a JSON array");
+        let results = vec![serde_json::json!({ "ErrorCode": 0, "Message": "OK" }); messages.len()];
+
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+// Pulls the 2FA code out of the plain-text body of a 2FA email
+pub fn two_fa_code_from_email(text_body: &str) -> String {
+    text_body
+        .split_once("finish signing in: ")
+        .and_then(|(_, rest)| rest.get(..6))
+        .expect("No 2FA code in email")
+        .to_owned()
+}
+
 pub fn get_random_email() -> String {
     format!("{}@example.com", uuid::Uuid::new_v4())
 }
 
-async fn configure_postgresql() -> (PgPool, String) {
-    let postgresql_conn_url = DB_URL.to_owned();
+pub fn test_settings() -> Settings {
+    dotenvy::dotenv().ok();
+
+    let db_url =
+        std::env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL not set in environment");
+    let redis_host_name =
+        std::env::var(env::REDIS_HOST_NAME_ENV_VAR).unwrap_or("127.0.0.1".to_owned());
+
+    Settings {
+        application: ApplicationSettings {
+            host: "127.0.0.1".to_owned(),
+            port: 0,
+            allowed_origins: vec!["http://localhost:8000".to_owned()],
+            assets_dir: "assets".to_owned(),
+            drain_timeout_seconds: 5,
+            metrics_host: "127.0.0.1".to_owned(),
+            metrics_port: 0,
+        },
+        auth: AuthSettings {
+            jwt_secret: Secret::new("test_jwt_secret".to_owned()),
+            token_ttl_seconds: 600,
+            cookie: CookieSettings {
+                name: JWT_COOKIE_NAME.to_owned(),
+                path: "/".to_owned(),
+                domain: None,
+                secure: false,
+                same_site: SameSiteSetting::Lax,
+                max_age_seconds: None,
+                host_prefix: false,
+            },
+        },
+        database: DatabaseSettings {
+            url: Secret::new(db_url),
+        },
+        redis: RedisSettings {
+            host_name: redis_host_name,
+        },
+        stores: StoreSettings {
+            users: StoreBackend::Postgres,
+            banned_tokens: StoreBackend::Redis,
+            two_fa_codes: StoreBackend::Redis,
+            email_outbox: StoreBackend::Postgres,
+            tokens_valid_after_cache: CacheBackend::Redis,
+            sweep_interval_seconds: 60,
+        },
+        // Stores using SQLite get a temporary database file in `TestApp::with_settings`
+        sqlite: None,
+        email_client: EmailClientSettings {
+            provider: EmailProvider::Postmark,
+            // Replaced with the mock server URI in `TestApp::with_settings`
+            base_url: "http://127.0.0.1".to_owned(),
+            sender: "test@email.com".to_owned(),
+            authorization_token: Secret::new("auth_token".to_owned()),
+            timeout_milliseconds: 200,
+            // The outbox worker retries failed deliveries, keep mock expectations exact
+            retry: EmailRetrySettings {
+                max_attempts: 1,
+                base_delay_milliseconds: 0,
+                max_delay_milliseconds: 0,
+            },
+            smtp: None,
+        },
+        two_fa: TwoFASettings {
+            code_ttl_seconds: 600,
+            max_failed_codes: 5,
+        },
+        lockout: LockoutSettings {
+            max_failed_attempts: 5,
+            duration_seconds: 900,
+        },
+        health: HealthSettings {
+            check_timeout_milliseconds: 1000,
+        },
+        tracing: TracingSettings {
+            service_name: "auth-service".to_owned(),
+            otlp_endpoint: None,
+            log_format: LogFormat::Compact,
+        },
+        audit: AuditSettings {
+            sink: AuditSinkKind::Postgres,
+            file_path: None,
+            trust_proxy_headers: true,
+        },
+        email_templates: EmailTemplateSettings {
+            product_name: "Live Bootcamp".to_owned(),
+            logo_url: None,
+            support_email: None,
+            accent_color: "#2563eb".to_owned(),
+            template_dir: None,
+            overrides: TemplateOverrides::default(),
+        },
+        email_outbox: EmailOutboxSettings {
+            poll_interval_milliseconds: 100,
+            batch_size: 10,
+            max_attempts: 3,
+            // Failed emails are due again straight away, so retries can be driven by the tests
+            base_delay_milliseconds: 0,
+            max_delay_milliseconds: 0,
+            lease_seconds: 60,
+        },
+        sms_client: Some(SmsClientSettings {
+            // Replaced with the mock server URI in `TestApp::with_settings`
+            base_url: "http://127.0.0.1".to_owned(),
+            sender: "LiveBootcamp".to_owned(),
+            authorization_token: Secret::new("sms_token".to_owned()),
+            timeout_milliseconds: 200,
+        }),
+    }
+}
+
+async fn configure_postgresql(settings: &DatabaseSettings) -> (PgPool, String) {
+    let postgresql_conn_url = settings.url.to_owned();
 
     // Creating new db for each test case, need to ensure each db has unique name
     let db_name = Uuid::new_v4().to_string();
@@ -216,10 +645,8 @@ async fn configure_database(db_conn_string: &Secret<String>, db_name: &str) {
         .expect("Failed to run migrations");
 }
 
-async fn delete_database(db_name: &Secret<String>) {
-    let postgresql_conn_url: Secret<String> = DB_URL.to_owned();
-
-    let connection_options = PgConnectOptions::from_str(&postgresql_conn_url.expose_secret())
+async fn delete_database(postgresql_conn_url: &Secret<String>, db_name: &Secret<String>) {
+    let connection_options = PgConnectOptions::from_str(postgresql_conn_url.expose_secret())
         .expect("Failed to parse PostgreSQL connection string");
 
     let mut connection = PgConnection::connect_with(&connection_options)
@@ -248,22 +675,134 @@ async fn delete_database(db_name: &Secret<String>) {
         .expect("Failed to drop database");
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+fn configure_user_store(settings: &Settings, backends: &Backends) -> UserStoreType {
+    match settings.stores.users {
+        StoreBackend::Postgres => Arc::new(MeteredUserStore::new(
+            PostgresUserStore::new(backends.postgres.clone()),
+            "postgres_user",
+        )),
+        StoreBackend::Sqlite => Arc::new(MeteredUserStore::new(
+            SqliteUserStore::new(backends.sqlite()),
+            "sqlite_user",
+        )),
+        backend => panic!("Unsupported user store backend: {backend:?}"),
+    }
+}
+
+fn configure_banned_token_store(settings: &Settings, backends: &Backends) -> BannedTokenStoreType {
+    match settings.stores.banned_tokens {
+        StoreBackend::Redis => Arc::new(MeteredBannedTokenStore::new(
+            RedisBannedTokenStore::new(backends.redis()),
+            "redis_banned_token",
+        )),
+        StoreBackend::Postgres => Arc::new(MeteredBannedTokenStore::new(
+            PostgresBannedTokenStore::new(backends.postgres.clone()),
+            "postgres_banned_token",
+        )),
+        StoreBackend::Sqlite => Arc::new(MeteredBannedTokenStore::new(
+            SqliteBannedTokenStore::new(backends.sqlite()),
+            "sqlite_banned_token",
+        )),
+        StoreBackend::Memory => Arc::new(MeteredBannedTokenStore::new(
+            HashSetBannedTokenStore::default(),
+            "memory_banned_token",
+        )),
+    }
+}
+
+fn configure_two_fa_code_store(settings: &Settings, backends: &Backends) -> TwoFACodeStoreType {
+    let ttl = Duration::from_secs(settings.two_fa.code_ttl_seconds);
+
+    match settings.stores.two_fa_codes {
+        StoreBackend::Redis => Arc::new(MeteredTwoFACodeStore::new(
+            RedisTwoFACodeStore::new(backends.redis(), ttl.as_secs()),
+            "redis_two_fa_code",
+        )),
+        StoreBackend::Postgres => Arc::new(MeteredTwoFACodeStore::new(
+            PostgresTwoFACodeStore::new(backends.postgres.clone(), ttl),
+            "postgres_two_fa_code",
+        )),
+        StoreBackend::Sqlite => Arc::new(MeteredTwoFACodeStore::new(
+            SqliteTwoFACodeStore::new(backends.sqlite(), ttl),
+            "sqlite_two_fa_code",
+        )),
+        StoreBackend::Memory => Arc::new(MeteredTwoFACodeStore::new(
+            HashMapTwoFACodeStore::new(ttl),
+            "memory_two_fa_code",
+        )),
+    }
+}
+
+fn configure_email_outbox(settings: &Settings, backends: &Backends) -> EmailOutboxType {
+    match settings.stores.email_outbox {
+        StoreBackend::Postgres => Arc::new(PostgresEmailOutbox::new(backends.postgres.clone())),
+        StoreBackend::Sqlite => Arc::new(SqliteEmailOutbox::new(backends.sqlite())),
+        backend => panic!("Unsupported email outbox backend: {backend:?}"),
+    }
+}
+
+fn configure_tokens_valid_after_cache(
+    settings: &Settings,
+    backends: &Backends,
+) -> Option<TokensValidAfterCacheType> {
+    match settings.stores.tokens_valid_after_cache {
+        CacheBackend::Redis => Some(Arc::new(RedisTokensValidAfterCache::new(
+            backends.redis(),
+            settings.auth.token_ttl_seconds as u64,
+        ))),
+        CacheBackend::None => None,
+    }
+}
+
+// Creates a migrated SQLite database in a temporary directory, removed when it is dropped
+pub async fn configure_sqlite() -> (SqlitePool, TempDir) {
+    let dir = TempDir::new().expect("Failed to create temporary directory");
+    let path = dir.path().join("auth-service.db");
+
+    let pool = get_sqlite_pool(path.to_str().expect("Temporary path is not UTF-8"))
+        .await
+        .expect("Failed to open SQLite database");
+    sqlx::migrate!("./migrations/sqlite")
+        .run(&pool)
+        .await
+        .expect("Failed to run SQLite migrations");
+
+    (pool, dir)
+}
+
+async fn configure_redis(settings: &RedisSettings) -> ConnectionManager {
+    get_redis_connection(settings.host_name.to_owned())
+        .await
         .expect("Failed to get Redis connection")
 }
 
-fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
-    let postmark_auth_token = Secret::new("auth_token".to_owned());
+fn configure_sms_client(settings: &SmsClientSettings) -> HttpSmsClient {
+    let http_client = Client::builder()
+        .timeout(settings.timeout())
+        .build()
+        .expect("Failed to build HTTP client");
 
-    let sender = Email::parse(test::email_client::SENDER).unwrap();
+    HttpSmsClient::new(
+        &settings.base_url,
+        settings.sender.to_owned(),
+        settings.authorization_token.to_owned(),
+        http_client,
+    )
+    .expect("Failed to build SMS client")
+}
 
+fn configure_postmark_email_client(settings: &EmailClientSettings) -> PostmarkEmailClient {
     let http_client = Client::builder()
-        .timeout(test::email_client::TIMEOUT)
+        .timeout(settings.timeout())
         .build()
         .expect("Failed to build HTTP client");
 
-    PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
+    PostmarkEmailClient::new(
+        &settings.base_url,
+        settings.sender().unwrap(),
+        settings.authorization_token.to_owned(),
+        http_client,
+    )
+    .expect("Failed to build Postmark client")
+    .with_retry_policy(settings.retry.retry_policy())
 }
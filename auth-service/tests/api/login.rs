@@ -1,8 +1,13 @@
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, test_settings, TestApp};
 use auth_service::{
-    domain::Email, routes::TwoFactorAuthResponse, utils::JWT_COOKIE_NAME, ErrorResponse,
+    domain::{Email, LoginAttemptId},
+    routes::TwoFactorAuthResponse,
+    settings::SameSiteSetting,
+    utils::JWT_COOKIE_NAME,
+    ErrorResponse,
 };
 use secrecy::ExposeSecret;
+use std::time::{Duration, Instant};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
@@ -94,6 +99,142 @@ async fn should_return_401_if_incorrect_credentials() {
     app.cleanup().await;
 }
 
+#[tokio::test]
+async fn should_lock_account_after_repeated_failures() {
+    let mut settings = test_settings();
+    settings.lockout.max_failed_attempts = 3;
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": false,
+    }))
+    .await;
+
+    let wrong = serde_json::json!({ "email": email, "password": "wrongPassword123!" });
+    let right = serde_json::json!({ "email": email, "password": "validPass123!" });
+
+    // A successful login starts the count over
+    for _ in 0..2 {
+        assert_eq!(app.login(&wrong).await.status().as_u16(), 401);
+    }
+    assert_eq!(app.login(&right).await.status().as_u16(), 200);
+    for _ in 0..2 {
+        assert_eq!(app.login(&wrong).await.status().as_u16(), 401);
+    }
+
+    assert_eq!(app.login(&wrong).await.status().as_u16(), 401);
+
+    // Locked, even with the right password, and answered exactly like an unknown email
+    let unknown = app
+        .login(&serde_json::json!({ "email": get_random_email(), "password": "validPass123!" }))
+        .await;
+    let unknown = (unknown.status(), unknown.text().await.unwrap());
+    let response = app.login(&right).await;
+    assert_eq!((response.status(), response.text().await.unwrap()), unknown);
+    assert_eq!(unknown.0.as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_403_if_account_disabled() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": false,
+    }))
+    .await;
+    app.app_state
+        .user_store
+        .set_disabled(&Email::parse(&email).unwrap(), true)
+        .await
+        .unwrap();
+
+    // The wrong password doesn't reveal that the account is disabled
+    let response = app
+        .login(&serde_json::json!({ "email": email, "password": "wrongPassword123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .login(&serde_json::json!({ "email": email, "password": "validPass123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Account disabled".to_string(),
+    );
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_not_reveal_whether_an_email_exists_through_timing() {
+    // Every sample is a failed login, which would otherwise lock the account
+    let mut settings = test_settings();
+    settings.lockout.max_failed_attempts = 100;
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": false,
+    }))
+    .await;
+
+    async fn time_failed_login(app: &TestApp, email: &str) -> Duration {
+        let started = Instant::now();
+        let response = app
+            .login(&serde_json::json!({
+                "email": email,
+                "password": "wrongPassword123!",
+            }))
+            .await;
+        let elapsed = started.elapsed();
+        assert_eq!(response.status().as_u16(), 401);
+        elapsed
+    }
+
+    fn median(mut samples: Vec<Duration>) -> Duration {
+        samples.sort();
+        samples[samples.len() / 2]
+    }
+
+    // Warm up connections and the password hashing thread pool
+    time_failed_login(&app, &email).await;
+    time_failed_login(&app, &get_random_email()).await;
+
+    // Interleave the samples so load from other tests affects both sides alike
+    const SAMPLES: usize = 11;
+    let mut existing = Vec::with_capacity(SAMPLES);
+    let mut unknown = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        existing.push(time_failed_login(&app, &email).await);
+        unknown.push(time_failed_login(&app, &get_random_email()).await);
+    }
+
+    // Skipping the password hash for unknown emails makes them an order of magnitude faster
+    let (existing, unknown) = (median(existing), median(unknown));
+    let ratio = unknown.as_secs_f64() / existing.as_secs_f64();
+    assert!(
+        (0.5..2.0).contains(&ratio),
+        "Median failed login took {existing:?} for an existing email but {unknown:?} for an unknown one"
+    );
+
+    app.cleanup().await;
+}
+
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     let mut app = TestApp::new().await;
@@ -132,6 +273,55 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     app.cleanup().await;
 }
 
+#[tokio::test]
+async fn should_set_auth_cookie_with_configured_attributes() {
+    let mut settings = test_settings();
+    settings.auth.cookie.secure = true;
+    settings.auth.cookie.same_site = SameSiteSetting::Strict;
+    settings.auth.cookie.max_age_seconds = Some(600);
+    settings.auth.cookie.host_prefix = true;
+
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|c| c.name() == format!("__Host-{JWT_COOKIE_NAME}"))
+        .expect("No auth cookie found");
+
+    assert!(auth_cookie.secure());
+    assert!(auth_cookie.http_only());
+    assert!(auth_cookie.same_site_strict());
+    assert_eq!(auth_cookie.path(), Some("/"));
+    assert_eq!(auth_cookie.domain(), None);
+    assert_eq!(
+        auth_cookie.max_age(),
+        Some(std::time::Duration::from_secs(600))
+    );
+
+    app.cleanup().await;
+}
+
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let mut app = TestApp::new().await;
@@ -168,14 +358,13 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         "Unexpected message in 2FA response"
     );
 
-    assert!(app
+    let attempt = app
         .app_state
         .two_fa_code_store
-        .read()
+        .get_code(&LoginAttemptId::parse(json_body.login_attempt_id).unwrap())
         .await
-        .get_code(&Email::parse(&email).unwrap())
-        .await
-        .is_ok());
+        .expect("No pending 2FA attempt");
+    assert_eq!(attempt.email, Email::parse(&email).unwrap());
 
     app.cleanup().await;
 }
@@ -251,15 +440,7 @@ async fn should_return_401_if_old_code() {
     assert_eq!(response.status().as_u16(), 206);
 
     // Get the first 2FA code
-    let email_obj = Email::parse(&email).unwrap();
-    let first_code = app
-        .app_state
-        .two_fa_code_store
-        .read()
-        .await
-        .get_code(&email_obj)
-        .await
-        .expect("Failed to get 2FA code");
+    let (_, first_attempt) = app.get_2fa_attempt(response).await;
 
     // Wait a moment to ensure time difference
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -274,12 +455,17 @@ async fn should_return_401_if_old_code() {
     // Login again to generate a new 2FA code
     let response = app.login(&login_body).await;
     assert_eq!(response.status().as_u16(), 206);
+    let (second_attempt_id, second_attempt) = app.get_2fa_attempt(response).await;
+    if second_attempt.code == first_attempt.code {
+        // Both attempts drew the same random code, nothing to tell apart
+        app.cleanup().await;
+        return;
+    }
 
-    // Try to verify with the old code
+    // Try to verify the new attempt with the old code
     let verify_body = serde_json::json!({
-        "email": email,
-        "loginAttemptId": first_code.0.as_ref().expose_secret(),
-        "2FACode": first_code.1.as_ref().expose_secret(),
+        "loginAttemptId": second_attempt_id.as_ref().expose_secret(),
+        "2FACode": first_attempt.code.as_ref().expose_secret(),
     });
 
     let response = app.verify_2fa(&verify_body).await;
@@ -332,21 +518,12 @@ async fn should_return_401_if_same_code_twice() {
     assert_eq!(response.status().as_u16(), 206);
 
     // Get the 2FA code
-    let email_obj = Email::parse(&email).unwrap();
-    let code = app
-        .app_state
-        .two_fa_code_store
-        .read()
-        .await
-        .get_code(&email_obj)
-        .await
-        .expect("Failed to get 2FA code");
+    let (login_attempt_id, attempt) = app.get_2fa_attempt(response).await;
 
     // First verification (should succeed)
     let verify_body = serde_json::json!({
-        "email": email,
-        "loginAttemptId": code.0.as_ref().expose_secret(),
-        "2FACode": code.1.as_ref().expose_secret(),
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": attempt.code.as_ref().expose_secret(),
     });
 
     let response = app.verify_2fa(&verify_body).await;
@@ -365,3 +542,165 @@ async fn should_return_401_if_same_code_twice() {
 
     app.cleanup().await;
 }
+
+#[tokio::test]
+async fn should_send_templated_2fa_email() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("User-Agent", "template-test/1.0")
+        .json(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (_, attempt) = app.get_2fa_attempt(response).await;
+    let code = attempt.code.as_ref().expose_secret().to_owned();
+
+    assert_eq!(app.process_email_outbox().await, 1);
+    let requests = app.email_server.received_requests().await.unwrap();
+    let batch: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    let body = &batch[0];
+
+    assert_eq!(body["Subject"], "Your Live Bootcamp verification code");
+    assert_eq!(body["Tag"], "2fa-code");
+    for field in ["HtmlBody", "TextBody"] {
+        let content = body[field].as_str().unwrap();
+        assert!(content.contains(&code), "{field} does not contain the code");
+        assert!(content.contains("10 minutes"), "{field} has no expiry");
+        assert!(
+            content.contains("template-test/1.0"),
+            "{field} has no device"
+        );
+    }
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<!DOCTYPE html>"));
+
+    app.cleanup().await;
+}
+
+async fn signup_with_sms_2fa(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": true,
+            "phoneNumber": "+14155550123",
+            "twoFAChannel": "sms",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+#[tokio::test]
+async fn should_send_2fa_code_by_sms_when_preferred() {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, ResponseTemplate,
+    };
+
+    let mut app = TestApp::new().await;
+    let email = signup_with_sms_2fa(&app).await;
+
+    Mock::given(path("/messages"))
+        .and(method("POST"))
+        .and(header("Authorization", "Bearer sms_token"))
+        .respond_with(ResponseTemplate::new(202))
+        .expect(1)
+        .mount(&app.sms_server)
+        .await;
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (_, attempt) = app.get_2fa_attempt(response).await;
+    let code = attempt.code;
+
+    let requests = app.sms_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["to"], "+14155550123");
+    assert_eq!(body["from"], "LiveBootcamp");
+    assert!(body["body"]
+        .as_str()
+        .unwrap()
+        .contains(code.as_ref().expose_secret()));
+
+    // Nothing goes out by email
+    assert_eq!(app.process_email_outbox().await, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_500_if_sms_provider_fails() {
+    use wiremock::{matchers::any, Mock, ResponseTemplate};
+
+    let mut app = TestApp::new().await;
+    let email = signup_with_sms_2fa(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.sms_server)
+        .await;
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_fall_back_to_email_if_sms_is_not_configured() {
+    let mut settings = test_settings();
+    settings.sms_client = None;
+    let mut app = TestApp::with_settings(settings).await;
+    let email = signup_with_sms_2fa(&app).await;
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    assert_eq!(app.deliver_emails_to_mailbox().await, 1);
+    assert!(app
+        .mailbox
+        .latest_to(&Email::parse(&email).unwrap())
+        .is_some());
+    assert!(app.sms_server.received_requests().await.unwrap().is_empty());
+
+    app.cleanup().await;
+}
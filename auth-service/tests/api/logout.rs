@@ -1,8 +1,11 @@
-use auth_service::utils::JWT_COOKIE_NAME;
-use reqwest::Url;
-use secrecy::Secret;
+use auth_service::{
+    settings::SameSiteSetting,
+    utils::{validate_token, JWT_COOKIE_NAME},
+};
+use reqwest::{header::COOKIE, Url};
+use secrecy::ExposeSecret;
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, test_settings, TestApp};
 
 #[tokio::test]
 async fn should_return_400_if_jwt_cookie_missing() {
@@ -76,16 +79,20 @@ async fn should_return_200_if_valid_jwt_cookie() {
         "Auth cookie should not be empty"
     );
 
-    // Extract the JWT token value before logout
-    let jwt_token = Secret::new(auth_cookie.value().to_string());
+    // Extract the JWT token's claims before logout
+    let claims = validate_token(
+        auth_cookie.value(),
+        &app.app_state.settings.auth,
+        &app.app_state.token_revocations,
+    )
+    .await
+    .expect("Failed to validate token");
 
     // Verify token is not banned before logout
     let is_banned_before = app
         .app_state
         .banned_token_store
-        .read()
-        .await
-        .is_token_banned(&jwt_token)
+        .is_token_banned(&claims)
         .await
         .expect("Failed to check if token is banned");
 
@@ -112,9 +119,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
     let is_banned_after = app
         .app_state
         .banned_token_store
-        .read()
-        .await
-        .is_token_banned(&jwt_token)
+        .is_token_banned(&claims)
         .await
         .expect("Failed to check if token is banned");
 
@@ -123,6 +128,146 @@ async fn should_return_200_if_valid_jwt_cookie() {
     app.cleanup().await;
 }
 
+#[tokio::test]
+async fn should_ban_bearer_token() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    let response = app.logout_with_bearer(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Banned tokens can't be used to log out again
+    let response = app.logout_with_bearer(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_already_banned() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    let jwt_token = auth_cookie.value().to_string();
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Replay the cookie the logout cleared
+    app.cookie_jar.add_cookie_str(
+        &format!("{JWT_COOKIE_NAME}={jwt_token}; Path=/; HttpOnly; SameSite=Lax"),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_ban_token_issued_without_jti() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Tokens issued before `jti` was added only carry `sub` and `exp`
+    let legacy_token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({
+            "sub": email,
+            "exp": chrono::Utc::now().timestamp() + 600,
+        }),
+        &jsonwebtoken::EncodingKey::from_secret(
+            app.app_state
+                .settings
+                .auth
+                .jwt_secret
+                .expose_secret()
+                .as_bytes(),
+        ),
+    )
+    .expect("Failed to encode token");
+
+    let response = app
+        .verify_token(&serde_json::json!({ "token": legacy_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{JWT_COOKIE_NAME}={legacy_token}; Path=/; HttpOnly; SameSite=Lax"),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .verify_token(&serde_json::json!({ "token": legacy_token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
 #[tokio::test]
 async fn should_return_400_if_logout_called_twice_in_a_row() {
     let mut app = TestApp::new().await;
@@ -185,3 +330,66 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
 
     app.cleanup().await;
 }
+
+#[tokio::test]
+async fn should_clear_cookie_with_matching_attributes() {
+    let mut settings = test_settings();
+    settings.auth.cookie.secure = true;
+    settings.auth.cookie.host_prefix = true;
+    settings.auth.cookie.same_site = SameSiteSetting::Strict;
+
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie_name = format!("__Host-{JWT_COOKIE_NAME}");
+    let token = response
+        .cookies()
+        .find(|c| c.name() == cookie_name)
+        .expect("No auth cookie found")
+        .value()
+        .to_string();
+
+    // The test server is plain HTTP, so the cookie jar won't send a Secure cookie back
+    let response = app
+        .http_client
+        .post(format!("{}/logout", &app.address))
+        .header(COOKIE, format!("{cookie_name}={token}"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let logout_cookie = response
+        .cookies()
+        .find(|c| c.name() == cookie_name)
+        .expect("No auth cookie found after logout");
+
+    assert!(logout_cookie.value().is_empty());
+    assert!(logout_cookie.secure());
+    assert!(logout_cookie.same_site_strict());
+    assert_eq!(logout_cookie.path(), Some("/"));
+    assert_eq!(logout_cookie.max_age(), Some(std::time::Duration::ZERO));
+
+    app.cleanup().await;
+}
@@ -0,0 +1,168 @@
+use auth_service::{
+    domain::Email,
+    settings::CacheBackend,
+    utils::{validate_token, JWT_COOKIE_NAME},
+};
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Logs in and returns the issued token, which also stays in the app's cookie jar
+async fn login(app: &TestApp, email: &str) -> String {
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+async fn verify_token_status(app: &TestApp, token: &str) -> u16 {
+    app.verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    app.cookie_jar.add_cookie_str(
+        &format!("{JWT_COOKIE_NAME}=invalid_token; Path=/; HttpOnly; SameSite=Lax"),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_revoke_every_token_of_the_user() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let other_email = get_random_email();
+    signup(&app, &email).await;
+    signup(&app, &other_email).await;
+
+    let other_token = login(&app, &other_email).await;
+    let first_token = login(&app, &email).await;
+    let second_token = login(&app, &email).await;
+
+    // Verifying caches the user's tokens_valid_after, revoking must replace it
+    assert_eq!(verify_token_status(&app, &first_token).await, 200);
+    assert_eq!(verify_token_status(&app, &second_token).await, 200);
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let logout_cookie = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found after logout");
+    assert!(logout_cookie.value().is_empty());
+
+    assert_eq!(verify_token_status(&app, &first_token).await, 401);
+    assert_eq!(verify_token_status(&app, &second_token).await, 401);
+    assert_eq!(verify_token_status(&app, &other_token).await, 200);
+
+    let user = app
+        .app_state
+        .user_store
+        .get_user(&Email::parse(&email).unwrap())
+        .await
+        .unwrap();
+    assert!(user.tokens_valid_after().is_some());
+
+    // Tokens issued in the second of the revocation are rejected too
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let new_token = login(&app, &email).await;
+    assert_eq!(verify_token_status(&app, &new_token).await, 200);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_reject_revoked_tokens_without_cache() {
+    let mut settings = test_settings();
+    settings.stores.tokens_valid_after_cache = CacheBackend::None;
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = login(&app, &email).await;
+    assert_eq!(verify_token_status(&app, &token).await, 200);
+
+    app.app_state
+        .token_revocations
+        .revoke_all(&Email::parse(&email).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(verify_token_status(&app, &token).await, 401);
+    assert!(validate_token(
+        &token,
+        &app.app_state.settings.auth,
+        &app.app_state.token_revocations,
+    )
+    .await
+    .is_err());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_already_banned() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = login(&app, &email).await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Replay the cookie the logout cleared
+    app.cookie_jar.add_cookie_str(
+        &format!("{JWT_COOKIE_NAME}={token}; Path=/; HttpOnly; SameSite=Lax"),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let response = app.logout_all().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
@@ -1,7 +1,20 @@
+mod admin;
+mod audit;
+mod cors;
+mod dev_mailbox;
+mod email_outbox;
+mod health;
 mod helpers;
 mod login;
 mod logout;
+mod logout_all;
+mod metrics;
+mod request_id;
 mod root;
+mod shutdown;
 mod signup;
+mod smtp_email_client;
+mod smtp_sink;
+mod store_conformance;
 mod verify_2fa;
 mod verify_token;
@@ -0,0 +1,139 @@
+use crate::helpers::{get_random_email, TestApp};
+
+// The Prometheus recorder is process-wide and shared by every test in this binary,
+// so these tests only assert on series they are guaranteed to have produced.
+
+#[tokio::test]
+async fn should_expose_metrics_in_prometheus_text_format() {
+    let mut app = TestApp::new().await;
+
+    app.get_health_live().await;
+
+    let response = app.get_metrics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain")));
+
+    let body = response.text().await.expect("Failed to read metrics body");
+
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/health/live",status="200"}"#));
+    assert!(body.contains("# TYPE http_request_duration_seconds histogram"));
+    assert!(body.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/health/live",status="200",le="0.005"}"#));
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_not_expose_metrics_on_the_public_listener() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_record_login_outcomes_and_password_hash_timings() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "wrongPassword123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let body = app
+        .get_metrics()
+        .await
+        .text()
+        .await
+        .expect("Failed to read metrics body");
+
+    for expected in [
+        r#"auth_logins_total{outcome="success"}"#,
+        r#"auth_logins_total{outcome="failure"}"#,
+        r#"http_requests_total{method="POST",route="/login",status="401"}"#,
+        r#"password_hash_duration_seconds_count{operation="hash"}"#,
+        r#"password_hash_duration_seconds_count{operation="verify"}"#,
+        r#"store_operation_duration_seconds_count{store="postgres_user",operation="validate_user",outcome="ok"}"#,
+        "db_pool_connections{state=\"idle\"}",
+    ] {
+        assert!(body.contains(expected), "Missing {expected} in:\n{body}");
+    }
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_count_email_send_failures() {
+    let mut app = TestApp::new().await;
+    app.email_server.reset().await;
+
+    wiremock::Mock::given(wiremock::matchers::any())
+        .respond_with(wiremock::ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    // The email is only sent by the outbox worker, a failing provider does not fail the login
+    assert_eq!(response.status().as_u16(), 206);
+    assert_eq!(app.process_email_outbox().await, 1);
+
+    let body = app
+        .get_metrics()
+        .await
+        .text()
+        .await
+        .expect("Failed to read metrics body");
+
+    assert!(body.contains(r#"email_send_failures_total{client="postmark"}"#));
+    assert!(body.contains(r#"auth_logins_total{outcome="2fa_required"}"#));
+
+    app.cleanup().await;
+}
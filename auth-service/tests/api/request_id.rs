@@ -0,0 +1,44 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_generate_request_id_when_none_is_sent() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_live().await;
+
+    let request_id = response
+        .headers()
+        .get("x-request-id")
+        .expect("Missing X-Request-Id header")
+        .to_str()
+        .unwrap();
+    assert!(uuid::Uuid::parse_str(request_id).is_ok());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_echo_incoming_request_id() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/verify-token", &app.address))
+        .header("X-Request-Id", "upstream-request-42")
+        .header(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .json(&serde_json::json!({ "token": "invalid" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers().get("x-request-id").unwrap(),
+        "upstream-request-42"
+    );
+
+    app.cleanup().await;
+}
@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use crate::helpers::{get_random_email, test_settings, TestApp};
+
+const LOCK_HOLD: Duration = Duration::from_millis(500);
+
+async fn app_with_drain_timeout(drain_timeout_seconds: u64) -> TestApp {
+    let mut settings = test_settings();
+    settings.application.drain_timeout_seconds = drain_timeout_seconds;
+
+    TestApp::with_settings(settings).await
+}
+
+async fn signup_with_2fa(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+#[tokio::test]
+async fn should_complete_in_flight_request_on_shutdown() {
+    let mut app = app_with_drain_timeout(5).await;
+    let email = signup_with_2fa(&app).await;
+
+    // Holding the users table lock keeps the login in flight while shutdown starts
+    let users_lock = app.lock_users_table().await;
+
+    let body = serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+    });
+    let login = app.login(&body);
+    let shutdown = async {
+        tokio::time::sleep(LOCK_HOLD / 5).await;
+        app.trigger_shutdown();
+        tokio::time::sleep(LOCK_HOLD).await;
+        drop(users_lock);
+    };
+    let (response, _) = tokio::join!(login, shutdown);
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    tokio::time::timeout(Duration::from_secs(2), app.wait_for_shutdown())
+        .await
+        .expect("Server did not stop after draining")
+        .expect("Server returned an error");
+
+    // No new connections are accepted once the server has stopped
+    assert!(app
+        .http_client
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await
+        .is_err());
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_stop_waiting_for_requests_after_drain_timeout() {
+    let mut app = app_with_drain_timeout(1).await;
+    let email = signup_with_2fa(&app).await;
+
+    // The login cannot finish while the lock is held, which outlasts the drain timeout
+    let users_lock = app.lock_users_table().await;
+
+    let login = tokio::spawn({
+        let http_client = app.http_client.clone();
+        let url = format!("{}/login", &app.address);
+        async move {
+            http_client
+                .post(url)
+                .json(&serde_json::json!({
+                    "email": email,
+                    "password": "validPass123!",
+                }))
+                .send()
+                .await
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = Instant::now();
+    app.trigger_shutdown();
+
+    app.wait_for_shutdown()
+        .await
+        .expect("Server returned an error");
+
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed >= Duration::from_secs(1),
+        "Stopped after {elapsed:?}"
+    );
+    assert!(
+        elapsed < Duration::from_secs(5),
+        "Stopped after {elapsed:?}"
+    );
+
+    drop(users_lock);
+    login.abort();
+    app.cleanup().await;
+}
@@ -1,4 +1,8 @@
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use auth_service::{
+    domain::{Email, PhoneNumber, TwoFAChannel},
+    routes::SignupResponse,
+    ErrorResponse,
+};
 
 use crate::helpers::{get_random_email, TestApp};
 
@@ -113,6 +117,20 @@ async fn should_return_400_if_invalid_input() {
             "password": "validPass123!",
             "requires2FA": true,
         }),
+        // Phone number not in E.164 format
+        serde_json::json!({
+            "email": random_email,
+            "password": "validPass123!",
+            "requires2FA": true,
+            "phoneNumber": "415-555-0123",
+        }),
+        // SMS channel without a phone number
+        serde_json::json!({
+            "email": random_email,
+            "password": "validPass123!",
+            "requires2FA": true,
+            "twoFAChannel": "sms",
+        }),
     ];
 
     for test_case in test_cases.iter() {
@@ -180,3 +198,35 @@ async fn should_return_409_if_email_already_exists() {
 
     app.cleanup().await;
 }
+
+#[tokio::test]
+async fn should_store_phone_number_and_2fa_channel() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let response = app
+        .signup(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+            "requires2FA": true,
+            "phoneNumber": "+14155550123",
+            "twoFAChannel": "sms",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let user = app
+        .app_state
+        .user_store
+        .get_user(&Email::parse(&email).unwrap())
+        .await
+        .expect("User was not stored");
+
+    assert_eq!(user.two_fa_channel(), TwoFAChannel::Sms);
+    assert_eq!(
+        user.phone_number(),
+        Some(&PhoneNumber::parse("+14155550123").unwrap())
+    );
+
+    app.cleanup().await;
+}
@@ -0,0 +1,165 @@
+use std::{sync::Arc, time::Duration};
+
+use auth_service::{
+    domain::{Email, EmailClient, EmailContent, PermanentEmailFailure},
+    services::{EmailOutboxWorker, MeteredEmailClient, SmtpEmailClient},
+    settings::{SmtpSettings, SmtpTls},
+};
+use secrecy::{ExposeSecret, Secret};
+use tokio::sync::RwLock;
+
+use crate::{
+    helpers::{get_random_email, TestApp},
+    smtp_sink::SmtpSink,
+};
+
+const SENDER: &str = "sender@example.com";
+
+fn smtp_settings(sink: &SmtpSink, username: Option<&str>) -> SmtpSettings {
+    SmtpSettings {
+        host: "127.0.0.1".to_owned(),
+        port: sink.port,
+        tls: SmtpTls::None,
+        username: username.map(str::to_owned),
+        password: Secret::new("smtp-password".to_owned()),
+        max_pool_size: 2,
+    }
+}
+
+fn smtp_client(sink: &SmtpSink, username: Option<&str>) -> SmtpEmailClient {
+    SmtpEmailClient::new(
+        &smtp_settings(sink, username),
+        Email::parse(SENDER).unwrap(),
+        Duration::from_secs(5),
+    )
+    .expect("Failed to build SMTP client")
+}
+
+fn content() -> EmailContent {
+    EmailContent {
+        subject: "Your code".to_owned(),
+        html_body: "<p>Your code is 123456</p>".to_owned(),
+        text_body: "Your code is 123456".to_owned(),
+    }
+}
+
+#[tokio::test]
+async fn should_send_multipart_email() {
+    let sink = SmtpSink::start(vec![]).await;
+    let client = smtp_client(&sink, None);
+    let recipient = get_random_email();
+
+    client
+        .send_email(&Email::parse(&recipient).unwrap(), &content())
+        .await
+        .expect("Failed to send email");
+
+    let messages = sink.messages();
+    assert_eq!(messages.len(), 1);
+
+    let message = &messages[0];
+    assert_eq!(message.from, SENDER);
+    assert_eq!(message.to, vec![recipient.clone()]);
+    assert!(message.data.contains("Subject: Your code"));
+    assert!(message.data.contains(&format!("To: {recipient}")));
+    assert!(message.data.contains("Content-Type: multipart/alternative"));
+    assert!(message.data.contains("Content-Type: text/plain"));
+    assert!(message.data.contains("Content-Type: text/html"));
+    assert!(message.data.contains("Your code is 123456"));
+    assert!(message.data.contains("<p>Your code is 123456</p>"));
+    assert!(sink.logins().is_empty());
+}
+
+#[tokio::test]
+async fn should_authenticate_with_configured_credentials() {
+    let sink = SmtpSink::start(vec![]).await;
+    let client = smtp_client(&sink, Some("smtp-user"));
+
+    client
+        .send_email(&Email::parse(&get_random_email()).unwrap(), &content())
+        .await
+        .expect("Failed to send email");
+
+    assert_eq!(
+        sink.logins(),
+        vec![("smtp-user".to_owned(), "smtp-password".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn should_reuse_pooled_connections() {
+    let sink = SmtpSink::start(vec![]).await;
+    let client = smtp_client(&sink, Some("smtp-user"));
+
+    for _ in 0..5 {
+        client
+            .send_email(&Email::parse(&get_random_email()).unwrap(), &content())
+            .await
+            .expect("Failed to send email");
+    }
+
+    // Connections go back to the pool asynchronously, so a send may occasionally open a
+    // new one, but never beyond the configured pool size
+    assert_eq!(sink.messages().len(), 5);
+    assert!(
+        sink.connections() <= 2,
+        "Opened {} connections",
+        sink.connections()
+    );
+    assert_eq!(sink.logins().len(), sink.connections());
+}
+
+#[tokio::test]
+async fn should_fail_when_recipient_is_rejected() {
+    let recipient = get_random_email();
+    let sink = SmtpSink::start(vec![recipient.clone()]).await;
+    let client = smtp_client(&sink, None);
+
+    let result = client
+        .send_email(&Email::parse(&recipient).unwrap(), &content())
+        .await;
+
+    assert!(PermanentEmailFailure::is_permanent(&result.unwrap_err()));
+    assert!(sink.messages().is_empty());
+}
+
+#[tokio::test]
+async fn should_deliver_2fa_email_from_outbox_over_smtp() {
+    let mut app = TestApp::new().await;
+    let sink = SmtpSink::start(vec![]).await;
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+    let response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let (_, attempt) = app.get_2fa_attempt(response).await;
+
+    let worker = EmailOutboxWorker::new(
+        app.app_state.email_outbox.clone(),
+        Arc::new(RwLock::new(MeteredEmailClient::new(
+            smtp_client(&sink, None),
+            "smtp",
+        ))),
+        &app.app_state.settings.email_outbox,
+    );
+    assert_eq!(worker.process_due().await.unwrap(), 1);
+
+    let messages = sink.messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].to, vec![email]);
+    assert!(messages[0]
+        .data
+        .contains(attempt.code.as_ref().expose_secret()));
+
+    app.cleanup().await;
+}
@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+// A message accepted by the sink
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub data: String,
+}
+
+#[derive(Default)]
+struct SinkState {
+    messages: Vec<ReceivedMessage>,
+    // Decoded AUTH PLAIN credentials, as (username, password)
+    logins: Vec<(String, String)>,
+    connections: usize,
+}
+
+// A minimal plaintext SMTP server recording everything it receives, so the SMTP email
+// client can be tested without a real mail provider. Recipients listed in `reject` are
+// refused with a permanent 550 error.
+pub struct SmtpSink {
+    pub port: u16,
+    state: Arc<Mutex<SinkState>>,
+    server: JoinHandle<()>,
+}
+
+impl SmtpSink {
+    pub async fn start(reject: Vec<String>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind SMTP sink");
+        let port = listener.local_addr().unwrap().port();
+        let state = Arc::new(Mutex::new(SinkState::default()));
+
+        let server = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    state.lock().unwrap().connections += 1;
+                    tokio::spawn(handle_session(stream, state.clone(), reject.clone()));
+                }
+            }
+        });
+
+        Self {
+            port,
+            state,
+            server,
+        }
+    }
+
+    pub fn messages(&self) -> Vec<ReceivedMessage> {
+        self.state.lock().unwrap().messages.clone()
+    }
+
+    pub fn logins(&self) -> Vec<(String, String)> {
+        self.state.lock().unwrap().logins.clone()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+}
+
+impl Drop for SmtpSink {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle_session(stream: TcpStream, state: Arc<Mutex<SinkState>>, reject: Vec<String>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let mut from = String::new();
+    let mut to = Vec::new();
+
+    if writer.write_all(b"220 smtp-sink ready\r\n").await.is_err() {
+        return;
+    }
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_ascii_uppercase();
+
+        let reply = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            "250-smtp-sink\r\n250-AUTH PLAIN\r\n250 8BITMIME\r\n".to_owned()
+        } else if command.starts_with("AUTH PLAIN") {
+            let encoded = line.split_whitespace().nth(2).unwrap_or_default();
+            match decode_plain(encoded) {
+                Some(login) => {
+                    state.lock().unwrap().logins.push(login);
+                    "235 Authenticated\r\n".to_owned()
+                }
+                None => "535 Invalid credentials\r\n".to_owned(),
+            }
+        } else if command.starts_with("MAIL FROM:") {
+            from = address(&line);
+            to.clear();
+            "250 OK\r\n".to_owned()
+        } else if command.starts_with("RCPT TO:") {
+            let recipient = address(&line);
+            if reject.contains(&recipient) {
+                "550 Mailbox unavailable\r\n".to_owned()
+            } else {
+                to.push(recipient);
+                "250 OK\r\n".to_owned()
+            }
+        } else if command == "DATA" {
+            if writer.write_all(b"354 End data with .\r\n").await.is_err() {
+                return;
+            }
+            let mut data = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                data.push(line.strip_prefix('.').unwrap_or(&line).to_owned());
+            }
+            state.lock().unwrap().messages.push(ReceivedMessage {
+                from: from.clone(),
+                to: std::mem::take(&mut to),
+                data: data.join("\r\n"),
+            });
+            "250 Queued\r\n".to_owned()
+        } else if command == "QUIT" {
+            let _ = writer.write_all(b"221 Bye\r\n").await;
+            return;
+        } else {
+            // RSET, NOOP, ...
+            "250 OK\r\n".to_owned()
+        };
+
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+// Extracts the address from `MAIL FROM:<a@b.c>` or `RCPT TO:<a@b.c>`
+fn address(line: &str) -> String {
+    line.split_once('<')
+        .and_then(|(_, rest)| rest.split_once('>'))
+        .map(|(address, _)| address.to_owned())
+        .unwrap_or_default()
+}
+
+fn decode_plain(encoded: &str) -> Option<(String, String)> {
+    use base64::Engine;
+
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let mut parts = decoded.split('\0').skip(1);
+
+    Some((parts.next()?.to_owned(), parts.next()?.to_owned()))
+}
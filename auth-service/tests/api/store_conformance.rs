@@ -0,0 +1,1250 @@
+// Conformance suites shared by every implementation of the store traits. Each case is
+// generic over a `StoreHarness`, which builds empty stores and moves their notion of time
+// forward, so the in-memory, Postgres, SQLite and Redis stores are held to the same behaviour.
+// `conformance_tests!` runs a suite against one implementation.
+use std::{sync::Arc, time::Duration};
+
+use auth_service::{
+    domain::{
+        BannedTokenStore, BannedTokenStoreError, Email, LoginAttemptId, Password, Role,
+        TwoFAAttempt, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, User, UserQuery, UserStore,
+        UserStoreError, ADMIN_ROLE,
+    },
+    get_redis_connection,
+    services::data_stores::{
+        HashMapTwoFACodeStore, HashMapUserStore, HashSetBannedTokenStore, PostgresBannedTokenStore,
+        PostgresTwoFACodeStore, PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore,
+        SqliteBannedTokenStore, SqliteTwoFACodeStore, SqliteUserStore,
+    },
+    utils::{Claims, Clock, ManualClock},
+};
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
+use futures::future::join_all;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, SqlitePool};
+use tempfile::TempDir;
+
+use crate::helpers::{configure_sqlite, get_random_email, test_settings, TestApp};
+
+// Kept short so the Redis suites can wait for real expiry
+const TTL: Duration = Duration::from_secs(1);
+
+const CONCURRENT_CALLS: usize = 10;
+
+#[async_trait::async_trait]
+pub trait StoreHarness: Send + Sync {
+    type Store: Send + Sync + 'static;
+
+    // An empty store whose entries expire `TTL` after they were added
+    async fn store(&self) -> Self::Store;
+
+    // Moves the stores' time forward by at least `by`
+    async fn advance(&self, by: Duration);
+
+    // The current time as the stores see it
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    async fn cleanup(&mut self) {}
+}
+
+macro_rules! conformance_tests {
+    ($name:ident, $suite:ident, $harness:expr, [$($case:ident),+ $(,)?]) => {
+        mod $name {
+            use super::*;
+
+            $(
+                #[tokio::test]
+                async fn $case() {
+                    let mut harness = $harness;
+                    super::$suite::$case(&harness).await;
+                    harness.cleanup().await;
+                }
+            )+
+        }
+    };
+}
+
+macro_rules! user_store_conformance {
+    ($name:ident, $harness:expr) => {
+        conformance_tests!(
+            $name,
+            user_store,
+            $harness,
+            [
+                should_get_added_user,
+                should_reject_duplicate_user,
+                should_not_find_missing_user,
+                should_validate_correct_password,
+                should_reject_wrong_password,
+                should_not_validate_missing_user,
+                should_admit_one_of_concurrent_duplicate_signups,
+                should_set_tokens_valid_after,
+                should_not_set_tokens_valid_after_for_missing_user,
+                should_list_users_in_pages,
+                should_search_users_literally,
+                should_set_disabled_and_requires_2fa,
+                should_lock_after_max_failed_logins,
+                should_reset_lockout,
+                should_delete_user,
+                should_not_update_missing_user,
+                should_list_seeded_roles,
+                should_assign_and_remove_roles,
+                should_list_users_with_their_roles,
+                should_not_assign_missing_role_or_to_missing_user,
+                should_drop_roles_with_deleted_user,
+            ]
+        );
+    };
+}
+
+macro_rules! banned_token_store_conformance {
+    ($name:ident, $harness:expr) => {
+        conformance_tests!(
+            $name,
+            banned_token_store,
+            $harness,
+            [
+                should_report_banned_token,
+                should_not_report_unknown_token,
+                should_reject_duplicate_ban,
+                should_ban_by_jti,
+                should_expire_ban_with_token,
+                should_admit_one_of_concurrent_bans,
+            ]
+        );
+    };
+}
+
+macro_rules! two_fa_code_store_conformance {
+    ($name:ident, $harness:expr) => {
+        conformance_tests!(
+            $name,
+            two_fa_code_store,
+            $harness,
+            [
+                should_get_added_attempt,
+                should_not_find_missing_attempt,
+                should_remove_attempt,
+                should_allow_removing_missing_attempt,
+                should_replace_attempt_with_same_id,
+                should_expire_attempt_after_ttl,
+                should_keep_concurrent_attempts_independent,
+                should_take_attempt_once,
+                should_not_take_expired_attempt,
+                should_let_one_of_concurrent_takes_succeed,
+                should_discard_attempt_after_max_failed_codes,
+                should_count_failed_codes_again_for_replaced_attempt,
+                should_ignore_failed_code_for_missing_attempt,
+                should_allow_max_failed_codes_when_missed_concurrently,
+            ]
+        );
+    };
+}
+
+mod user_store {
+    use super::*;
+
+    const PASSWORD: &str = "validPass123!";
+
+    fn user(email: &Email) -> User {
+        User::new(
+            email.clone(),
+            Password::parse(&Secret::new(PASSWORD.to_owned())).unwrap(),
+            true,
+        )
+    }
+
+    fn random_email() -> Email {
+        Email::parse(&get_random_email()).unwrap()
+    }
+
+    pub async fn should_get_added_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+
+        store.add_user(user(&email)).await.unwrap();
+
+        let stored = store.get_user(&email).await.unwrap();
+        assert_eq!(
+            stored.email().expose_secret(),
+            email.as_ref().expose_secret()
+        );
+        assert!(stored.requires_2fa());
+    }
+
+    pub async fn should_reject_duplicate_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+
+        store.add_user(user(&email)).await.unwrap();
+
+        assert_eq!(
+            store.add_user(user(&email)).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+    }
+
+    pub async fn should_not_find_missing_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+
+        assert_eq!(
+            store.get_user(&random_email()).await.err(),
+            Some(UserStoreError::UserNotFound)
+        );
+    }
+
+    pub async fn should_validate_correct_password<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+
+        assert_eq!(store.validate_user(&email, PASSWORD).await, Ok(()));
+    }
+
+    pub async fn should_reject_wrong_password<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+
+        assert_eq!(
+            store.validate_user(&email, "wrongPass123!").await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+    }
+
+    pub async fn should_not_validate_missing_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+
+        assert_eq!(
+            store.validate_user(&random_email(), PASSWORD).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    pub async fn should_admit_one_of_concurrent_duplicate_signups<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        // Calls share the store without a lock, like requests do
+        let store = Arc::new(harness.store().await);
+        let email = random_email();
+
+        let signups = (0..CONCURRENT_CALLS).map(|_| {
+            let store = store.clone();
+            let user = user(&email);
+            tokio::spawn(async move { store.add_user(user).await })
+        });
+        let results: Vec<_> = join_all(signups)
+            .await
+            .into_iter()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .all(|e| *e == UserStoreError::UserAlreadyExists));
+    }
+
+    pub async fn should_set_tokens_valid_after<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+        assert_eq!(
+            store.get_user(&email).await.unwrap().tokens_valid_after(),
+            None
+        );
+
+        let valid_after = Utc::now().trunc_subsecs(0);
+        store
+            .set_tokens_valid_after(&email, valid_after)
+            .await
+            .unwrap();
+
+        let stored = store.get_user(&email).await.unwrap();
+        assert_eq!(stored.tokens_valid_after(), Some(valid_after));
+        assert_eq!(store.validate_user(&email, PASSWORD).await, Ok(()));
+    }
+
+    pub async fn should_not_set_tokens_valid_after_for_missing_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+
+        assert_eq!(
+            store
+                .set_tokens_valid_after(&random_email(), Utc::now())
+                .await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    // Emails sharing a prefix no other test uses, so searching for it sees only these users
+    fn emails_with_prefix(count: usize) -> (String, Vec<Email>) {
+        let prefix = uuid::Uuid::new_v4().simple().to_string();
+        let emails = (0..count)
+            .map(|i| Email::parse(&format!("{prefix}-{i}@example.com")).unwrap())
+            .collect();
+        (prefix, emails)
+    }
+
+    fn emails_of(users: &[User]) -> Vec<String> {
+        users
+            .iter()
+            .map(|user| user.email().expose_secret().to_owned())
+            .collect()
+    }
+
+    pub async fn should_list_users_in_pages<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let (prefix, emails) = emails_with_prefix(3);
+        // Added out of order, listing sorts by email
+        for email in emails.iter().rev() {
+            store.add_user(user(email)).await.unwrap();
+        }
+        let expected: Vec<_> = emails
+            .iter()
+            .map(|email| email.as_ref().expose_secret().to_owned())
+            .collect();
+
+        let mut query = UserQuery {
+            search: Some(prefix.to_uppercase()),
+            after: None,
+            limit: 2,
+        };
+        let first_page = store.list_users(&query).await.unwrap();
+        assert_eq!(emails_of(&first_page), expected[..2]);
+
+        query.after = Some(expected[1].clone());
+        let second_page = store.list_users(&query).await.unwrap();
+        assert_eq!(emails_of(&second_page), expected[2..]);
+
+        query.after = Some(expected[2].clone());
+        assert!(store.list_users(&query).await.unwrap().is_empty());
+    }
+
+    pub async fn should_search_users_literally<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let (prefix, emails) = emails_with_prefix(2);
+        for email in &emails {
+            store.add_user(user(email)).await.unwrap();
+        }
+
+        let search = |search: String| UserQuery {
+            search: Some(search),
+            after: None,
+            limit: 10,
+        };
+        assert_eq!(
+            store
+                .list_users(&search(format!("{prefix}-1")))
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        // `%` and `_` are wildcards in SQL patterns
+        for wildcard in [format!("{prefix}%"), format!("{prefix}_")] {
+            assert!(store
+                .list_users(&search(wildcard))
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    pub async fn should_set_disabled_and_requires_2fa<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+        let stored = store.get_user(&email).await.unwrap();
+        assert!(!stored.disabled());
+        assert!(stored.roles().is_empty());
+
+        store.set_disabled(&email, true).await.unwrap();
+        store.set_requires_2fa(&email, false).await.unwrap();
+        let stored = store.get_user(&email).await.unwrap();
+        assert!(stored.disabled());
+        assert!(!stored.requires_2fa());
+
+        store.set_disabled(&email, false).await.unwrap();
+        store.set_requires_2fa(&email, true).await.unwrap();
+        let stored = store.get_user(&email).await.unwrap();
+        assert!(!stored.disabled());
+        assert!(stored.requires_2fa());
+    }
+
+    pub async fn should_lock_after_max_failed_logins<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+        let lock_until = Utc::now().trunc_subsecs(0) + TimeDelta::minutes(15);
+
+        for attempts in 1..3 {
+            store
+                .record_failed_login(&email, 3, lock_until)
+                .await
+                .unwrap();
+            let stored = store.get_user(&email).await.unwrap();
+            assert_eq!(stored.failed_login_attempts(), attempts);
+            assert_eq!(stored.locked_until(), None);
+        }
+
+        // The count starts over once the account is locked
+        store
+            .record_failed_login(&email, 3, lock_until)
+            .await
+            .unwrap();
+        let stored = store.get_user(&email).await.unwrap();
+        assert_eq!(stored.failed_login_attempts(), 0);
+        assert_eq!(stored.locked_until(), Some(lock_until));
+        assert!(stored.is_locked_at(Utc::now()));
+    }
+
+    pub async fn should_reset_lockout<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+        let lock_until = Utc::now().trunc_subsecs(0) + TimeDelta::minutes(15);
+        store
+            .record_failed_login(&email, 1, lock_until)
+            .await
+            .unwrap();
+        store
+            .record_failed_login(&email, 2, lock_until)
+            .await
+            .unwrap();
+
+        store.reset_lockout(&email).await.unwrap();
+
+        let stored = store.get_user(&email).await.unwrap();
+        assert_eq!(stored.failed_login_attempts(), 0);
+        assert_eq!(stored.locked_until(), None);
+    }
+
+    pub async fn should_delete_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+
+        store.delete_user(&email).await.unwrap();
+
+        assert_eq!(
+            store.get_user(&email).await.err(),
+            Some(UserStoreError::UserNotFound)
+        );
+        // The email can be signed up again
+        store.add_user(user(&email)).await.unwrap();
+    }
+
+    pub async fn should_not_update_missing_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+
+        assert_eq!(
+            store.set_disabled(&email, true).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.set_requires_2fa(&email, true).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.record_failed_login(&email, 3, Utc::now()).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.reset_lockout(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.delete_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    pub async fn should_list_seeded_roles<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+
+        let roles = store.list_roles().await.unwrap();
+        assert!(roles.contains(&Role::admin()));
+    }
+
+    pub async fn should_assign_and_remove_roles<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+
+        store.assign_role(&email, ADMIN_ROLE).await.unwrap();
+        // Assigning a role the user has is a no-op
+        store.assign_role(&email, ADMIN_ROLE).await.unwrap();
+
+        let stored = store.get_user(&email).await.unwrap();
+        assert_eq!(stored.roles(), [Role::admin()]);
+        assert_eq!(stored.permissions(), Role::admin().permissions);
+
+        store.remove_role(&email, ADMIN_ROLE).await.unwrap();
+        // So is removing one they don't have
+        store.remove_role(&email, ADMIN_ROLE).await.unwrap();
+
+        assert!(store.get_user(&email).await.unwrap().roles().is_empty());
+    }
+
+    pub async fn should_list_users_with_their_roles<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let (prefix, emails) = emails_with_prefix(2);
+        for email in &emails {
+            store.add_user(user(email)).await.unwrap();
+        }
+        store.assign_role(&emails[1], ADMIN_ROLE).await.unwrap();
+
+        let users = store
+            .list_users(&UserQuery {
+                search: Some(prefix),
+                after: None,
+                limit: 10,
+            })
+            .await
+            .unwrap();
+
+        assert!(users[0].roles().is_empty());
+        assert_eq!(users[1].roles(), [Role::admin()]);
+    }
+
+    pub async fn should_not_assign_missing_role_or_to_missing_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+
+        assert_eq!(
+            store.assign_role(&email, "no-such-role").await,
+            Err(UserStoreError::RoleNotFound)
+        );
+        assert_eq!(
+            store.assign_role(&random_email(), ADMIN_ROLE).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            store.remove_role(&random_email(), ADMIN_ROLE).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    pub async fn should_drop_roles_with_deleted_user<H: StoreHarness>(harness: &H)
+    where
+        H::Store: UserStore,
+    {
+        let store = harness.store().await;
+        let email = random_email();
+        store.add_user(user(&email)).await.unwrap();
+        store.assign_role(&email, ADMIN_ROLE).await.unwrap();
+
+        store.delete_user(&email).await.unwrap();
+        store.add_user(user(&email)).await.unwrap();
+
+        // Signing up again doesn't bring the old account's roles back
+        assert!(store.get_user(&email).await.unwrap().roles().is_empty());
+    }
+}
+
+mod banned_token_store {
+    use super::*;
+
+    // Claims with a new `jti`, for a token expiring between one and two `TTL`s from now
+    // (`exp` is in whole seconds)
+    fn claims<H: StoreHarness>(harness: &H) -> Claims {
+        Claims {
+            sub: get_random_email(),
+            exp: ((harness.now() + TTL).timestamp() + 1) as usize,
+            iat: harness.now().timestamp() as usize,
+            jti: uuid::Uuid::new_v4().to_string(),
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        }
+    }
+
+    pub async fn should_report_banned_token<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        let store = harness.store().await;
+        let claims = claims(harness);
+
+        store.add_token(&claims).await.unwrap();
+
+        assert!(store.is_token_banned(&claims).await.unwrap());
+    }
+
+    pub async fn should_not_report_unknown_token<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        let store = harness.store().await;
+
+        assert!(!store.is_token_banned(&claims(harness)).await.unwrap());
+    }
+
+    pub async fn should_reject_duplicate_ban<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        let store = harness.store().await;
+        let claims = claims(harness);
+
+        store.add_token(&claims).await.unwrap();
+
+        assert_eq!(
+            store.add_token(&claims).await,
+            Err(BannedTokenStoreError::TokenAlreadyExists)
+        );
+        assert!(store.is_token_banned(&claims).await.unwrap());
+    }
+
+    pub async fn should_ban_by_jti<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        let store = harness.store().await;
+        let claims = claims(harness);
+        store.add_token(&claims).await.unwrap();
+
+        // Another token with the same jti is banned as well, other claims do not matter
+        let same_jti = Claims {
+            sub: get_random_email(),
+            ..claims.clone()
+        };
+        assert!(store.is_token_banned(&same_jti).await.unwrap());
+
+        let other_jti = Claims {
+            jti: uuid::Uuid::new_v4().to_string(),
+            ..claims
+        };
+        assert!(!store.is_token_banned(&other_jti).await.unwrap());
+    }
+
+    pub async fn should_expire_ban_with_token<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        let store = harness.store().await;
+        let claims = claims(harness);
+        store.add_token(&claims).await.unwrap();
+
+        harness.advance(TTL * 2).await;
+
+        assert!(!store.is_token_banned(&claims).await.unwrap());
+        // An expired ban no longer blocks banning the token again
+        assert_eq!(store.add_token(&claims).await, Ok(()));
+    }
+
+    pub async fn should_admit_one_of_concurrent_bans<H: StoreHarness>(harness: &H)
+    where
+        H::Store: BannedTokenStore,
+    {
+        // Calls share the store without a lock, like requests do
+        let store = Arc::new(harness.store().await);
+        let claims = claims(harness);
+
+        let bans = (0..CONCURRENT_CALLS).map(|_| {
+            let store = store.clone();
+            let claims = claims.clone();
+            tokio::spawn(async move { store.add_token(&claims).await })
+        });
+        let results: Vec<_> = join_all(bans)
+            .await
+            .into_iter()
+            .map(|result| result.unwrap())
+            .collect();
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .all(|e| *e == BannedTokenStoreError::TokenAlreadyExists));
+    }
+}
+
+mod two_fa_code_store {
+    use super::*;
+
+    fn attempt() -> TwoFAAttempt {
+        TwoFAAttempt::new(
+            Email::parse(&get_random_email()).unwrap(),
+            TwoFACode::default(),
+            "fingerprint".to_owned(),
+        )
+    }
+
+    fn is_not_found(result: Result<TwoFAAttempt, TwoFACodeStoreError>) -> bool {
+        matches!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound))
+    }
+
+    pub async fn should_get_added_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        let attempt = attempt();
+
+        store
+            .add_code(login_attempt_id.clone(), attempt.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_code(&login_attempt_id).await.unwrap(), attempt);
+    }
+
+    pub async fn should_not_find_missing_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+
+        assert!(is_not_found(
+            store.get_code(&LoginAttemptId::default()).await
+        ));
+    }
+
+    pub async fn should_remove_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        store.remove_code(&login_attempt_id).await.unwrap();
+
+        assert!(is_not_found(store.get_code(&login_attempt_id).await));
+    }
+
+    pub async fn should_allow_removing_missing_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+
+        assert!(store.remove_code(&LoginAttemptId::default()).await.is_ok());
+    }
+
+    pub async fn should_replace_attempt_with_same_id<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        let replacement = attempt();
+
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+        store
+            .add_code(login_attempt_id.clone(), replacement.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_code(&login_attempt_id).await.unwrap(),
+            replacement
+        );
+    }
+
+    pub async fn should_expire_attempt_after_ttl<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        harness.advance(TTL).await;
+
+        assert!(is_not_found(store.get_code(&login_attempt_id).await));
+    }
+
+    pub async fn should_keep_concurrent_attempts_independent<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        // Calls share the store without a lock, like requests do
+        let store = Arc::new(harness.store().await);
+        let attempts: Vec<_> = (0..CONCURRENT_CALLS)
+            .map(|_| (LoginAttemptId::default(), attempt()))
+            .collect();
+
+        let adds = attempts.iter().cloned().map(|(login_attempt_id, attempt)| {
+            let store = store.clone();
+            tokio::spawn(async move { store.add_code(login_attempt_id, attempt).await })
+        });
+        for result in join_all(adds).await {
+            result.unwrap().unwrap();
+        }
+
+        for (login_attempt_id, attempt) in attempts {
+            assert_eq!(store.get_code(&login_attempt_id).await.unwrap(), attempt);
+        }
+    }
+
+    pub async fn should_take_attempt_once<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        let attempt = attempt();
+        store
+            .add_code(login_attempt_id.clone(), attempt.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.take_code(&login_attempt_id).await.unwrap(),
+            Some(attempt)
+        );
+        assert_eq!(store.take_code(&login_attempt_id).await.unwrap(), None);
+        assert!(is_not_found(store.get_code(&login_attempt_id).await));
+    }
+
+    pub async fn should_not_take_expired_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        harness.advance(TTL).await;
+
+        assert_eq!(store.take_code(&login_attempt_id).await.unwrap(), None);
+    }
+
+    pub async fn should_let_one_of_concurrent_takes_succeed<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = Arc::new(harness.store().await);
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        let takes = (0..CONCURRENT_CALLS).map(|_| {
+            let store = store.clone();
+            let login_attempt_id = login_attempt_id.clone();
+            tokio::spawn(async move { store.take_code(&login_attempt_id).await })
+        });
+        let taken = join_all(takes)
+            .await
+            .into_iter()
+            .filter(|result| result.as_ref().unwrap().as_ref().unwrap().is_some())
+            .count();
+
+        assert_eq!(taken, 1);
+    }
+
+    pub async fn should_discard_attempt_after_max_failed_codes<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        let attempt = attempt();
+        store
+            .add_code(login_attempt_id.clone(), attempt.clone())
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert!(!store
+                .record_failed_code(&login_attempt_id, 3)
+                .await
+                .unwrap());
+        }
+        assert_eq!(store.get_code(&login_attempt_id).await.unwrap(), attempt);
+
+        assert!(store
+            .record_failed_code(&login_attempt_id, 3)
+            .await
+            .unwrap());
+        assert!(is_not_found(store.get_code(&login_attempt_id).await));
+    }
+
+    pub async fn should_count_failed_codes_again_for_replaced_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            store
+                .record_failed_code(&login_attempt_id, 3)
+                .await
+                .unwrap();
+        }
+
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert!(!store
+                .record_failed_code(&login_attempt_id, 3)
+                .await
+                .unwrap());
+        }
+        assert!(store.get_code(&login_attempt_id).await.is_ok());
+    }
+
+    pub async fn should_ignore_failed_code_for_missing_attempt<H: StoreHarness>(harness: &H)
+    where
+        H::Store: TwoFACodeStore,
+    {
+        let store = harness.store().await;
+        let login_attempt_id = LoginAttemptId::default();
+
+        assert!(!store
+            .record_failed_code(&login_attempt_id, 1)
+            .await
+            .unwrap());
+
+        // Misses before the attempt existed don't count against it
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+        assert!(store.get_code(&login_attempt_id).await.is_ok());
+    }
+
+    pub async fn should_allow_max_failed_codes_when_missed_concurrently<H: StoreHarness>(
+        harness: &H,
+    ) where
+        H::Store: TwoFACodeStore,
+    {
+        let store = Arc::new(harness.store().await);
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(login_attempt_id.clone(), attempt())
+            .await
+            .unwrap();
+
+        let max_failed_codes = CONCURRENT_CALLS as u32 + 1;
+        let misses = (0..CONCURRENT_CALLS).map(|_| {
+            let store = store.clone();
+            let login_attempt_id = login_attempt_id.clone();
+            tokio::spawn(async move {
+                store
+                    .record_failed_code(&login_attempt_id, max_failed_codes)
+                    .await
+            })
+        });
+        for miss in join_all(misses).await {
+            assert!(!miss.unwrap().unwrap());
+        }
+
+        // Every concurrent miss was counted
+        assert!(store
+            .record_failed_code(&login_attempt_id, max_failed_codes)
+            .await
+            .unwrap());
+    }
+}
+
+// In-memory stores, with time driven by a manual clock
+macro_rules! in_memory_harness {
+    ($harness:ident, $store:ty, $build:expr) => {
+        #[derive(Default)]
+        struct $harness {
+            clock: ManualClock,
+        }
+
+        #[async_trait::async_trait]
+        impl StoreHarness for $harness {
+            type Store = $store;
+
+            async fn store(&self) -> $store {
+                let build: fn(ManualClock) -> $store = $build;
+                build(self.clock.clone())
+            }
+
+            async fn advance(&self, by: Duration) {
+                self.clock.advance(by);
+            }
+
+            fn now(&self) -> DateTime<Utc> {
+                self.clock.now()
+            }
+        }
+    };
+}
+
+in_memory_harness!(HashMapUserHarness, HashMapUserStore, |_| {
+    HashMapUserStore::default()
+});
+in_memory_harness!(
+    HashSetBannedTokenHarness,
+    HashSetBannedTokenStore,
+    |clock| HashSetBannedTokenStore::default().with_clock(Arc::new(clock))
+);
+in_memory_harness!(HashMapTwoFACodeHarness, HashMapTwoFACodeStore, |clock| {
+    HashMapTwoFACodeStore::new(TTL).with_clock(Arc::new(clock))
+});
+
+// Postgres stores, backed by the test app's database
+struct PostgresHarness<S> {
+    app: TestApp,
+    build: fn(PgPool) -> S,
+}
+
+impl<S> PostgresHarness<S> {
+    async fn new(build: fn(PgPool) -> S) -> Self {
+        Self {
+            app: TestApp::new().await,
+            build,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Send + Sync + 'static> StoreHarness for PostgresHarness<S> {
+    type Store = S;
+
+    async fn store(&self) -> S {
+        (self.build)(self.app.db_pool.clone())
+    }
+
+    async fn advance(&self, by: Duration) {
+        tokio::time::sleep(by).await;
+    }
+
+    async fn cleanup(&mut self) {
+        self.app.cleanup().await;
+    }
+}
+
+// SQLite stores, backed by a temporary database file
+struct SqliteHarness<S> {
+    pool: SqlitePool,
+    build: fn(SqlitePool) -> S,
+    _dir: TempDir,
+}
+
+impl<S> SqliteHarness<S> {
+    async fn new(build: fn(SqlitePool) -> S) -> Self {
+        let (pool, dir) = configure_sqlite().await;
+
+        Self {
+            pool,
+            build,
+            _dir: dir,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Send + Sync + 'static> StoreHarness for SqliteHarness<S> {
+    type Store = S;
+
+    async fn store(&self) -> S {
+        (self.build)(self.pool.clone())
+    }
+
+    async fn advance(&self, by: Duration) {
+        tokio::time::sleep(by).await;
+    }
+}
+
+// Redis stores, which expire entries on the server so time can only pass for real
+struct RedisHarness<S> {
+    conn: ConnectionManager,
+    build: fn(ConnectionManager) -> S,
+}
+
+impl<S> RedisHarness<S> {
+    async fn new(build: fn(ConnectionManager) -> S) -> Self {
+        let conn = get_redis_connection(test_settings().redis.host_name)
+            .await
+            .expect("Failed to get Redis connection");
+
+        Self { conn, build }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Send + Sync + 'static> StoreHarness for RedisHarness<S> {
+    type Store = S;
+
+    async fn store(&self) -> S {
+        (self.build)(self.conn.clone())
+    }
+
+    async fn advance(&self, by: Duration) {
+        // Redis expiry is only accurate to the millisecond
+        tokio::time::sleep(by + Duration::from_millis(100)).await;
+    }
+}
+
+user_store_conformance!(hashmap_user_store, HashMapUserHarness::default());
+user_store_conformance!(
+    postgres_user_store,
+    PostgresHarness::new(PostgresUserStore::new).await
+);
+user_store_conformance!(
+    sqlite_user_store,
+    SqliteHarness::new(SqliteUserStore::new).await
+);
+
+banned_token_store_conformance!(
+    hashset_banned_token_store,
+    HashSetBannedTokenHarness::default()
+);
+banned_token_store_conformance!(
+    postgres_banned_token_store,
+    PostgresHarness::new(PostgresBannedTokenStore::new).await
+);
+banned_token_store_conformance!(
+    sqlite_banned_token_store,
+    SqliteHarness::new(SqliteBannedTokenStore::new).await
+);
+banned_token_store_conformance!(
+    redis_banned_token_store,
+    RedisHarness::new(RedisBannedTokenStore::new).await
+);
+
+two_fa_code_store_conformance!(
+    hashmap_two_fa_code_store,
+    HashMapTwoFACodeHarness::default()
+);
+two_fa_code_store_conformance!(
+    postgres_two_fa_code_store,
+    PostgresHarness::new(|pool| PostgresTwoFACodeStore::new(pool, TTL)).await
+);
+two_fa_code_store_conformance!(
+    sqlite_two_fa_code_store,
+    SqliteHarness::new(|pool| SqliteTwoFACodeStore::new(pool, TTL)).await
+);
+two_fa_code_store_conformance!(
+    redis_two_fa_code_store,
+    RedisHarness::new(|conn| RedisTwoFACodeStore::new(conn, TTL.as_secs())).await
+);
+
+// Expired rows are hidden straight away but only deleted by the sweeper
+#[tokio::test]
+async fn postgres_stores_should_sweep_only_expired_entries() {
+    let mut app = TestApp::new().await;
+    let banned_token_store = PostgresBannedTokenStore::new(app.db_pool.clone());
+    let two_fa_code_store = PostgresTwoFACodeStore::new(app.db_pool.clone(), TTL);
+
+    let claims_expiring_in = |lifetime: Duration| Claims {
+        sub: get_random_email(),
+        exp: (Utc::now() + lifetime).timestamp() as usize,
+        iat: Utc::now().timestamp() as usize,
+        jti: uuid::Uuid::new_v4().to_string(),
+        roles: Vec::new(),
+        permissions: Vec::new(),
+    };
+    let kept_token = claims_expiring_in(TTL * 60);
+    banned_token_store
+        .add_token(&claims_expiring_in(TTL))
+        .await
+        .unwrap();
+    banned_token_store.add_token(&kept_token).await.unwrap();
+    two_fa_code_store
+        .add_code(
+            LoginAttemptId::default(),
+            TwoFAAttempt::new(
+                Email::parse(&get_random_email()).unwrap(),
+                TwoFACode::default(),
+                "fingerprint".to_owned(),
+            ),
+        )
+        .await
+        .unwrap();
+
+    tokio::time::sleep(TTL).await;
+
+    assert_eq!(banned_token_store.remove_expired().await.unwrap(), 1);
+    assert_eq!(banned_token_store.remove_expired().await.unwrap(), 0);
+    assert!(banned_token_store
+        .is_token_banned(&kept_token)
+        .await
+        .unwrap());
+    assert_eq!(two_fa_code_store.remove_expired().await.unwrap(), 1);
+
+    app.cleanup().await;
+}
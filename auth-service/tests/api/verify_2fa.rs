@@ -1,31 +1,52 @@
 use secrecy::ExposeSecret;
 
 use auth_service::{
-    domain::{Email, LoginAttemptId},
+    domain::{Email, LoginAttemptId, TwoFACode},
     routes::TwoFactorAuthResponse,
+    settings::{CacheBackend, StoreBackend},
     utils::JWT_COOKIE_NAME,
 };
 
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, test_settings, two_fa_code_from_email, TestApp};
+
+// Logs in as `email`, returning the pending attempt and the code it is waiting for
+async fn start_login(app: &TestApp, email: &str) -> (LoginAttemptId, TwoFACode) {
+    let login_response = app
+        .login(&serde_json::json!({
+            "email": email,
+            "password": "validPass123!",
+        }))
+        .await;
+
+    assert_eq!(login_response.status().as_u16(), 206);
+
+    let (login_attempt_id, attempt) = app.get_2fa_attempt(login_response).await;
+    assert_eq!(attempt.email, Email::parse(email).unwrap());
+
+    (login_attempt_id, attempt.code)
+}
+
+fn verify_body(login_attempt_id: &LoginAttemptId, code: &TwoFACode) -> serde_json::Value {
+    serde_json::json!({
+        "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
+        "2FACode": code.as_ref().expose_secret(),
+    })
+}
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
     let mut app = TestApp::new().await;
-    let email = get_random_email();
 
-    // verify_2fa route expects email, loginAttemptId, 2FACode fields
+    // verify_2fa route expects loginAttemptId and 2FACode fields
     let test_cases = [
         serde_json::json!({
-            "email": email,
             "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret(),
         }),
         serde_json::json!({
-            "email": email,
             "2FACode": "123456",
         }),
         serde_json::json!({
-            "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret(),
-            "2FACode": "123456",
+            "email": get_random_email(),
         }),
     ];
 
@@ -48,17 +69,10 @@ async fn should_return_400_if_invalid_input() {
 
     let test_cases = [
         serde_json::json!({
-            "email": "invalid_email",
-            "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret(),
-            "2FACode": "123456",
-        }),
-        serde_json::json!({
-            "email": get_random_email(),
             "loginAttemptId": "invalid_id",
             "2FACode": "123456",
         }),
         serde_json::json!({
-            "email": get_random_email(),
             "loginAttemptId": LoginAttemptId::default().as_ref().expose_secret(),
             "2FACode": "123",
         }),
@@ -109,7 +123,6 @@ async fn should_return_401_if_incorrect_credentials() {
         .login_attempt_id;
     let response = app
         .verify_2fa(&serde_json::json!({
-            "email": email,
             "loginAttemptId": login_response_id,
             "2FACode": "123456",
         }))
@@ -120,6 +133,46 @@ async fn should_return_401_if_incorrect_credentials() {
     app.cleanup().await;
 }
 
+#[tokio::test]
+async fn should_discard_attempt_after_max_failed_codes() {
+    let mut settings = test_settings();
+    settings.two_fa.max_failed_codes = 3;
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+    let wrong_code = ["000000", "111111"]
+        .into_iter()
+        .map(|c| TwoFACode::parse(c.to_owned()).unwrap())
+        .find(|c| *c != code)
+        .unwrap();
+
+    for _ in 0..3 {
+        let response = app
+            .verify_2fa(&verify_body(&login_attempt_id, &wrong_code))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The right code no longer completes the discarded login
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Starting over works
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.cleanup().await;
+}
+
 #[tokio::test]
 async fn should_return_401_if_old_code() {
     let mut app = TestApp::new().await;
@@ -136,66 +189,102 @@ async fn should_return_401_if_old_code() {
 
     assert_eq!(response.status().as_u16(), 201);
 
-    let login_response = app
-        .login(&serde_json::json!({
-            "email": email,
-            "password": "validPass123!",
-        }))
-        .await;
+    let (login_attempt_id, code) = start_login(&app, &email).await;
 
-    assert_eq!(login_response.status().as_u16(), 206);
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+
+    assert_eq!(response.status().as_u16(), 200);
 
-    let (login_attempt_id, code) = {
-        let two_fa_code_store = app.app_state.two_fa_code_store.write().await;
-        two_fa_code_store
-            .get_code(&Email::parse(&email).unwrap())
-            .await
-            .unwrap()
-    };
+    let (login_attempt_id, _code) = start_login(&app, &email).await;
+
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_200_if_correct_code() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
 
     let response = app
-        .verify_2fa(&serde_json::json!({
+        .signup(&serde_json::json!({
             "email": email,
-            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-            "2FACode": code.as_ref().expose_secret(),
+            "password": "validPass123!",
+            "requires2FA": true,
         }))
         .await;
 
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+
     assert_eq!(response.status().as_u16(), 200);
 
-    let login_response = app
-        .login(&serde_json::json!({
-            "email": email,
-            "password": "validPass123!",
-        }))
-        .await;
+    let auth_cookie = response
+        .cookies()
+        .find(|c| c.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
 
-    assert_eq!(login_response.status().as_u16(), 206);
+    assert!(
+        !auth_cookie.value().is_empty(),
+        "Auth cookie should not be empty"
+    );
 
-    let (login_attempt_id, _code) = {
-        let two_fa_code_store = app.app_state.two_fa_code_store.read().await;
-        two_fa_code_store
-            .get_code(&Email::parse(&email).unwrap())
-            .await
-            .unwrap()
-    };
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_log_in_and_out_with_postgres_stores_and_no_redis() {
+    let mut settings = test_settings();
+    settings.stores.banned_tokens = StoreBackend::Postgres;
+    settings.stores.two_fa_codes = StoreBackend::Postgres;
+    settings.stores.tokens_valid_after_cache = CacheBackend::None;
+    // Connecting to Redis would fail
+    settings.redis.host_name = "redis.invalid".to_owned();
+    let mut app = TestApp::with_settings(settings).await;
+
+    let email = get_random_email();
 
     let response = app
-        .verify_2fa(&serde_json::json!({
+        .signup(&serde_json::json!({
             "email": email,
-            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-            "2FACode": code.as_ref().expose_secret(),
+            "password": "validPass123!",
+            "requires2FA": true,
         }))
         .await;
+    assert_eq!(response.status().as_u16(), 201);
 
-    assert_eq!(response.status().as_u16(), 401);
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_health_ready().await;
+    assert_eq!(response.status().as_u16(), 200);
 
     app.cleanup().await;
 }
 
 #[tokio::test]
-async fn should_return_200_if_correct_code() {
-    let mut app = TestApp::new().await;
+async fn should_log_in_and_out_with_sqlite_stores_only() {
+    let mut settings = test_settings();
+    settings.stores.users = StoreBackend::Sqlite;
+    settings.stores.banned_tokens = StoreBackend::Sqlite;
+    settings.stores.two_fa_codes = StoreBackend::Sqlite;
+    settings.stores.email_outbox = StoreBackend::Sqlite;
+    settings.stores.tokens_valid_after_cache = CacheBackend::None;
+    // Connecting to Redis would fail
+    settings.redis.host_name = "redis.invalid".to_owned();
+    let mut app = TestApp::with_settings(settings).await;
 
     let email = get_random_email();
 
@@ -206,34 +295,47 @@ async fn should_return_200_if_correct_code() {
             "requires2FA": true,
         }))
         .await;
-
     assert_eq!(response.status().as_u16(), 201);
 
-    let login_response = app
-        .login(&serde_json::json!({
-            "email": email,
-            "password": "validPass123!",
-        }))
-        .await;
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+    assert_eq!(app.deliver_emails_to_mailbox().await, 1);
 
-    assert_eq!(login_response.status().as_u16(), 206);
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
 
-    let (login_attempt_id, code) = {
-        let two_fa_code_store = app.app_state.two_fa_code_store.write().await;
-        two_fa_code_store
-            .get_code(&Email::parse(&email).unwrap())
-            .await
-            .unwrap()
-    };
+    // Nothing was written to PostgreSQL
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 0);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_same_code_twice() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
 
     let response = app
-        .verify_2fa(&serde_json::json!({
+        .signup(&serde_json::json!({
             "email": email,
-            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-            "2FACode": code.as_ref().expose_secret(),
+            "password": "validPass123!",
+            "requires2FA": true,
         }))
         .await;
 
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+
     assert_eq!(response.status().as_u16(), 200);
 
     let auth_cookie = response
@@ -246,11 +348,15 @@ async fn should_return_200_if_correct_code() {
         "Auth cookie should not be empty"
     );
 
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
     app.cleanup().await;
 }
 
 #[tokio::test]
-async fn should_return_401_if_same_code_twice() {
+async fn should_accept_same_code_only_once_when_verified_concurrently() {
     let mut app = TestApp::new().await;
 
     let email = get_random_email();
@@ -265,52 +371,153 @@ async fn should_return_401_if_same_code_twice() {
 
     assert_eq!(response.status().as_u16(), 201);
 
-    let login_response = app
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+    let body = verify_body(&login_attempt_id, &code);
+
+    let (first, second) = tokio::join!(app.verify_2fa(&body), app.verify_2fa(&body));
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+
+    assert_eq!(statuses, [200, 401]);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_200_with_code_from_email() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let login_attempt_id = app
         .login(&serde_json::json!({
             "email": email,
             "password": "validPass123!",
         }))
-        .await;
-
-    assert_eq!(login_response.status().as_u16(), 206);
+        .await
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
 
-    let (login_attempt_id, code) = {
-        let two_fa_code_store = app.app_state.two_fa_code_store.write().await;
-        two_fa_code_store
-            .get_code(&Email::parse(&email).unwrap())
-            .await
-            .unwrap()
-    };
+    assert_eq!(app.deliver_emails_to_mailbox().await, 1);
+    let received = app
+        .mailbox
+        .latest_to(&Email::parse(&email).unwrap())
+        .expect("No 2FA email received");
 
     let response = app
         .verify_2fa(&serde_json::json!({
-            "email": email,
-            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-            "2FACode": code.as_ref().expose_secret(),
+            "loginAttemptId": login_attempt_id,
+            "2FACode": two_fa_code_from_email(&received.text_body),
         }))
         .await;
 
     assert_eq!(response.status().as_u16(), 200);
 
-    let auth_cookie = response
-        .cookies()
-        .find(|c| c.name() == JWT_COOKIE_NAME)
-        .expect("No auth cookie found");
+    app.cleanup().await;
+}
 
-    assert!(
-        !auth_cookie.value().is_empty(),
-        "Auth cookie should not be empty"
-    );
+#[tokio::test]
+async fn should_allow_concurrent_login_attempts() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    // e.g. the same user logging in from two tabs
+    let (first_attempt_id, first_code) = start_login(&app, &email).await;
+    let (second_attempt_id, second_code) = start_login(&app, &email).await;
 
     let response = app
-        .verify_2fa(&serde_json::json!({
-            "email": email,
-            "loginAttemptId": login_attempt_id.as_ref().expose_secret(),
-            "2FACode": code.as_ref().expose_secret(),
-        }))
+        .verify_2fa(&verify_body(&first_attempt_id, &first_code))
         .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .verify_2fa(&verify_body(&second_attempt_id, &second_code))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_code_belongs_to_another_attempt() {
+    let mut app = TestApp::new().await;
 
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let (first_attempt_id, first_code) = start_login(&app, &email).await;
+    let (second_attempt_id, second_code) = start_login(&app, &email).await;
+    if first_code == second_code {
+        // Both attempts drew the same random code, nothing to tell apart
+        app.cleanup().await;
+        return;
+    }
+
+    let response = app
+        .verify_2fa(&verify_body(&first_attempt_id, &second_code))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The failed guess does not consume either attempt
+    let response = app
+        .verify_2fa(&verify_body(&second_attempt_id, &second_code))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.cleanup().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_verified_from_another_client() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+
+    app.signup(&serde_json::json!({
+        "email": email,
+        "password": "validPass123!",
+        "requires2FA": true,
+    }))
+    .await;
+
+    let (login_attempt_id, code) = start_login(&app, &email).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/verify-2fa", &app.address))
+        .header("User-Agent", "another-browser/1.0")
+        .json(&verify_body(&login_attempt_id, &code))
+        .send()
+        .await
+        .expect("Failed to execute request");
     assert_eq!(response.status().as_u16(), 401);
 
+    // The original client can still finish its login
+    let response = app.verify_2fa(&verify_body(&login_attempt_id, &code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
     app.cleanup().await;
 }
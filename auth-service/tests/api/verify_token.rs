@@ -1,5 +1,4 @@
-use auth_service::utils::JWT_COOKIE_NAME;
-use secrecy::Secret;
+use auth_service::utils::{validate_token, Claims, JWT_COOKIE_NAME};
 
 use crate::helpers::{get_random_email, TestApp};
 
@@ -57,6 +56,14 @@ async fn should_return_200_if_valid_token() {
 
     assert_eq!(response.status().as_u16(), 200);
 
+    let claims: Claims = response
+        .json()
+        .await
+        .expect("Could not deserialize response body to Claims");
+
+    assert_eq!(claims.sub, email);
+    assert!(!claims.jti.is_empty());
+
     app.cleanup().await;
 }
 
@@ -142,13 +149,14 @@ async fn should_return_401_if_banned_token() {
     );
 
     // Simulate banning the token
-    let _ = app
-        .app_state
-        .banned_token_store
-        .write()
-        .await
-        .add_token(&Secret::new(auth_cookie.value().to_string()))
-        .await;
+    let claims = validate_token(
+        auth_cookie.value(),
+        &app.app_state.settings.auth,
+        &app.app_state.token_revocations,
+    )
+    .await
+    .expect("Failed to validate token");
+    let _ = app.app_state.banned_token_store.add_token(&claims).await;
 
     let response = app
         .verify_token(&serde_json::json!({